@@ -0,0 +1,16 @@
+use std::{env, fs, path::PathBuf};
+
+use prost::Message;
+
+// Compile the gRPC protos. The descriptor set is kept for server reflection.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+    let descriptors = protox::compile(["user.proto"], ["proto"])?;
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    fs::write(
+        out_dir.join("user_descriptor.bin"),
+        descriptors.encode_to_vec(),
+    )?;
+    tonic_build::configure().compile_fds(descriptors)?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use actix_web::{
+    delete, post, web,
+    web::{Data, Path, ReqData},
+    HttpResponse,
+};
+use actix_web_grants::proc_macro::has_any_role;
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    auth::claims::Claims,
+    handler::validated::{self, ValidatedJson},
+    models::error_model::{ApiError, ApiErrorType},
+    services::auth_service,
+};
+
+// -- Configurations...
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(import_accounts).service(delete_account);
+}
+
+// -- DTO's
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct ImportAccount {
+    #[validate(email(message = "email must be valid email"))]
+    #[schema(format = Email)]
+    pub email: String,
+
+    #[validate(length(min = 1, max = 50, message = "first name length between 1 and 50"))]
+    #[schema(min_length = 1, max_length = 50)]
+    pub first_name: String,
+
+    #[validate(length(min = 2, max = 50, message = "last name length between 2 and 50"))]
+    #[schema(min_length = 2, max_length = 50)]
+    pub last_name: String,
+
+    // Existing hash in argon2, bcrypt, scrypt or PBKDF2-SHA256 format.
+    pub password_hash: String,
+
+    #[validate(custom(function = "validated::known_roles"))]
+    pub roles: Option<Vec<String>>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct ImportAccountsRequest {
+    #[validate(length(
+        min = 1,
+        max = 1000,
+        message = "accounts must have between 1 and 1000 entries"
+    ))]
+    #[schema(min_items = 1, max_items = 1000)]
+    pub accounts: Vec<ImportAccount>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ImportSkipped {
+    pub email: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ImportAccountsResponse {
+    pub imported: usize,
+    pub skipped: Vec<ImportSkipped>,
+}
+
+// -- Controllers...
+// Bulk import accounts with their existing password hashes.
+#[utoipa::path(
+    tag = "accounts",
+    request_body = ImportAccountsRequest,
+    responses(
+        (status = 200, description = "Imported and skipped accounts", body = ImportAccountsResponse),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 422, description = "Validation failed", body = ApiError),
+    )
+)]
+#[post("/accounts/import")]
+#[has_any_role("ADMIN")]
+pub async fn import_accounts(
+    client: Data<Client>,
+    import_request: ValidatedJson<ImportAccountsRequest>,
+) -> Result<HttpResponse, ApiErrorType> {
+    auth_service::import_accounts(&client, import_request.into_inner()).await
+}
+
+// Delete an account together with its linked user profile.
+#[utoipa::path(
+    tag = "accounts",
+    responses(
+        (status = 204, description = "Account and profile deleted"),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 404, description = "Account not found", body = ApiError),
+    )
+)]
+#[delete("/accounts/{id}")]
+#[has_any_role("ADMIN")]
+pub async fn delete_account(
+    client: Data<Client>,
+    claims: ReqData<Claims>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiErrorType> {
+    auth_service::delete_account(&client, &claims, &path.into_inner()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imported_roles_must_be_known() {
+        let account = |roles: Option<Vec<&str>>| ImportAccount {
+            email: "john@example.com".to_owned(),
+            first_name: "John".to_owned(),
+            last_name: "Doe".to_owned(),
+            password_hash: "$2b$12$hash".to_owned(),
+            roles: roles.map(|roles| roles.into_iter().map(str::to_owned).collect()),
+            active: None,
+        };
+        assert!(account(None).validate().is_ok());
+        assert!(account(Some(vec!["ROLE_ADMIN"])).validate().is_ok());
+        let errors = account(Some(vec!["ADMIN"])).validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("roles"));
+    }
+}
@@ -1,12 +1,19 @@
 use actix_web::web::Data;
-use actix_web::{get, post, put, web, web::Json, HttpResponse};
+use actix_web::{get, middleware, post, put, web, HttpResponse};
 use log::info;
-use log::warn;
 use mongodb::Client;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use validator::Validate;
 
-use crate::{models::error_model::ApiErrorType, services::auth_service};
+use crate::{
+    auth::{
+        password_policy::PasswordPolicy, provider::AuthProviders, registration::RegistrationPolicy,
+    },
+    handler::{idempotency_middleware, validated::ValidatedJson},
+    models::error_model::{ApiError, ApiErrorType},
+    services::auth_service,
+};
 
 // -- configurations
 pub fn init(cfg: &mut web::ServiceConfig) {
@@ -14,121 +21,223 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(auth_login);
     cfg.service(update_password);
     cfg.service(forgot_password);
+    cfg.service(reset_password);
     cfg.service(logout);
 }
 
 // -- DTO's
 // TODO: Move DTO's to models or different package.
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct RegisterRequest {
     #[validate(email(message = "email must be valid email"))]
+    #[schema(format = Email)]
     pub email: String,
 
     #[validate(length(min = 1, max = 50, message = "first name length between 1 and 50"))]
+    #[schema(min_length = 1, max_length = 50)]
     pub first_name: String,
 
     #[validate(length(min = 2, max = 50, message = "last name length between 2 and 50"))]
+    #[schema(min_length = 2, max_length = 50)]
     pub last_name: String,
 
     #[validate(length(
         min = 12,
         message = "password is required and must be at least 12 characters"
     ))]
+    #[schema(format = Password, min_length = 12)]
     pub password: String,
+
+    // Required when registration is invite only.
+    pub invite_code: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RegisterResponse {
     pub status: String,
     pub message: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+// `400` body of `/a/register`, only used by the OpenAPI document. A taken email is answered
+// with a `RegisterResponse`, a malformed payload with an `ApiError`.
+#[allow(dead_code)]
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum RegisterFailure {
+    EmailTaken(RegisterResponse),
+    Malformed(ApiError),
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct LoginRequest {
     #[validate(email(message = "email must be valid email"))]
+    #[schema(format = Email)]
     pub email: String,
 
-    #[validate(length(
-        min = 12,
-        message = "password is required and must be at least 12 characters"
-    ))]
+    // Only required. The length policy applies to new passwords, legacy and directory
+    // passwords may be shorter.
+    #[validate(length(min = 1, message = "password is required"))]
+    #[schema(format = Password, min_length = 1)]
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct LoginResponse {
     pub access_token: String,
     pub token_type: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct UpdatePasswordRequest {
     #[validate(email(message = "email must be valid email"))]
+    #[schema(format = Email)]
     pub email: String,
 
     #[validate(length(
         min = 12,
         message = "current password is required and must be at least 12 characters"
     ))]
+    #[schema(format = Password, min_length = 12)]
     pub current_password: String,
 
     #[validate(length(
         min = 12,
         message = "new password is required and must be at least 12 characters"
     ))]
+    #[schema(format = Password, min_length = 12)]
     pub new_password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct ForgotPasswordRequest {
     #[validate(email(message = "email must be valid email"))]
+    #[schema(format = Email)]
     pub email: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct ResetPasswordRequest {
+    // Token from the forgot password request.
+    #[validate(length(min = 1, message = "token is required"))]
+    #[schema(min_length = 1)]
+    pub token: String,
+
+    #[validate(length(
+        min = 12,
+        message = "new password is required and must be at least 12 characters"
+    ))]
+    #[schema(format = Password, min_length = 12)]
+    pub new_password: String,
+}
+
 // -- Controllers...
 // Register a user.
-#[post("/a/register")]
+#[utoipa::path(
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 201, description = "User registered", body = RegisterResponse),
+        (status = 400, description = "Email is taken or the payload is malformed", body = RegisterFailure),
+        (status = 403, description = "Registration is disabled or needs an invitation", body = ApiError),
+        (status = 422, description = "Validation or password policy failed", body = ApiError),
+    ),
+    security(())
+)]
+#[post(
+    "/a/register",
+    wrap = "middleware::from_fn(idempotency_middleware::idempotency)"
+)]
 pub async fn auth_register(
     client: Data<Client>,
-    register_user: Json<RegisterRequest>,
+    policy: Data<RegistrationPolicy>,
+    password_policy: Data<PasswordPolicy>,
+    register_user: ValidatedJson<RegisterRequest>,
 ) -> Result<HttpResponse, ApiErrorType> {
-    // Step 1: Validate payload.
-    match register_user.validate() {
-        Ok(_) => auth_service::create_user(&client, register_user.0).await,
-        Err(err) => {
-            warn!("Error: {}", err);
-            // Validation error.
-            Err(ApiErrorType::BadRequest)
-        }
-    }
+    auth_service::create_user(
+        &client,
+        &policy,
+        &password_policy,
+        register_user.into_inner(),
+    )
+    .await
 }
 
 // Login using credentials.
+#[utoipa::path(
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Bearer token", body = LoginResponse),
+        (status = 401, description = "Invalid credentials", body = ApiError),
+        (status = 422, description = "Validation failed", body = ApiError),
+    ),
+    security(())
+)]
 #[post("/a/login")]
 pub async fn auth_login(
     client: Data<Client>,
-    login_user: Json<LoginRequest>,
+    providers: Data<AuthProviders>,
+    login_user: ValidatedJson<LoginRequest>,
 ) -> Result<HttpResponse, ApiErrorType> {
-    // Step 1: Validate payload.
-    match login_user.validate() {
-        Ok(_) => auth_service::login(&client, login_user.0).await,
-        Err(err) => {
-            warn!("Error: {}", err);
-            Err(ApiErrorType::BadRequest)
-        }
-    }
+    auth_service::login(&client, &providers, login_user.into_inner()).await
 }
 
 // Update password for an existing user with credentials.
+#[utoipa::path(
+    tag = "auth",
+    request_body = UpdatePasswordRequest,
+    responses(
+        (status = 204, description = "Password updated"),
+        (status = 401, description = "Invalid credentials", body = ApiError),
+        (status = 422, description = "Validation or password policy failed", body = ApiError),
+    ),
+    security(())
+)]
 #[put("/a/password")]
-pub async fn update_password(update_password: Json<UpdatePasswordRequest>) -> HttpResponse {
-    HttpResponse::Ok().json(update_password)
+pub async fn update_password(
+    client: Data<Client>,
+    password_policy: Data<PasswordPolicy>,
+    update_password: ValidatedJson<UpdatePasswordRequest>,
+) -> Result<HttpResponse, ApiErrorType> {
+    auth_service::update_password(&client, &password_policy, update_password.into_inner()).await
 }
 
-// Forgett password flow.
+// Forgett password flow. Sends a one time reset token to local accounts.
+#[utoipa::path(
+    tag = "auth",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 202, description = "Request accepted. The response is the same for unknown emails"),
+        (status = 422, description = "Validation failed", body = ApiError),
+    ),
+    security(())
+)]
 #[post("/a/forgot-password")]
-pub async fn forgot_password(forgot_password: Json<ForgotPasswordRequest>) -> HttpResponse {
-    HttpResponse::Ok().json(forgot_password)
+pub async fn forgot_password(
+    client: Data<Client>,
+    forgot_password: ValidatedJson<ForgotPasswordRequest>,
+) -> Result<HttpResponse, ApiErrorType> {
+    auth_service::forgot_password(&client, forgot_password.into_inner()).await
+}
+
+// Set a new password with the reset token.
+#[utoipa::path(
+    tag = "auth",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 204, description = "Password updated"),
+        (status = 400, description = "Reset token is invalid or expired", body = ApiError),
+        (status = 422, description = "Validation or password policy failed", body = ApiError),
+    ),
+    security(())
+)]
+#[post("/a/reset-password")]
+pub async fn reset_password(
+    client: Data<Client>,
+    password_policy: Data<PasswordPolicy>,
+    reset_password: ValidatedJson<ResetPasswordRequest>,
+) -> Result<HttpResponse, ApiErrorType> {
+    auth_service::reset_password(&client, &password_policy, reset_password.into_inner()).await
 }
 
 // Logout user.
@@ -137,3 +246,33 @@ pub async fn logout(path: web::Path<String>) -> HttpResponse {
     info!("{}", path);
     HttpResponse::NoContent().finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_only_requires_a_password() {
+        let login = |password: &str| LoginRequest {
+            email: "john@example.com".to_owned(),
+            password: password.to_owned(),
+        };
+        // Imported legacy hashes may have been set under a shorter policy.
+        assert!(login("legacy").validate().is_ok());
+        let errors = login("").validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("password"));
+    }
+
+    #[test]
+    fn new_passwords_need_twelve_characters() {
+        let register = RegisterRequest {
+            email: "john@example.com".to_owned(),
+            first_name: "John".to_owned(),
+            last_name: "Doe".to_owned(),
+            password: "legacy".to_owned(),
+            invite_code: None,
+        };
+        let errors = register.validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("password"));
+    }
+}
@@ -0,0 +1,45 @@
+use actix_web::web::{Data, ReqData};
+use actix_web::{get, web, HttpResponse};
+use async_graphql::dataloader::DataLoader;
+use async_graphql::http::GraphiQLSource;
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use mongodb::Client;
+
+use crate::auth::claims::Claims;
+use crate::graphql::loader::{AccountLoader, UserLoader};
+use crate::graphql::ApiSchema;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("").route(web::post().to(graphql)));
+}
+
+// GraphiQL page, registered only when enabled.
+pub fn init_graphiql(cfg: &mut web::ServiceConfig) {
+    cfg.service(graphiql);
+}
+
+// Run a query or mutation for the caller. Loaders live for one request, so a query reads
+// each user and account once, without caching between callers.
+pub async fn graphql(
+    schema: Data<ApiSchema>,
+    client: Data<Client>,
+    claims: ReqData<Claims>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let request = request
+        .into_inner()
+        .data(claims.into_inner())
+        .data(DataLoader::new(
+            UserLoader(client.clone()),
+            actix_web::rt::spawn,
+        ))
+        .data(DataLoader::new(AccountLoader(client), actix_web::rt::spawn));
+    schema.execute(request).await.into()
+}
+
+#[get("/graphiql")]
+pub async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/graphql").finish())
+}
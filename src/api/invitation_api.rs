@@ -0,0 +1,126 @@
+use actix_web::{
+    delete, get, post, web,
+    web::{Data, Path, ReqData},
+    HttpResponse,
+};
+use actix_web_grants::proc_macro::has_any_role;
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    auth::claims::Claims,
+    handler::validated::{self, ValidatedJson},
+    models::error_model::{ApiError, ApiErrorType},
+    services::invitation_service,
+};
+
+// -- Configurations...
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_invitation);
+    cfg.service(get_invitations);
+    cfg.service(revoke_invitation);
+}
+
+// -- DTO's
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct InvitationRequest {
+    // Restrict the invitation to a single email address.
+    #[validate(email(message = "email must be valid email"))]
+    #[schema(format = Email)]
+    pub email: Option<String>,
+
+    // Roles given on registration. Defaults to `ROLE_USER`.
+    #[validate(custom(function = "validated::known_roles"))]
+    pub roles: Option<Vec<String>>,
+
+    #[validate(range(min = 1, max = 720, message = "expiry must be between 1 and 720 hours"))]
+    #[schema(minimum = 1, maximum = 720)]
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct InvitationResponse {
+    pub code: String,
+    pub email: Option<String>,
+    pub roles: Vec<String>,
+    pub created_by: String,
+    pub created_ts: String,
+    pub expires_ts: String,
+}
+
+// -- Controllers...
+// Create an invitation with pre-assigned roles.
+#[utoipa::path(
+    tag = "invitations",
+    request_body = InvitationRequest,
+    responses(
+        (status = 201, description = "Invitation created", body = InvitationResponse),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 422, description = "Validation failed", body = ApiError),
+    )
+)]
+#[post("/invitations")]
+#[has_any_role("ADMIN")]
+pub async fn create_invitation(
+    client: Data<Client>,
+    claims: ReqData<Claims>,
+    invitation: ValidatedJson<InvitationRequest>,
+) -> Result<HttpResponse, ApiErrorType> {
+    invitation_service::create_invitation(&client, &claims, invitation.into_inner()).await
+}
+
+// Get all pending invitations.
+#[utoipa::path(
+    tag = "invitations",
+    responses(
+        (status = 200, description = "Pending invitations", body = Vec<InvitationResponse>),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+    )
+)]
+#[get("/invitations")]
+#[has_any_role("ADMIN")]
+pub async fn get_invitations(client: Data<Client>) -> Result<HttpResponse, ApiErrorType> {
+    invitation_service::get_pending_invitations(&client).await
+}
+
+// Revoke an invitation by code.
+#[utoipa::path(
+    tag = "invitations",
+    responses(
+        (status = 204, description = "Invitation revoked"),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 404, description = "Invitation not found", body = ApiError),
+    )
+)]
+#[delete("/invitations/{code}")]
+#[has_any_role("ADMIN")]
+pub async fn revoke_invitation(
+    client: Data<Client>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiErrorType> {
+    invitation_service::revoke_invitation(&client, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invited_roles_must_be_known() {
+        let invitation = |roles: Option<Vec<&str>>| InvitationRequest {
+            email: None,
+            roles: roles.map(|roles| roles.into_iter().map(str::to_owned).collect()),
+            expires_in_hours: None,
+        };
+        assert!(invitation(None).validate().is_ok());
+        assert!(invitation(Some(vec!["ROLE_USER", "ROLE_ADMIN"]))
+            .validate()
+            .is_ok());
+        let errors = invitation(Some(vec!["ROLE_USER", "ADMIN"]))
+            .validate()
+            .unwrap_err();
+        assert!(errors.field_errors().contains_key("roles"));
+    }
+}
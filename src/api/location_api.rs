@@ -1,4 +1,5 @@
-use crate::models::error_model::ApiErrorType;
+use crate::models::error_model::{ApiError, ApiErrorType};
+use crate::models::location_model::Location;
 use crate::services::location_service;
 use actix_web::{get, web, HttpResponse};
 
@@ -6,6 +7,14 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(get_location);
 }
 
+#[utoipa::path(
+    tag = "locations",
+    responses(
+        (status = 200, description = "Location of the server", body = Location),
+        (status = 500, description = "Location service failed", body = ApiError),
+    ),
+    security(())
+)]
 #[get("/locations")]
 pub async fn get_location() -> Result<HttpResponse, ApiErrorType> {
     location_service::get_location().await
@@ -1,11 +1,21 @@
+pub mod account_api;
 pub mod auth_api;
+pub mod graphql_api;
 pub mod hello_api;
+pub mod invitation_api;
 pub mod location_api;
+pub mod openapi_api;
 pub mod ping_api;
+pub mod role_grant_api;
 pub mod user_api;
 
+pub use account_api::init as init_account_api;
 pub use auth_api::init as init_auth_api;
+pub use graphql_api::init as init_graphql_api;
 pub use hello_api::init as init_hello_api;
+pub use invitation_api::init as init_invitation_api;
 pub use location_api::init as init_location_api;
+pub use openapi_api::init as init_openapi_api;
 pub use ping_api::init as init_ping_api;
+pub use role_grant_api::init as init_role_grant_api;
 pub use user_api::init as init_user_api;
@@ -0,0 +1,282 @@
+use actix_web::web::Data;
+use actix_web::{get, web, HttpResponse};
+use utoipa::openapi::path::{ParameterBuilder, ParameterIn};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{ContentBuilder, ObjectBuilder, Ref, Required, ResponseBuilder, Type};
+use utoipa::{Modify, OpenApi};
+
+use crate::api::{
+    account_api, auth_api, invitation_api, location_api, ping_api, role_grant_api, user_api,
+};
+use crate::config::openapi::OpenApiConfig;
+use crate::constants;
+
+// Redoc release served by `/docs`. Update `OPENAPI.REDOC_INTEGRITY` along with it.
+const REDOC_SCRIPT: &str = "https://cdn.redoc.ly/redoc/v2.5.0/bundles/redoc.standalone.js";
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(openapi_json);
+}
+
+// Redoc page, registered only when enabled.
+pub fn init_docs(cfg: &mut web::ServiceConfig, config: &OpenApiConfig) {
+    let integrity = config.redoc_integrity.as_deref().unwrap_or_default();
+    cfg.app_data(Data::new(RedocPage(redoc_page(integrity))))
+        .service(docs);
+}
+
+// `/docs` page, built once with the script integrity.
+struct RedocPage(String);
+
+// Public routes. Everything else needs a bearer token.
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        auth_api::auth_register,
+        auth_api::auth_login,
+        auth_api::update_password,
+        auth_api::forgot_password,
+        auth_api::reset_password,
+        ping_api::ping,
+        location_api::get_location,
+    ),
+    nest((path = "/api", api = SecuredApi)),
+    modifiers(&BearerAuth, &IdempotencyKey),
+    security(("bearer_auth" = [])),
+    tags(
+        (name = "auth", description = "Registration, login and passwords"),
+        (name = "users", description = "User profiles"),
+        (name = "accounts", description = "Auth accounts"),
+        (name = "invitations", description = "Invitations for invite only registration"),
+        (name = "role-grants", description = "Temporary roles"),
+        (name = "locations", description = "Known user locations"),
+        (name = "ping", description = "Liveness check"),
+    )
+)]
+pub struct ApiDoc;
+
+// Routes under `/api`.
+#[derive(OpenApi)]
+#[openapi(paths(
+    user_api::create_user,
+    user_api::bulk_users,
+    user_api::export_users,
+    user_api::user_events,
+    user_api::import_users,
+    user_api::search_users,
+    user_api::get_user,
+    user_api::update_user,
+    user_api::patch_user,
+    user_api::delete_user,
+    user_api::put_avatar,
+    user_api::get_avatar,
+    user_api::delete_avatar,
+    user_api::get_user_history,
+    user_api::revert_user,
+    user_api::restore_user,
+    user_api::get_deleted_users,
+    user_api::get_all_users,
+    account_api::import_accounts,
+    account_api::delete_account,
+    invitation_api::create_invitation,
+    invitation_api::get_invitations,
+    invitation_api::revoke_invitation,
+    role_grant_api::request_role_grant,
+    role_grant_api::get_role_grants,
+    role_grant_api::approve_role_grant,
+    role_grant_api::deny_role_grant,
+))]
+struct SecuredApi;
+
+// JWT from `POST /a/login`, sent as `Authorization: Bearer <token>`.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+// The idempotency middleware wraps `POST /a/register` and every `POST` under `/api`.
+struct IdempotencyKey;
+
+impl Modify for IdempotencyKey {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if path != "/a/register" && !path.starts_with("/api/") {
+                continue;
+            }
+            let Some(operation) = item.post.as_mut() else {
+                continue;
+            };
+            let header = ParameterBuilder::new()
+                .name("Idempotency-Key")
+                .parameter_in(ParameterIn::Header)
+                .required(Required::False)
+                .description(Some("Replays the stored response of a retried request"))
+                .schema(Some(
+                    ObjectBuilder::new()
+                        .schema_type(Type::String)
+                        .min_length(Some(1))
+                        .max_length(Some(constants::IDEMPOTENCY_KEY_MAX_LENGTH)),
+                ))
+                .build();
+            operation
+                .parameters
+                .get_or_insert_with(Vec::new)
+                .push(header);
+            operation
+                .responses
+                .responses
+                .entry("409".to_owned())
+                .or_insert_with(|| {
+                    ResponseBuilder::new()
+                        .description("Idempotency key reused or in progress")
+                        .content(
+                            "application/json",
+                            ContentBuilder::new()
+                                .schema(Some(Ref::from_schema_name("ApiError")))
+                                .build(),
+                        )
+                        .build()
+                        .into()
+                });
+        }
+    }
+}
+
+// OpenAPI 3.1 document of the REST API.
+#[get("/openapi.json")]
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[get("/docs")]
+async fn docs(page: Data<RedocPage>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page.0.clone())
+}
+
+// The browser refuses the script when it does not match the integrity.
+fn redoc_page(integrity: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>actix-api</title>
+    <meta charset="utf-8"/>
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+  </head>
+  <body>
+    <redoc spec-url="/openapi.json"></redoc>
+    <script src="{}" integrity="{}" crossorigin="anonymous"></script>
+  </body>
+</html>
+"#,
+        REDOC_SCRIPT,
+        escape_attribute(integrity)
+    )
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::{call_and_read_body, init_service, TestRequest};
+    use actix_web::App;
+    use serde_json::Value;
+
+    use super::*;
+
+    fn document() -> Value {
+        serde_json::to_value(ApiDoc::openapi()).unwrap()
+    }
+
+    fn header_names(operation: &Value) -> Vec<&str> {
+        operation["parameters"]
+            .as_array()
+            .map(|parameters| {
+                parameters
+                    .iter()
+                    .filter(|parameter| parameter["in"] == "header")
+                    .filter_map(|parameter| parameter["name"].as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn secured_routes_are_nested_under_api() {
+        let document = document();
+        let paths = document["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/a/register"));
+        assert!(paths.contains_key("/api/users"));
+        assert!(paths.contains_key("/api/users/{id}"));
+        assert!(document["components"]["securitySchemes"]["bearer_auth"].is_object());
+    }
+
+    #[test]
+    fn register_errors_document_their_bodies() {
+        let document = document();
+        let responses = &document["paths"]["/a/register"]["post"]["responses"];
+        assert_eq!(
+            responses["400"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/RegisterFailure"
+        );
+        let failure = &document["components"]["schemas"]["RegisterFailure"]["oneOf"];
+        assert_eq!(failure[0]["$ref"], "#/components/schemas/RegisterResponse");
+        assert_eq!(failure[1]["$ref"], "#/components/schemas/ApiError");
+        for status in ["403", "409", "422"] {
+            assert_eq!(
+                responses[status]["content"]["application/json"]["schema"]["$ref"],
+                "#/components/schemas/ApiError",
+                "status {}",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn idempotency_key_is_listed_on_post_routes() {
+        let document = document();
+        let paths = &document["paths"];
+        assert!(header_names(&paths["/a/register"]["post"]).contains(&"Idempotency-Key"));
+        assert!(header_names(&paths["/api/users"]["post"]).contains(&"Idempotency-Key"));
+        assert!(!header_names(&paths["/a/login"]["post"]).contains(&"Idempotency-Key"));
+        assert!(!header_names(&paths["/api/users"]["get"]).contains(&"Idempotency-Key"));
+    }
+
+    #[test]
+    fn redoc_page_pins_the_script_with_its_integrity() {
+        let page = redoc_page("sha384-abc\"<");
+        assert!(page.contains(&format!(r#"src="{}""#, REDOC_SCRIPT)));
+        assert!(page.contains(r#"integrity="sha384-abc&quot;&lt;" crossorigin="anonymous""#));
+        assert!(!REDOC_SCRIPT.contains("latest"));
+    }
+
+    #[actix_web::test]
+    async fn docs_serves_the_page() {
+        let config = OpenApiConfig {
+            docs: true,
+            redoc_integrity: Some("sha384-abc".to_owned()),
+        };
+        let app = init_service(App::new().configure(|cfg| init_docs(cfg, &config))).await;
+        let body = call_and_read_body(&app, TestRequest::get().uri("/docs").to_request()).await;
+        assert_eq!(body, redoc_page("sha384-abc").into_bytes());
+    }
+}
@@ -5,6 +5,11 @@ pub fn init(cfg: &mut web::ServiceConfig) {
 }
 
 // Ping controller to check the health of application.
+#[utoipa::path(
+    tag = "ping",
+    responses((status = 200, description = "Application is up", body = String)),
+    security(())
+)]
 #[get("/ping")]
 pub async fn ping() -> HttpResponse {
     HttpResponse::Ok().body("pong!".to_string())
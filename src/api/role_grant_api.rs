@@ -0,0 +1,170 @@
+use actix_web::{
+    get, post, web,
+    web::{Data, Json, Path, Query, ReqData},
+    HttpResponse,
+};
+use actix_web_grants::proc_macro::has_any_role;
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::{
+    auth::claims::Claims,
+    config::role_grant::RoleGrantConfig,
+    constants,
+    handler::validated::ValidatedJson,
+    models::{
+        error_model::{ApiError, ApiErrorType},
+        role_grant_model::GrantStatus,
+    },
+    services::role_grant_service,
+};
+
+// -- Configurations...
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.service(request_role_grant);
+    cfg.service(get_role_grants);
+    cfg.service(approve_role_grant);
+    cfg.service(deny_role_grant);
+}
+
+// -- DTO's
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct RoleGrantRequest {
+    // One of `ROLE_GRANT.GRANTABLE_ROLES`.
+    #[validate(length(min = 1, max = 50, message = "role length between 1 and 50"))]
+    #[schema(min_length = 1, max_length = 50)]
+    pub role: String,
+
+    #[validate(length(
+        min = 10,
+        max = 500,
+        message = "justification length between 10 and 500"
+    ))]
+    #[schema(min_length = 10, max_length = 500)]
+    pub justification: String,
+
+    #[validate(range(
+        min = 1,
+        max = constants::MAX_ROLE_GRANT_HOURS,
+        message = "duration must be between 1 and 24 hours"
+    ))]
+    #[schema(minimum = 1, maximum = 24)]
+    pub duration_hours: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RoleGrantDecision {
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RoleGrantResponse {
+    pub id: String,
+    pub auth_id: String,
+    pub role: String,
+    pub justification: String,
+    pub duration_hours: i64,
+    pub status: GrantStatus,
+    pub decided_by: Option<String>,
+    pub decision_note: Option<String>,
+    pub requested_ts: String,
+    pub decided_ts: Option<String>,
+    pub expires_ts: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RoleGrantFilter {
+    pub status: Option<GrantStatus>,
+}
+
+// -- Controllers...
+// Request a temporary role for the signed in user.
+#[utoipa::path(
+    tag = "role-grants",
+    request_body = RoleGrantRequest,
+    responses(
+        (status = 201, description = "Role grant requested", body = RoleGrantResponse),
+        (status = 422, description = "Validation failed or the role can not be granted", body = ApiError),
+    )
+)]
+#[post("/role-grants")]
+pub async fn request_role_grant(
+    client: Data<Client>,
+    config: Data<RoleGrantConfig>,
+    claims: ReqData<Claims>,
+    grant_request: ValidatedJson<RoleGrantRequest>,
+) -> Result<HttpResponse, ApiErrorType> {
+    role_grant_service::request_grant(&client, &config, &claims, grant_request.into_inner()).await
+}
+
+// Get role grants, optionally filtered by status.
+#[utoipa::path(
+    tag = "role-grants",
+    params(RoleGrantFilter),
+    responses(
+        (status = 200, description = "Role grants", body = Vec<RoleGrantResponse>),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+    )
+)]
+#[get("/role-grants")]
+#[has_any_role("ADMIN")]
+pub async fn get_role_grants(
+    client: Data<Client>,
+    filter: Query<RoleGrantFilter>,
+) -> Result<HttpResponse, ApiErrorType> {
+    role_grant_service::get_grants(&client, filter.status).await
+}
+
+// Approve a pending role grant. The approver must not be the requester.
+#[utoipa::path(
+    tag = "role-grants",
+    request_body(content = Option<RoleGrantDecision>),
+    responses(
+        (status = 200, description = "Approved role grant", body = RoleGrantResponse),
+        (status = 403, description = "Needs `ROLE_ADMIN`, or the approver is the requester", body = ApiError),
+        (status = 404, description = "Role grant not found", body = ApiError),
+        (status = 409, description = "Role grant is already decided", body = ApiError),
+        (status = 422, description = "Role can not be granted", body = ApiError),
+    )
+)]
+#[post("/role-grants/{id}/approve")]
+#[has_any_role("ADMIN")]
+pub async fn approve_role_grant(
+    client: Data<Client>,
+    config: Data<RoleGrantConfig>,
+    claims: ReqData<Claims>,
+    path: Path<String>,
+    decision: Option<Json<RoleGrantDecision>>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let note = decision.and_then(|d| d.0.note);
+    role_grant_service::decide_grant(&client, &config, &claims, path, GrantStatus::Approved, note)
+        .await
+}
+
+// Deny a pending role grant.
+#[utoipa::path(
+    tag = "role-grants",
+    request_body(content = Option<RoleGrantDecision>),
+    responses(
+        (status = 200, description = "Denied role grant", body = RoleGrantResponse),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 404, description = "Role grant not found", body = ApiError),
+        (status = 409, description = "Role grant is already decided", body = ApiError),
+    )
+)]
+#[post("/role-grants/{id}/deny")]
+#[has_any_role("ADMIN")]
+pub async fn deny_role_grant(
+    client: Data<Client>,
+    config: Data<RoleGrantConfig>,
+    claims: ReqData<Claims>,
+    path: Path<String>,
+    decision: Option<Json<RoleGrantDecision>>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let note = decision.and_then(|d| d.0.note);
+    role_grant_service::decide_grant(&client, &config, &claims, path, GrantStatus::Denied, note)
+        .await
+}
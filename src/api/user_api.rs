@@ -1,89 +1,816 @@
 use actix_web::{
-    delete, get, post, put, web,
-    web::{Data, Json, Path},
-    HttpResponse,
+    delete, get,
+    http::header::{IfMatch, IfModifiedSince, IfNoneMatch, Range},
+    patch, post, put, web,
+    web::{Data, Header, Json, Path, ReqData},
+    HttpMessage, HttpRequest, HttpResponse,
 };
 use actix_web_grants::proc_macro::has_any_role;
 use log::warn;
 use mongodb::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
 use validator::Validate;
 
 use crate::{
-    models::{error_model::ApiErrorType, user_model::User},
+    auth::claims::Claims,
+    config::user::UserConfig,
+    constants,
+    handler::validated::{ValidatedJson, ValidatedQuery},
+    models::{
+        auth_model::AccountSummary,
+        error_model::{ApiError, ApiErrorType},
+        user_history_model::{FieldChange, RevisionOperation},
+        user_list_response::{CountMode, Link, Meta, UserListResponse},
+        user_model::User,
+    },
+    services::avatar_service,
+    services::user_event_service::{self, EventSubscribers},
     services::user_service,
 };
 
 // -- Configurations...
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(create_user);
+    cfg.service(bulk_users);
+    cfg.service(restore_user);
+    cfg.service(import_users);
+    // Registered before `/users/{id}` so `_deleted`, `export`, `search` and `events` are not
+    // taken for an id.
+    cfg.service(get_deleted_users);
+    cfg.service(export_users);
+    cfg.service(search_users);
+    cfg.service(user_events);
     cfg.service(get_user);
     cfg.service(update_user);
+    cfg.service(patch_user);
     cfg.service(delete_user);
+    cfg.service(put_avatar);
+    cfg.service(get_avatar);
+    cfg.service(delete_avatar);
+    cfg.service(get_user_history);
+    cfg.service(revert_user);
     cfg.service(get_all_users);
 }
 
 // -- Controllers...
 // Create a user
+#[utoipa::path(
+    tag = "users",
+    request_body = User,
+    responses(
+        (status = 201, description = "User created", body = UserResponse),
+        (status = 422, description = "Validation failed", body = ApiError),
+    )
+)]
 #[post("/users")]
 pub async fn create_user(
     client: Data<Client>,
-    new_user: Json<User>,
-) -> Result<HttpResponse, ApiErrorType> {
-    let is_valid = new_user.validate();
-    match is_valid {
-        Ok(_) => user_service::create_user(&client, new_user).await,
-        Err(err) => {
-            warn!("Payload validation Error on add user: {}", err);
-            // Validation error.
-            Err(ApiErrorType::ValidationError {
-                validation_error: err,
-                object: "User".to_string(),
+    claims: ReqData<Claims>,
+    new_user: ValidatedJson<User>,
+) -> Result<HttpResponse, ApiErrorType> {
+    user_service::create_user(&client, &claims, new_user.into_inner()).await
+}
+
+// Create, update and delete users in one request.
+#[utoipa::path(
+    tag = "users",
+    request_body = BulkRequest,
+    responses(
+        (status = 200, description = "Result of every operation", body = BulkResponse),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 413, description = "Too many operations", body = ApiError),
+    )
+)]
+#[post("/users/_bulk")]
+#[has_any_role("ADMIN")]
+pub async fn bulk_users(
+    client: Data<Client>,
+    config: Data<UserConfig>,
+    claims: ReqData<Claims>,
+    request: Json<BulkRequest>,
+) -> Result<HttpResponse, ApiErrorType> {
+    user_service::bulk_users(&client, &config, &claims, request.into_inner()).await
+}
+
+// Download users matching the list filters as CSV or NDJSON.
+#[utoipa::path(
+    tag = "users",
+    params(ExportQuery, UserFilter),
+    responses(
+        (status = 200, description = "Users as CSV or NDJSON", content(
+            (String = "text/csv"),
+            (String = "application/x-ndjson"),
+        )),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+    )
+)]
+#[get("/users/export")]
+#[has_any_role("ADMIN")]
+pub async fn export_users(
+    client: Data<Client>,
+    export: web::Query<ExportQuery>,
+    filter: web::Query<UserFilter>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let format = export.format.unwrap_or_default();
+    user_service::export_users(&client, &filter.0, format).await
+}
+
+// Stream user changes as Server-Sent Events. Takes the list filters.
+#[utoipa::path(
+    tag = "users",
+    params(
+        UserFilter,
+        ("Last-Event-ID" = Option<String>, Header, description = "Resume after this event"),
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events of user changes", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid event id", body = ApiError),
+        (status = 403, description = "Needs `ROLE_USER`"),
+        (status = 503, description = "Too many subscribers", body = ApiError),
+    )
+)]
+#[get("/users/events")]
+#[has_any_role("USER")]
+pub async fn user_events(
+    client: Data<Client>,
+    config: Data<UserConfig>,
+    subscribers: Data<EventSubscribers>,
+    req: HttpRequest,
+    filter: web::Query<UserFilter>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let last_event_id = req
+        .headers()
+        .get(constants::LAST_EVENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+    user_event_service::user_events(&client, &config, &subscribers, &filter, last_event_id).await
+}
+
+// Create users from a CSV (`text/csv`) or NDJSON (`application/x-ndjson`) upload.
+#[utoipa::path(
+    tag = "users",
+    params(ImportQuery),
+    request_body(content(
+        (String = "text/csv"),
+        (String = "application/x-ndjson"),
+    )),
+    responses(
+        (status = 200, description = "Import summary with the rejected rows", body = ImportResponse),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 413, description = "Upload is too large", body = ApiError),
+        (status = 415, description = "Not CSV or NDJSON", body = ApiError),
+        (status = 422, description = "Upload can not be read", body = ApiError),
+    )
+)]
+#[post("/users/import")]
+#[has_any_role("ADMIN")]
+pub async fn import_users(
+    client: Data<Client>,
+    config: Data<UserConfig>,
+    claims: ReqData<Claims>,
+    req: HttpRequest,
+    import: web::Query<ImportQuery>,
+    payload: web::Payload,
+) -> Result<HttpResponse, ApiErrorType> {
+    let dry_run = import.dry_run.unwrap_or(false);
+    // Only this route accepts uploads above the default payload limit.
+    let body = match payload.to_bytes_limited(config.import_max_bytes).await {
+        Ok(Ok(body)) => body,
+        Ok(Err(err)) => {
+            warn!("Error reading import upload: {}", err);
+            return Err(ApiErrorType::BadRequest);
+        }
+        Err(_) => {
+            return Err(ApiErrorType::ImportTooLarge {
+                max: config.import_max_bytes,
             })
         }
-    }
+    };
+    user_service::import_users(
+        &client,
+        &config,
+        &claims,
+        req.content_type(),
+        dry_run,
+        &body,
+    )
+    .await
+}
+
+// Full text search over user name, title and location.
+#[utoipa::path(
+    tag = "users",
+    params(UserSearch),
+    responses(
+        (status = 200, description = "Matching users, most relevant first", body = UserListResponse),
+        (status = 403, description = "Needs `ROLE_USER`"),
+        (status = 422, description = "Validation failed", body = ApiError),
+    )
+)]
+#[get("/users/search")]
+#[has_any_role("USER")]
+pub async fn search_users(
+    client: Data<Client>,
+    search: ValidatedQuery<UserSearch>,
+) -> Result<HttpResponse, ApiErrorType> {
+    user_service::search_users(&client, &search.0).await
 }
 
 // Get user by unique user id.
+// Answers `304 Not Modified` when the `If-None-Match` or `If-Modified-Since` header still holds.
+#[utoipa::path(
+    tag = "users",
+    params(
+        UserFields,
+        ("If-None-Match" = Option<String>, Header, description = "`ETag` of a cached user"),
+        ("If-Modified-Since" = Option<String>, Header, description = "`Last-Modified` of a cached user"),
+    ),
+    responses(
+        (status = 200, description = "User", body = UserResponse),
+        (status = 304, description = "Cached user is current"),
+        (status = 400, description = "Unknown field", body = ApiError),
+        (status = 404, description = "User not found", body = ApiError),
+    )
+)]
 #[get("/users/{id}")]
 pub async fn get_user(
     client: Data<Client>,
+    config: Data<UserConfig>,
     path: Path<String>,
+    fields: web::Query<UserFields>,
+    if_none_match: Option<Header<IfNoneMatch>>,
+    if_modified_since: Option<Header<IfModifiedSince>>,
 ) -> Result<HttpResponse, ApiErrorType> {
-    user_service::get_user_by_id(&client, path).await
+    let if_none_match = if_none_match.map(Header::into_inner);
+    let if_modified_since = if_modified_since.map(Header::into_inner);
+    user_service::get_user_by_id(
+        &client,
+        &config,
+        path,
+        &fields,
+        if_none_match,
+        if_modified_since,
+    )
+    .await
 }
 
 // Update user by unique user id.
+// Only updates the version in the `If-Match` header, when given.
+#[utoipa::path(
+    tag = "users",
+    request_body = User,
+    params(("If-Match" = Option<String>, Header, description = "Only write this version of the user")),
+    responses(
+        (status = 200, description = "Updated user", body = UserResponse),
+        (status = 404, description = "User not found", body = ApiError),
+        (status = 412, description = "User has another version", body = ApiError),
+        (status = 422, description = "Validation failed", body = ApiError),
+        (status = 428, description = "`If-Match` is required", body = ApiError),
+    )
+)]
 #[put("/users/{id}")]
 pub async fn update_user(
     client: Data<Client>,
+    config: Data<UserConfig>,
+    claims: ReqData<Claims>,
+    path: Path<String>,
+    update_user: ValidatedJson<User>,
+    if_match: Option<Header<IfMatch>>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let if_match = if_match.map(Header::into_inner);
+    let update_user = update_user.into_inner();
+    user_service::update_user(&client, &config, &claims, path, update_user, if_match).await
+}
+
+// Partially update user by unique user id.
+// Accepts `application/merge-patch+json` and `application/json-patch+json` payloads.
+#[utoipa::path(
+    tag = "users",
+    request_body(content(
+        (Object = "application/merge-patch+json"),
+        (Object = "application/json-patch+json"),
+    )),
+    params(("If-Match" = Option<String>, Header, description = "Only write this version of the user")),
+    responses(
+        (status = 200, description = "Patched user", body = UserResponse),
+        (status = 404, description = "User not found", body = ApiError),
+        (status = 409, description = "User changed while patching", body = ApiError),
+        (status = 412, description = "User has another version", body = ApiError),
+        (status = 415, description = "Not a merge patch or JSON patch", body = ApiError),
+        (status = 422, description = "Patch can not be applied or validation failed", body = ApiError),
+        (status = 428, description = "`If-Match` is required", body = ApiError),
+    )
+)]
+#[patch("/users/{id}")]
+pub async fn patch_user(
+    client: Data<Client>,
+    config: Data<UserConfig>,
+    claims: ReqData<Claims>,
+    req: HttpRequest,
     path: Path<String>,
-    update_user: Json<User>,
+    patch: Json<Value>,
+    if_match: Option<Header<IfMatch>>,
 ) -> Result<HttpResponse, ApiErrorType> {
-    user_service::update_user(&client, path, update_user).await
+    let if_match = if_match.map(Header::into_inner);
+    user_service::patch_user(
+        &client,
+        &config,
+        &claims,
+        path,
+        req.content_type(),
+        patch.into_inner(),
+        if_match,
+    )
+    .await
 }
 
-// Delete user by unique user id.
+// Soft delete user by unique user id.
+// Only deletes the version in the `If-Match` header, when given.
+#[utoipa::path(
+    tag = "users",
+    params(("If-Match" = Option<String>, Header, description = "Only write this version of the user")),
+    responses(
+        (status = 204, description = "User deleted"),
+        (status = 404, description = "User not found", body = ApiError),
+        (status = 412, description = "User has another version", body = ApiError),
+        (status = 428, description = "`If-Match` is required", body = ApiError),
+    )
+)]
 #[delete("/users/{id}")]
 pub async fn delete_user(
     client: Data<Client>,
+    config: Data<UserConfig>,
+    claims: ReqData<Claims>,
+    path: Path<String>,
+    if_match: Option<Header<IfMatch>>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let if_match = if_match.map(Header::into_inner);
+    user_service::delete_user(&client, &config, &claims, path, if_match).await
+}
+
+// Upload the avatar of a user, as the `avatar` field of a `multipart/form-data` body or as
+// the raw image. Only updates the version in the `If-Match` header, when given.
+#[utoipa::path(
+    tag = "users",
+    request_body(content(
+        (Vec<u8> = "multipart/form-data"),
+        (Vec<u8> = "image/png"),
+        (Vec<u8> = "image/jpeg"),
+        (Vec<u8> = "image/gif"),
+        (Vec<u8> = "image/webp"),
+    )),
+    params(("If-Match" = Option<String>, Header, description = "Only write this version of the user")),
+    responses(
+        (status = 204, description = "Avatar stored"),
+        (status = 404, description = "User not found", body = ApiError),
+        (status = 412, description = "User has another version", body = ApiError),
+        (status = 413, description = "Avatar is too large", body = ApiError),
+        (status = 415, description = "Not an image", body = ApiError),
+        (status = 422, description = "Image can not be read", body = ApiError),
+    )
+)]
+#[put("/users/{id}/avatar")]
+pub async fn put_avatar(
+    client: Data<Client>,
+    config: Data<UserConfig>,
+    claims: ReqData<Claims>,
+    req: HttpRequest,
+    path: Path<String>,
+    payload: web::Payload,
+    if_match: Option<Header<IfMatch>>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let if_match = if_match.map(Header::into_inner);
+    avatar_service::put_avatar(
+        &client,
+        &config,
+        &claims,
+        path.into_inner(),
+        &req,
+        payload,
+        if_match,
+    )
+    .await
+}
+
+// Download the avatar of a user, or its thumbnail with `size=thumbnail`.
+// Supports a single `Range` and `If-None-Match`.
+#[utoipa::path(
+    tag = "users",
+    params(
+        AvatarQuery,
+        ("Range" = Option<String>, Header, description = "A single byte range"),
+        ("If-None-Match" = Option<String>, Header, description = "`ETag` of a cached avatar"),
+    ),
+    responses(
+        (status = 200, description = "Avatar image", content_type = "image/*", body = Vec<u8>),
+        (status = 206, description = "Requested byte range", content_type = "image/*", body = Vec<u8>),
+        (status = 304, description = "Cached avatar is current"),
+        (status = 404, description = "User or avatar not found", body = ApiError),
+        (status = 416, description = "Range can not be satisfied"),
+    )
+)]
+#[get("/users/{id}/avatar")]
+pub async fn get_avatar(
+    client: Data<Client>,
+    config: Data<UserConfig>,
     path: Path<String>,
+    avatar: web::Query<AvatarQuery>,
+    range: Option<Header<Range>>,
+    if_none_match: Option<Header<IfNoneMatch>>,
 ) -> Result<HttpResponse, ApiErrorType> {
-    user_service::delete_user(&client, path).await
+    avatar_service::get_avatar(
+        &client,
+        &config,
+        path.into_inner(),
+        avatar.size.unwrap_or_default(),
+        range.map(Header::into_inner),
+        if_none_match.map(Header::into_inner),
+    )
+    .await
 }
 
-#[derive(Deserialize)]
+// Delete the avatar of a user.
+#[utoipa::path(
+    tag = "users",
+    params(("If-Match" = Option<String>, Header, description = "Only write this version of the user")),
+    responses(
+        (status = 204, description = "Avatar deleted"),
+        (status = 404, description = "User or avatar not found", body = ApiError),
+        (status = 412, description = "User has another version", body = ApiError),
+    )
+)]
+#[delete("/users/{id}/avatar")]
+pub async fn delete_avatar(
+    client: Data<Client>,
+    config: Data<UserConfig>,
+    claims: ReqData<Claims>,
+    path: Path<String>,
+    if_match: Option<Header<IfMatch>>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let if_match = if_match.map(Header::into_inner);
+    avatar_service::delete_avatar(&client, &config, &claims, path.into_inner(), if_match).await
+}
+
+// List the revisions of a user, newest first.
+#[utoipa::path(
+    tag = "users",
+    params(Pagination),
+    responses(
+        (status = 200, description = "Revisions, newest first", body = UserHistoryResponse),
+        (status = 403, description = "Needs `ROLE_USER`"),
+        (status = 422, description = "Validation failed", body = ApiError),
+    )
+)]
+#[get("/users/{id}/history")]
+#[has_any_role("USER")]
+pub async fn get_user_history(
+    client: Data<Client>,
+    path: Path<String>,
+    pagination: ValidatedQuery<Pagination>,
+) -> Result<HttpResponse, ApiErrorType> {
+    user_service::get_user_history(&client, path.into_inner(), &pagination.0).await
+}
+
+// Set the name, location and title of a user back to the ones of a revision.
+#[utoipa::path(
+    tag = "users",
+    params(("If-Match" = Option<String>, Header, description = "Only write this version of the user")),
+    responses(
+        (status = 200, description = "Reverted user", body = UserResponse),
+        (status = 404, description = "User or revision not found", body = ApiError),
+        (status = 412, description = "User has another version", body = ApiError),
+        (status = 428, description = "`If-Match` is required", body = ApiError),
+    )
+)]
+#[post("/users/{id}/history/{rev}/revert")]
+pub async fn revert_user(
+    client: Data<Client>,
+    config: Data<UserConfig>,
+    claims: ReqData<Claims>,
+    path: Path<(String, i64)>,
+    if_match: Option<Header<IfMatch>>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let (id, rev) = path.into_inner();
+    let if_match = if_match.map(Header::into_inner);
+    user_service::revert_user(&client, &config, &claims, id, rev, if_match).await
+}
+
+// Restore a soft deleted user.
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "Restored user", body = UserResponse),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 404, description = "No deleted user with the id", body = ApiError),
+    )
+)]
+#[post("/users/{id}/restore")]
+#[has_any_role("ADMIN")]
+pub async fn restore_user(
+    client: Data<Client>,
+    claims: ReqData<Claims>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiErrorType> {
+    user_service::restore_user(&client, &claims, path).await
+}
+
+// List soft deleted users that are not purged yet.
+#[utoipa::path(
+    tag = "users",
+    params(Pagination),
+    responses(
+        (status = 200, description = "Deleted users", body = Vec<UserResponse>),
+        (status = 403, description = "Needs `ROLE_ADMIN`"),
+        (status = 422, description = "Validation failed", body = ApiError),
+    )
+)]
+#[get("/users/_deleted")]
+#[has_any_role("ADMIN")]
+pub async fn get_deleted_users(
+    client: Data<Client>,
+    pagination: ValidatedQuery<Pagination>,
+) -> Result<HttpResponse, ApiErrorType> {
+    user_service::get_deleted_users(&client, &pagination.0).await
+}
+
+// User as returned by the API.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UserResponse {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub name: String,
+    pub location: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_id: Option<String>,
+    pub version: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_ts: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_ts: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
+    // Linked auth account. Only set with `include=account`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<AccountSummary>,
+}
+
+// One stored version of a user.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserRevisionResponse {
+    pub rev: i64,
+    pub operation: RevisionOperation,
+    pub changed_by: String,
+    pub changed_ts: String,
+    pub user: UserResponse,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserHistoryResponse {
+    pub data: Vec<UserRevisionResponse>,
+    pub meta: Meta,
+    pub _link: Link,
+}
+
+// Batch of user operations. Ordered batches (the default) stop at the first failed operation.
+#[derive(Deserialize, ToSchema)]
+pub struct BulkRequest {
+    pub ordered: Option<bool>,
+    pub operations: Vec<BulkOperation>,
+}
+
+// One operation of a bulk request. `version` makes updates and deletes conditional, like
+// `If-Match` does for single requests.
+#[derive(Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BulkOperation {
+    Create {
+        user: User,
+    },
+    Update {
+        id: String,
+        user: User,
+        version: Option<i64>,
+    },
+    Delete {
+        id: String,
+        version: Option<i64>,
+    },
+}
+
+#[derive(Debug, Serialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkStatus {
+    Created,
+    Updated,
+    Deleted,
+    Failed,
+    // Not run because an earlier operation of an ordered batch failed.
+    Skipped,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkResult {
+    pub index: usize,
+    pub status: BulkStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkResponse {
+    pub ordered: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub results: Vec<BulkResult>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    pub format: Option<ExportFormat>,
+}
+
+// `dry_run` validates the upload without creating users.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    pub dry_run: Option<bool>,
+}
+
+// CSV row of the export. Import reads `name`, `location` and `title` and ignores the rest.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserRecord {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    pub location: String,
+    pub title: String,
+    #[serde(default)]
+    pub version: Option<i64>,
+    #[serde(default)]
+    pub updated_ts: Option<String>,
+}
+
+// Rows are numbered from 1, not counting the CSV header.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportRowError {
+    pub row: usize,
+    pub error: ApiError,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportResponse {
+    pub dry_run: bool,
+    pub rows: usize,
+    pub valid: usize,
+    pub imported: usize,
+    pub failed: usize,
+    pub errors: Vec<ImportRowError>,
+}
+
+// Offset pagination with `offset`, or cursor pagination with the opaque `after` / `before`
+// cursors from the `next` / `previous` links.
+#[derive(Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Pagination {
     pub offset: Option<u64>,
+    #[validate(range(
+        min = 1,
+        max = constants::MAX_LIMIT_SIZE,
+        message = "limit must be between 1 and 1000"
+    ))]
+    #[param(minimum = 1, maximum = 1000)]
+    pub limit: Option<i64>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub count: Option<CountMode>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AvatarSize {
+    #[default]
+    Original,
+    Thumbnail,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AvatarQuery {
+    pub size: Option<AvatarSize>,
+}
+
+// Sparse fieldsets and embedded relations. `fields` is a comma separated list of the user
+// fields to return and `include=account` embeds the linked auth account.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserFields {
+    pub fields: Option<String>,
+    pub include: Option<String>,
+}
+
+// Filters and sort order of the user list.
+// `<field>` matches exactly, `<field>_prefix` matches the start and `<field>_contains` matches
+// anywhere ignoring case. `sort` is a comma separated field list, `-` prefix for descending.
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_contains: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location_contains: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_contains: Option<String>,
+    #[serde(skip_serializing, alias = "sort_by")]
+    pub sort: Option<String>,
+}
+
+// Text search with offset pagination. `highlight` marks the matched words of each field.
+#[derive(Debug, Serialize, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserSearch {
+    pub q: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<bool>,
+    #[serde(skip_serializing)]
+    pub offset: Option<u64>,
+    #[serde(skip_serializing)]
+    #[validate(range(
+        min = 1,
+        max = constants::MAX_LIMIT_SIZE,
+        message = "limit must be between 1 and 1000"
+    ))]
+    #[param(minimum = 1, maximum = 1000)]
     pub limit: Option<i64>,
 }
 
-// Get list of all users in the database and handle pagination.
+// Get list of all users in the database and handle pagination, filters and sorting.
+#[utoipa::path(
+    tag = "users",
+    params(
+        Pagination,
+        UserFilter,
+        UserFields,
+        ("If-None-Match" = Option<String>, Header, description = "`ETag` of a cached page"),
+    ),
+    responses(
+        (status = 200, description = "Page of users", body = UserListResponse),
+        (status = 304, description = "Cached page is current"),
+        (status = 400, description = "Invalid sort, field or cursor", body = ApiError),
+        (status = 403, description = "Needs `ROLE_USER`"),
+        (status = 422, description = "Validation failed", body = ApiError),
+    )
+)]
 #[get("/users")]
 #[has_any_role("USER")]
 pub async fn get_all_users(
     client: Data<Client>,
-    pagination: web::Query<Pagination>,
+    config: Data<UserConfig>,
+    pagination: ValidatedQuery<Pagination>,
+    filter: web::Query<UserFilter>,
+    fields: web::Query<UserFields>,
+    if_none_match: Option<Header<IfNoneMatch>>,
 ) -> Result<HttpResponse, ApiErrorType> {
-    user_service::get_all_users(&client, &pagination.0).await
+    let if_none_match = if_none_match.map(Header::into_inner);
+    user_service::get_all_users(
+        &client,
+        &config,
+        &pagination.0,
+        &filter.0,
+        &fields,
+        if_none_match,
+    )
+    .await
 }
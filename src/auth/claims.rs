@@ -6,13 +6,14 @@ use serde::{Deserialize, Serialize};
 
 use crate::api::auth_api::LoginResponse;
 use crate::models::auth_model::Auth;
+use crate::models::role_grant_model::RoleGrant;
 
 // JWT lifetime and Secret key.
 const JWT_EXPIRATION_HOURS: i64 = 24;
 const SECRET: &str = "secret";
 
 // Claims for JWT Body.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub permissions: Vec<String>,
@@ -24,19 +25,31 @@ pub struct Claims {
 // Kind of constructor for Rust.
 // TODO: Use Public and Private key to Signing and verifying JWT token.
 impl Claims {
-    pub fn new(sub: &String, permissions: &Vec<String>) -> Self {
+    pub fn new(sub: &String, permissions: &Vec<String>, grants: &[RoleGrant]) -> Self {
+        let now = Utc::now();
+        let mut permissions = permissions.to_owned();
+        let mut exp = now + Duration::hours(JWT_EXPIRATION_HOURS);
+        // Add temporary roles active right now. The token must not outlive any of them.
+        for grant in grants.iter().filter(|g| g.is_active(now)) {
+            if !permissions.contains(&grant.role) {
+                permissions.push(grant.role.to_owned());
+            }
+            if let Some(expires_ts) = grant.expires_ts {
+                exp = exp.min(expires_ts);
+            }
+        }
         Self {
             sub: sub.to_string(),
-            permissions: permissions.to_owned(),
+            permissions,
             iss: String::from("https://c12.io"),
-            exp: (Utc::now() + Duration::hours(JWT_EXPIRATION_HOURS)).timestamp(),
-            iat: (Utc::now()).timestamp(),
+            exp: exp.timestamp(),
+            iat: now.timestamp(),
         }
     }
 
-    // Create JWT token from Auth values.
-    pub fn create_jwt_token(auth: &Auth) -> Result<LoginResponse, Error> {
-        let claim = Claims::new(&auth.id, &auth.roles);
+    // Create JWT token from Auth values and temporary role grants.
+    pub fn create_jwt_token(auth: &Auth, grants: &[RoleGrant]) -> Result<LoginResponse, Error> {
+        let claim = Claims::new(&auth.id, &auth.roles, grants);
         let encoding_key = EncodingKey::from_secret(SECRET.as_bytes());
         let jwt_token = encode(&Header::default(), &claim, &encoding_key);
 
@@ -56,4 +69,9 @@ impl Claims {
             .map(|data| data.claims)
             .map_err(ErrorUnauthorized)
     }
+
+    // Expiry of the token, in seconds since the epoch.
+    pub fn expires_at(&self) -> i64 {
+        self.exp
+    }
 }
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use ldap3::{dn_escape, ldap_escape, LdapConnAsync, LdapError, Scope, SearchEntry};
+
+// LDAP result code returned by a bind with a wrong DN or password.
+const INVALID_CREDENTIALS: u32 = 49;
+
+// LDAP directory settings used by the LDAP authentication provider.
+#[derive(Debug, Clone)]
+pub struct LdapConfig {
+    // Directory URL. e.g. `ldap://localhost:389` or `ldaps://ldap.c12.io`
+    pub url: String,
+    // DN to bind as the user. `{username}` is replaced with the local part of the email and
+    // `{email}` with the full email address. e.g. `uid={username},ou=people,dc=c12,dc=io`
+    pub bind_dn_template: String,
+    // Base DN to search for the groups of the user.
+    pub search_base: String,
+    // Filter to find the groups of the user. `{dn}` is replaced with the bound user DN.
+    pub group_filter: String,
+    // Group DN (lower case) to application role mapping.
+    pub role_mapping: HashMap<String, String>,
+    // Roles given to every directory user on top of the mapped roles.
+    pub default_roles: Vec<String>,
+}
+
+// Directory profile of a user after a successful bind.
+#[derive(Debug)]
+pub struct LdapUser {
+    pub first_name: String,
+    pub last_name: String,
+    pub roles: Vec<String>,
+}
+
+impl LdapConfig {
+    fn bind_dn(&self, email: &str) -> String {
+        let username = email.split('@').next().unwrap_or(email);
+        self.bind_dn_template
+            .replace("{username}", &dn_escape(username))
+            .replace("{email}", &dn_escape(email))
+    }
+
+    fn roles_for(&self, group_dns: &[String]) -> Vec<String> {
+        let mut roles = self.default_roles.clone();
+        for group_dn in group_dns {
+            if let Some(role) = self.role_mapping.get(&group_dn.to_lowercase()) {
+                if !roles.contains(role) {
+                    roles.push(role.to_owned());
+                }
+            }
+        }
+        roles
+    }
+}
+
+// Simple bind as the user, then read the profile and group membership with the same connection.
+// Returns `None` when the directory rejects the credentials.
+pub async fn authenticate(
+    config: &LdapConfig,
+    email: &str,
+    password: &str,
+) -> Result<Option<LdapUser>, LdapError> {
+    // A bind with an empty password is an unauthenticated bind and always succeeds.
+    if password.is_empty() {
+        return Ok(None);
+    }
+
+    let (conn, mut ldap) = LdapConnAsync::new(&config.url).await?;
+    ldap3::drive!(conn);
+
+    let bind_dn = config.bind_dn(email);
+    let bind = ldap.simple_bind(&bind_dn, password).await?;
+    if bind.rc == INVALID_CREDENTIALS {
+        ldap.unbind().await?;
+        return Ok(None);
+    }
+    bind.success()?;
+
+    // Profile attributes from the user entry itself.
+    let (entries, _) = ldap
+        .search(
+            &bind_dn,
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["givenName", "sn", "cn"],
+        )
+        .await?
+        .success()?;
+    let profile = entries.into_iter().next().map(SearchEntry::construct);
+    let attr = |name: &str| {
+        profile
+            .as_ref()
+            .and_then(|p| p.attrs.get(name))
+            .and_then(|values| values.first())
+            .cloned()
+    };
+
+    // Groups the user is a member of.
+    let group_filter = config.group_filter.replace("{dn}", &ldap_escape(&bind_dn));
+    let (groups, _) = ldap
+        .search(
+            &config.search_base,
+            Scope::Subtree,
+            &group_filter,
+            vec!["1.1"],
+        )
+        .await?
+        .success()?;
+    let group_dns: Vec<String> = groups
+        .into_iter()
+        .map(|group| SearchEntry::construct(group).dn)
+        .collect();
+    ldap.unbind().await?;
+
+    Ok(Some(LdapUser {
+        first_name: attr("givenName").or_else(|| attr("cn")).unwrap_or_default(),
+        last_name: attr("sn").unwrap_or_default(),
+        roles: config.roles_for(&group_dns),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LdapConfig {
+        LdapConfig {
+            url: std::env::var("LDAP_TEST_URL").unwrap_or("ldap://localhost:389".to_owned()),
+            bind_dn_template: "cn={username},dc=c12,dc=io".to_owned(),
+            search_base: "dc=c12,dc=io".to_owned(),
+            group_filter: "(|(member={dn})(uniqueMember={dn}))".to_owned(),
+            role_mapping: HashMap::from([(
+                "cn=admins,ou=groups,dc=c12,dc=io".to_owned(),
+                "ROLE_ADMIN".to_owned(),
+            )]),
+            default_roles: vec!["ROLE_USER".to_owned()],
+        }
+    }
+
+    #[test]
+    fn bind_dn_uses_local_part_and_email() {
+        let mut config = config();
+        assert_eq!(config.bind_dn("john@c12.io"), "cn=john,dc=c12,dc=io");
+
+        config.bind_dn_template = "mail={email},ou=people,dc=c12,dc=io".to_owned();
+        assert_eq!(
+            config.bind_dn("john@c12.io"),
+            "mail=john@c12.io,ou=people,dc=c12,dc=io"
+        );
+    }
+
+    #[test]
+    fn bind_dn_escapes_special_characters() {
+        let config = config();
+        assert_eq!(
+            config.bind_dn("john,ou=admins@c12.io"),
+            "cn=john\\2cou\\3dadmins,dc=c12,dc=io"
+        );
+    }
+
+    #[test]
+    fn roles_for_maps_groups_case_insensitively() {
+        let config = config();
+        let roles = config.roles_for(&[
+            "CN=Admins,OU=Groups,DC=c12,DC=io".to_owned(),
+            "cn=admins,ou=groups,dc=c12,dc=io".to_owned(),
+            "cn=other,ou=groups,dc=c12,dc=io".to_owned(),
+        ]);
+        assert_eq!(roles, vec!["ROLE_USER", "ROLE_ADMIN"]);
+    }
+
+    #[test]
+    fn roles_for_without_groups_gives_default_roles() {
+        assert_eq!(config().roles_for(&[]), vec!["ROLE_USER"]);
+    }
+
+    #[actix_web::test]
+    async fn empty_password_is_rejected_without_connecting() {
+        let mut config = config();
+        config.url = "ldap://127.0.0.1:1".to_owned();
+        assert!(authenticate(&config, "admin@c12.io", "")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    // Needs a local directory, see the README:
+    // `docker run -p 389:389 -e LDAP_DOMAIN=c12.io -e LDAP_ADMIN_PASSWORD=admin osixia/openldap`
+    // Run with `cargo test -- --ignored`.
+    #[actix_web::test]
+    #[ignore]
+    async fn binds_against_local_directory() {
+        let config = config();
+        let user = authenticate(&config, "admin@c12.io", "admin")
+            .await
+            .unwrap()
+            .expect("admin bind");
+        assert_eq!(user.roles, vec!["ROLE_USER"]);
+
+        let wrong = authenticate(&config, "admin@c12.io", "wrong")
+            .await
+            .unwrap();
+        assert!(wrong.is_none());
+    }
+}
@@ -1 +1,6 @@
 pub mod claims;
+pub mod ldap;
+pub mod password;
+pub mod password_policy;
+pub mod provider;
+pub mod registration;
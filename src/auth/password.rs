@@ -0,0 +1,390 @@
+use actix_web::web;
+use argon2::{Config, Variant, Version};
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use derive_more::Display;
+use rand::Rng;
+use scrypt::password_hash::{self, PasswordHash, PasswordVerifier};
+use scrypt::Scrypt;
+use sha2::Sha256;
+
+// Encoded prefix of hashes created with the current argon2 settings.
+const CURRENT_ARGON2_PREFIX: &str = "$argon2id$v=19$m=65536,t=10,p=4$";
+
+// Shortest PBKDF2 digest accepted. A shorter one, or an empty one, would match too many passwords.
+const MIN_PBKDF2_DIGEST_LENGTH: usize = 16;
+
+// Highest work factors accepted. Every login verifies with them on the blocking thread pool, so
+// an imported hash above them could hold a thread for minutes or allocate gigabytes.
+const MAX_PBKDF2_ITERATIONS: u32 = 2_000_000;
+const MAX_PBKDF2_DIGEST_LENGTH: usize = 64;
+// `128 * r * p * 2^ln` bytes, a bound on both the memory and the time of scrypt.
+const MAX_SCRYPT_COST: u64 = 256 * 1024 * 1024;
+const MAX_BCRYPT_COST: u32 = 16;
+const MAX_ARGON2_MEMORY_KIB: u32 = 256 * 1024;
+const MAX_ARGON2_ITERATIONS: u32 = 16;
+const MAX_ARGON2_LANES: u32 = 16;
+
+// Password hash formats accepted on login.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashScheme {
+    Argon2,
+    Bcrypt,
+    Scrypt,
+    Pbkdf2Sha256,
+}
+
+#[derive(Debug, Display)]
+pub enum HashError {
+    #[display(fmt = "Unknown password hash format")]
+    UnknownFormat,
+    #[display(fmt = "Malformed {} password hash", _0)]
+    Malformed(&'static str),
+    #[display(fmt = "{} password hash parameters exceed the supported maximum", _0)]
+    TooExpensive(&'static str),
+    #[display(fmt = "Password hashing failed")]
+    Failed,
+}
+
+impl HashScheme {
+    // Recognise the hash format from its prefix.
+    pub fn detect(hash: &str) -> Option<HashScheme> {
+        if hash.starts_with("$argon2") {
+            Some(HashScheme::Argon2)
+        } else if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+        {
+            Some(HashScheme::Bcrypt)
+        } else if hash.starts_with("$scrypt$") {
+            Some(HashScheme::Scrypt)
+        } else if hash.starts_with("$pbkdf2-sha256$") || hash.starts_with("pbkdf2_sha256$") {
+            Some(HashScheme::Pbkdf2Sha256)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            HashScheme::Argon2 => "argon2",
+            HashScheme::Bcrypt => "bcrypt",
+            HashScheme::Scrypt => "scrypt",
+            HashScheme::Pbkdf2Sha256 => "pbkdf2-sha256",
+        }
+    }
+}
+
+// Fully parse a hash, so a malformed one is caught on import and not on the first login.
+pub fn check_hash(hash: &str) -> Result<HashScheme, HashError> {
+    let scheme = HashScheme::detect(hash).ok_or(HashError::UnknownFormat)?;
+    let valid = match scheme {
+        HashScheme::Argon2 => PasswordHash::new(hash)
+            .is_ok_and(|parsed| parsed.salt.is_some() && parsed.hash.is_some()),
+        HashScheme::Bcrypt => hash.parse::<bcrypt::HashParts>().is_ok(),
+        HashScheme::Scrypt => PasswordHash::new(hash).is_ok_and(|parsed| {
+            parsed.salt.is_some()
+                && parsed.hash.is_some()
+                && scrypt::Params::try_from(&parsed).is_ok()
+        }),
+        HashScheme::Pbkdf2Sha256 => parse_pbkdf2_sha256(hash).is_some(),
+    };
+    if !valid {
+        return Err(HashError::Malformed(scheme.name()));
+    }
+    if !within_limits(scheme, hash) {
+        return Err(HashError::TooExpensive(scheme.name()));
+    }
+    Ok(scheme)
+}
+
+fn within_limits(scheme: HashScheme, hash: &str) -> bool {
+    match scheme {
+        HashScheme::Argon2 => PasswordHash::new(hash).is_ok_and(|parsed| {
+            let param = |name: &str| parsed.params.get_decimal(name).unwrap_or_default();
+            param("m") <= MAX_ARGON2_MEMORY_KIB
+                && param("t") <= MAX_ARGON2_ITERATIONS
+                && param("p") <= MAX_ARGON2_LANES
+        }),
+        HashScheme::Bcrypt => hash
+            .parse::<bcrypt::HashParts>()
+            .is_ok_and(|parts| parts.get_cost() <= MAX_BCRYPT_COST),
+        HashScheme::Scrypt => PasswordHash::new(hash)
+            .ok()
+            .and_then(|parsed| scrypt::Params::try_from(&parsed).ok())
+            .and_then(|params| {
+                1u64.checked_shl(params.log_n().into())?
+                    .checked_mul(128 * u64::from(params.r()) * u64::from(params.p()))
+            })
+            .is_some_and(|cost| cost <= MAX_SCRYPT_COST),
+        HashScheme::Pbkdf2Sha256 => {
+            parse_pbkdf2_sha256(hash).is_some_and(|(iterations, _, expected)| {
+                iterations <= MAX_PBKDF2_ITERATIONS && expected.len() <= MAX_PBKDF2_DIGEST_LENGTH
+            })
+        }
+    }
+}
+
+// Current argon2id settings for new password hashes.
+fn argon2_config() -> Config<'static> {
+    Config {
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        mem_cost: 65536,
+        time_cost: 10,
+        lanes: 4,
+        secret: &[],
+        ad: &[],
+        hash_length: 64,
+    }
+}
+
+// Hash password with argon2 and a random 16-byte salt.
+pub fn hash_password(password: &str) -> Result<String, argon2::Error> {
+    let mut rng = rand::rng();
+    let salt: [u8; 16] = rng.random();
+    argon2::hash_encoded(password.as_bytes(), &salt, &argon2_config())
+}
+
+// Verify password against a hash in any of the supported formats.
+pub fn verify_password(hash: &str, password: &str) -> Result<bool, HashError> {
+    let scheme = HashScheme::detect(hash);
+    if let Some(scheme) = scheme.filter(|scheme| !within_limits(*scheme, hash)) {
+        return Err(HashError::TooExpensive(scheme.name()));
+    }
+    match scheme {
+        Some(HashScheme::Argon2) => argon2::verify_encoded(hash, password.as_bytes())
+            .map_err(|_| HashError::Malformed("argon2")),
+        Some(HashScheme::Bcrypt) => {
+            bcrypt::verify(password, hash).map_err(|_| HashError::Malformed("bcrypt"))
+        }
+        Some(HashScheme::Scrypt) => verify_scrypt(hash, password),
+        Some(HashScheme::Pbkdf2Sha256) => verify_pbkdf2_sha256(hash, password),
+        None => Err(HashError::UnknownFormat),
+    }
+}
+
+// Argon2 with the current settings takes a lot of time and memory on purpose. Async callers use
+// the `_blocking` variants, so it runs on the blocking thread pool and not on the actix workers.
+pub async fn hash_password_blocking(password: &str) -> Result<String, HashError> {
+    let password = password.to_owned();
+    web::block(move || hash_password(&password))
+        .await
+        .map_err(|_| HashError::Failed)?
+        .map_err(|_| HashError::Failed)
+}
+
+pub async fn verify_password_blocking(hash: &str, password: &str) -> Result<bool, HashError> {
+    let (hash, password) = (hash.to_owned(), password.to_owned());
+    web::block(move || verify_password(&hash, &password))
+        .await
+        .map_err(|_| HashError::Failed)?
+}
+
+// Legacy formats and argon2 hashes with older settings should be re-hashed.
+pub fn needs_rehash(hash: &str) -> bool {
+    !hash.starts_with(CURRENT_ARGON2_PREFIX)
+}
+
+// PHC string format. e.g. `$scrypt$ln=16,r=8,p=1$<salt>$<hash>`
+fn verify_scrypt(hash: &str, password: &str) -> Result<bool, HashError> {
+    let parsed = PasswordHash::new(hash).map_err(|_| HashError::Malformed("scrypt"))?;
+    match Scrypt.verify_password(password.as_bytes(), &parsed) {
+        Ok(_) => Ok(true),
+        Err(password_hash::Error::Password) => Ok(false),
+        Err(_) => Err(HashError::Malformed("scrypt")),
+    }
+}
+
+// Django `pbkdf2_sha256$<iterations>$<salt>$<hash>`, passlib `$pbkdf2-sha256$<iterations>$<salt>$<hash>`
+// and PHC `$pbkdf2-sha256$i=<iterations>,l=<length>$<salt>$<hash>` formats.
+fn verify_pbkdf2_sha256(hash: &str, password: &str) -> Result<bool, HashError> {
+    let (iterations, salt, expected) =
+        parse_pbkdf2_sha256(hash).ok_or(HashError::Malformed("pbkdf2-sha256"))?;
+    let mut derived = vec![0u8; expected.len()];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, iterations, &mut derived);
+    Ok(constant_time_eq(&derived, &expected))
+}
+
+fn parse_pbkdf2_sha256(hash: &str) -> Option<(u32, Vec<u8>, Vec<u8>)> {
+    if let Some(rest) = hash.strip_prefix("pbkdf2_sha256$") {
+        // Django uses the salt as is and a padded base64 hash.
+        let mut parts = rest.splitn(3, '$');
+        let iterations = parts.next()?.parse().ok()?;
+        let salt = parts.next()?.as_bytes().to_vec();
+        let expected = STANDARD.decode(parts.next()?).ok()?;
+        return valid_pbkdf2_params(iterations, salt, expected);
+    }
+    // Passlib and PHC use unpadded base64. Passlib replaces `+` with `.`.
+    let mut parts = hash.strip_prefix("$pbkdf2-sha256$")?.splitn(3, '$');
+    let params = parts.next()?;
+    let iterations = params
+        .split(',')
+        .find_map(|param| param.strip_prefix("i="))
+        .unwrap_or(params)
+        .parse()
+        .ok()?;
+    let decode = |value: &str| STANDARD_NO_PAD.decode(value.replace('.', "+")).ok();
+    let salt = decode(parts.next()?)?;
+    let expected = decode(parts.next()?)?;
+    valid_pbkdf2_params(iterations, salt, expected)
+}
+
+fn valid_pbkdf2_params(
+    iterations: u32,
+    salt: Vec<u8>,
+    expected: Vec<u8>,
+) -> Option<(u32, Vec<u8>, Vec<u8>)> {
+    if iterations == 0 || salt.is_empty() || expected.len() < MIN_PBKDF2_DIGEST_LENGTH {
+        return None;
+    }
+    Some((iterations, salt, expected))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse";
+    const DJANGO: &str = "pbkdf2_sha256$1000$pepper$gQV4o8bSAk9lLV90oJ74VRcTZ9w9mWIZJC4kEwODp3Y=";
+    const PASSLIB: &str =
+        "$pbkdf2-sha256$1000$MDEyMzQ1Njc4OWFiY2RlZg$cBg8D2DungRB9k76szThf5ehfyBz991ay6PT8Srwk4M";
+    const PHC: &str = "$pbkdf2-sha256$i=1000,l=32$MDEyMzQ1Njc4OWFiY2RlZg$cBg8D2DungRB9k76szThf5ehfyBz991ay6PT8Srwk4M";
+    const SCRYPT: &str =
+        "$scrypt$ln=4,r=8,p=1$MDEyMzQ1Njc4OWFiY2RlZg$OAkZqUBelG6VIYdTJfYSENL5OmZRxMa3GwXn5oFliTg";
+
+    #[test]
+    fn verifies_pbkdf2_formats() {
+        for hash in [DJANGO, PASSLIB, PHC] {
+            assert!(verify_password(hash, PASSWORD).unwrap(), "{}", hash);
+            assert!(!verify_password(hash, "wrong").unwrap(), "{}", hash);
+            assert_eq!(check_hash(hash).unwrap(), HashScheme::Pbkdf2Sha256);
+        }
+    }
+
+    #[test]
+    fn verifies_scrypt_and_bcrypt() {
+        assert!(verify_password(SCRYPT, PASSWORD).unwrap());
+        assert!(!verify_password(SCRYPT, "wrong").unwrap());
+        assert_eq!(check_hash(SCRYPT).unwrap(), HashScheme::Scrypt);
+
+        let bcrypt_hash = bcrypt::hash(PASSWORD, 4).unwrap();
+        assert!(verify_password(&bcrypt_hash, PASSWORD).unwrap());
+        assert!(!verify_password(&bcrypt_hash, "wrong").unwrap());
+        assert_eq!(check_hash(&bcrypt_hash).unwrap(), HashScheme::Bcrypt);
+    }
+
+    #[test]
+    fn verifies_argon2_and_flags_old_settings_for_rehash() {
+        let config = Config {
+            mem_cost: 1024,
+            time_cost: 1,
+            lanes: 1,
+            ..argon2_config()
+        };
+        let hash = argon2::hash_encoded(PASSWORD.as_bytes(), b"0123456789abcdef", &config).unwrap();
+        assert!(verify_password(&hash, PASSWORD).unwrap());
+        assert!(!verify_password(&hash, "wrong").unwrap());
+        assert_eq!(check_hash(&hash).unwrap(), HashScheme::Argon2);
+        assert!(needs_rehash(&hash));
+        assert!(needs_rehash(DJANGO));
+        assert!(!needs_rehash(&format!(
+            "{}salt$hash",
+            CURRENT_ARGON2_PREFIX
+        )));
+    }
+
+    #[test]
+    fn rejects_pbkdf2_with_empty_or_short_digest() {
+        for hash in [
+            "pbkdf2_sha256$1000$pepper$",
+            "pbkdf2_sha256$1000$pepper$AAAA",
+            "$pbkdf2-sha256$1000$MDEyMzQ1Njc4OWFiY2RlZg$",
+            "$pbkdf2-sha256$i=1000,l=32$MDEyMzQ1Njc4OWFiY2RlZg$AAAA",
+        ] {
+            assert!(verify_password(hash, "anything").is_err(), "{}", hash);
+            assert!(check_hash(hash).is_err(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn rejects_pbkdf2_with_empty_salt_or_no_iterations() {
+        for hash in [
+            "pbkdf2_sha256$1000$$gQV4o8bSAk9lLV90oJ74VRcTZ9w9mWIZJC4kEwODp3Y=",
+            "pbkdf2_sha256$0$pepper$gQV4o8bSAk9lLV90oJ74VRcTZ9w9mWIZJC4kEwODp3Y=",
+            "$pbkdf2-sha256$0$MDEyMzQ1Njc4OWFiY2RlZg$cBg8D2DungRB9k76szThf5ehfyBz991ay6PT8Srwk4M",
+            "$pbkdf2-sha256$i=1000,l=32$$cBg8D2DungRB9k76szThf5ehfyBz991ay6PT8Srwk4M",
+            "pbkdf2_sha256$many$pepper$gQV4o8bSAk9lLV90oJ74VRcTZ9w9mWIZJC4kEwODp3Y=",
+        ] {
+            assert!(verify_password(hash, "anything").is_err(), "{}", hash);
+            assert!(check_hash(hash).is_err(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn rejects_work_factors_above_the_limits() {
+        for hash in [
+            "pbkdf2_sha256$2000001$pepper$gQV4o8bSAk9lLV90oJ74VRcTZ9w9mWIZJC4kEwODp3Y=",
+            "$pbkdf2-sha256$i=4294967295,l=32$MDEyMzQ1Njc4OWFiY2RlZg$cBg8D2DungRB9k76szThf5ehfyBz991ay6PT8Srwk4M",
+            "$scrypt$ln=30,r=8,p=1$MDEyMzQ1Njc4OWFiY2RlZg$OAkZqUBelG6VIYdTJfYSENL5OmZRxMa3GwXn5oFliTg",
+            "$scrypt$ln=17,r=8,p=16$MDEyMzQ1Njc4OWFiY2RlZg$OAkZqUBelG6VIYdTJfYSENL5OmZRxMa3GwXn5oFliTg",
+            "$argon2id$v=19$m=4194304,t=1,p=1$MDEyMzQ1Njc4OWFiY2RlZg$OAkZqUBelG6VIYdTJfYSENL5OmZRxMa3GwXn5oFliTg",
+            "$argon2id$v=19$m=65536,t=1000,p=1$MDEyMzQ1Njc4OWFiY2RlZg$OAkZqUBelG6VIYdTJfYSENL5OmZRxMa3GwXn5oFliTg",
+        ] {
+            assert!(
+                matches!(check_hash(hash), Err(HashError::TooExpensive(_))),
+                "{}",
+                hash
+            );
+            assert!(
+                matches!(
+                    verify_password(hash, PASSWORD),
+                    Err(HashError::TooExpensive(_))
+                ),
+                "{}",
+                hash
+            );
+        }
+        let bcrypt_hash = bcrypt::hash(PASSWORD, 4)
+            .unwrap()
+            .replacen("$04$", "$31$", 1);
+        assert!(matches!(
+            check_hash(&bcrypt_hash),
+            Err(HashError::TooExpensive("bcrypt"))
+        ));
+        // The recommended scrypt and the current argon2 settings are accepted.
+        assert!(check_hash(
+            "$scrypt$ln=17,r=8,p=1$MDEyMzQ1Njc4OWFiY2RlZg$OAkZqUBelG6VIYdTJfYSENL5OmZRxMa3GwXn5oFliTg"
+        )
+        .is_ok());
+        assert!(check_hash(&format!(
+            "{}MDEyMzQ1Njc4OWFiY2RlZg$OAkZqUBelG6VIYdTJfYSENL5OmZRxMa3GwXn5oFliTg",
+            CURRENT_ARGON2_PREFIX
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn check_hash_rejects_unknown_and_truncated_hashes() {
+        assert!(matches!(
+            check_hash("md5$abc"),
+            Err(HashError::UnknownFormat)
+        ));
+        assert!(matches!(
+            check_hash("$2b$12$tooshort"),
+            Err(HashError::Malformed("bcrypt"))
+        ));
+        assert!(matches!(
+            check_hash("$scrypt$ln=4,r=8,p=1$MDEyMzQ1Njc4OWFiY2RlZg"),
+            Err(HashError::Malformed("scrypt"))
+        ));
+        assert!(matches!(
+            check_hash("$argon2id$v=19$m=1024,t=1,p=1"),
+            Err(HashError::Malformed("argon2"))
+        ));
+    }
+}
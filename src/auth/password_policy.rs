@@ -0,0 +1,251 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use actix_web::web;
+use log::error;
+use sha1::{Digest, Sha1};
+use zxcvbn::zxcvbn;
+
+use crate::auth::password;
+use crate::models::error_model::ApiErrorType;
+
+// Single check of the password policy.
+#[derive(Debug, Clone)]
+pub enum PasswordRule {
+    // zxcvbn strength score from 0 (too guessable) to 4 (very unguessable).
+    Strength { min_score: u8 },
+    // Password must not contain the email or the name of the user.
+    PersonalInfo,
+    // Password must not be in the local breached password list.
+    // The directory holds k-anonymity range files named by the first 5 hex characters of the
+    // SHA-1 hash (e.g. `21BD1.txt`) with `SUFFIX:COUNT` lines, as served by Have I Been Pwned.
+    Breached { dir: PathBuf },
+    // Password must not match the current or the recently used passwords.
+    History,
+}
+
+// User details the password is checked against.
+pub struct PasswordContext {
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    // Current and previous password hashes, newest first.
+    pub previous_hashes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub rules: Vec<PasswordRule>,
+    // Number of previous password hashes kept on auth for the history rule.
+    pub history_size: usize,
+}
+
+impl PasswordPolicy {
+    // Run every rule and report all violations together.
+    pub fn check(
+        &self,
+        object: &str,
+        field: &str,
+        password: &str,
+        context: &PasswordContext,
+    ) -> Result<(), ApiErrorType> {
+        let violations: Vec<String> = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.check(password, context))
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiErrorType::PasswordPolicyError {
+                object: object.to_owned(),
+                field: field.to_owned(),
+                violations,
+            })
+        }
+    }
+
+    // `check` on the blocking thread pool. The history rule runs argon2 for every previous hash
+    // and the breached rule reads files.
+    pub async fn check_blocking(
+        &self,
+        object: &str,
+        field: &str,
+        password: &str,
+        context: PasswordContext,
+    ) -> Result<(), ApiErrorType> {
+        let policy = self.clone();
+        let (object, field, password) = (object.to_owned(), field.to_owned(), password.to_owned());
+        web::block(move || policy.check(&object, &field, &password, &context))
+            .await
+            .map_err(|err| {
+                error!("Error running password policy: {}", err);
+                ApiErrorType::InternalServerError
+            })?
+    }
+}
+
+impl PasswordRule {
+    // Returns the violation message when the password breaks the rule.
+    fn check(&self, password: &str, context: &PasswordContext) -> Option<String> {
+        match self {
+            PasswordRule::Strength { min_score } => {
+                let entropy = zxcvbn(
+                    password,
+                    &[&context.email, &context.first_name, &context.last_name],
+                );
+                if u8::from(entropy.score()) >= *min_score {
+                    return None;
+                }
+                Some(match entropy.feedback().and_then(|f| f.warning()) {
+                    Some(warning) => format!("password is too weak. {}", warning),
+                    None => "password is too weak".to_owned(),
+                })
+            }
+            PasswordRule::PersonalInfo => {
+                let password = password.to_lowercase();
+                let email_name = context.email.split('@').next().unwrap_or_default();
+                [email_name, &context.first_name, &context.last_name]
+                    .iter()
+                    .map(|value| value.to_lowercase())
+                    .any(|value| value.chars().count() >= 3 && password.contains(&value))
+                    .then(|| "password must not contain your email or name".to_owned())
+            }
+            PasswordRule::Breached { dir } => is_breached(dir, password)
+                .then(|| "password has appeared in a data breach".to_owned()),
+            PasswordRule::History => context
+                .previous_hashes
+                .iter()
+                .any(|hash| password::verify_password(hash, password).unwrap_or(false))
+                .then(|| "password was used recently".to_owned()),
+        }
+    }
+}
+
+// Only the range file of the hash prefix is read. A missing file means no breached password
+// shares the prefix.
+fn is_breached(dir: &Path, password: &str) -> bool {
+    let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(5);
+    let path = dir.join(format!("{}.txt", prefix));
+    match fs::read_to_string(&path) {
+        Ok(content) => content
+            .lines()
+            .filter_map(|line| line.split(':').next())
+            .any(|hash_suffix| hash_suffix.trim().eq_ignore_ascii_case(suffix)),
+        Err(err) if err.kind() == ErrorKind::NotFound => false,
+        Err(err) => {
+            error!(
+                "Error reading breached password file {}: {}",
+                path.display(),
+                err
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // PBKDF2 hash of `correct horse`. Cheap to verify, unlike argon2.
+    const PREVIOUS_HASH: &str =
+        "pbkdf2_sha256$1000$pepper$gQV4o8bSAk9lLV90oJ74VRcTZ9w9mWIZJC4kEwODp3Y=";
+
+    fn context() -> PasswordContext {
+        PasswordContext {
+            email: "johnny@c12.io".to_owned(),
+            first_name: "John".to_owned(),
+            last_name: "Doe".to_owned(),
+            previous_hashes: vec![PREVIOUS_HASH.to_owned()],
+        }
+    }
+
+    fn violation(rule: PasswordRule, password: &str) -> Option<String> {
+        rule.check(password, &context())
+    }
+
+    #[test]
+    fn strength_rule_rejects_guessable_passwords() {
+        let rule = PasswordRule::Strength { min_score: 3 };
+        assert!(violation(rule.clone(), "password1234")
+            .unwrap()
+            .starts_with("password is too weak"));
+        assert!(violation(rule, "Tq7#vW9!pLm2@xZr").is_none());
+    }
+
+    #[test]
+    fn personal_info_rule_rejects_email_and_names() {
+        for password in ["xx-JOHNNY-xx-1234", "my name is doe!!", "john smith rocks"] {
+            assert_eq!(
+                violation(PasswordRule::PersonalInfo, password).as_deref(),
+                Some("password must not contain your email or name"),
+                "{}",
+                password
+            );
+        }
+        assert!(violation(PasswordRule::PersonalInfo, "Tq7#vW9!pLm2@xZr").is_none());
+    }
+
+    #[test]
+    fn breached_rule_reads_hash_prefix_file() {
+        let dir = std::env::temp_dir().join(format!("breached-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // SHA-1 of `password1234` is E6B6AFBD6D76BB5D2041542D7D2E3FAC5BB05593.
+        fs::write(
+            dir.join("E6B6A.txt"),
+            "0000000000000000000000000000000000A:1\r\nfbd6d76bb5d2041542d7d2e3fac5bb05593:42\r\n",
+        )
+        .unwrap();
+        let rule = PasswordRule::Breached { dir: dir.clone() };
+        assert_eq!(
+            violation(rule.clone(), "password1234").as_deref(),
+            Some("password has appeared in a data breach")
+        );
+        // No range file for the prefix.
+        assert!(violation(rule, "Tq7#vW9!pLm2@xZr").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn history_rule_rejects_previous_passwords() {
+        assert_eq!(
+            violation(PasswordRule::History, "correct horse").as_deref(),
+            Some("password was used recently")
+        );
+        assert!(violation(PasswordRule::History, "battery staple").is_none());
+    }
+
+    #[actix_web::test]
+    async fn policy_reports_every_violation() {
+        let policy = PasswordPolicy {
+            rules: vec![
+                PasswordRule::Strength { min_score: 3 },
+                PasswordRule::PersonalInfo,
+                PasswordRule::History,
+            ],
+            history_size: 5,
+        };
+        let result = policy
+            .check_blocking("UpdatePasswordRequest", "new_password", "john", context())
+            .await;
+        match result {
+            Err(ApiErrorType::PasswordPolicyError {
+                object,
+                field,
+                violations,
+            }) => {
+                assert_eq!(object, "UpdatePasswordRequest");
+                assert_eq!(field, "new_password");
+                assert_eq!(violations.len(), 2);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(policy
+            .check_blocking("RegisterRequest", "password", "Tq7#vW9!pLm2@xZr", context())
+            .await
+            .is_ok());
+    }
+}
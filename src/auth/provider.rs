@@ -0,0 +1,188 @@
+use actix_web::web::Data;
+use chrono::Utc;
+use log::{error, info, warn};
+use mongodb::Client;
+use nanoid::nanoid;
+
+use crate::api::auth_api::LoginRequest;
+use crate::auth::ldap::{self, LdapConfig};
+use crate::auth::password;
+use crate::models::auth_model::{Auth, AuthSource};
+use crate::models::error_model::ApiErrorType;
+use crate::repository::{account_repo, auth_repo};
+use crate::services::auth_service;
+
+// Backend that can verify login credentials.
+#[derive(Debug, Clone)]
+pub enum AuthProvider {
+    // Argon2 password hash stored in the MongoDB auth collection.
+    Local,
+    // Simple bind against an LDAP directory.
+    Ldap(LdapConfig),
+}
+
+// Configured providers, tried in order on login.
+#[derive(Debug, Clone)]
+pub struct AuthProviders(pub Vec<AuthProvider>);
+
+impl AuthProviders {
+    // Authenticate with the first provider that owns the account.
+    pub async fn authenticate(
+        &self,
+        client: &Data<Client>,
+        login_request: &LoginRequest,
+    ) -> Result<Auth, ApiErrorType> {
+        for provider in &self.0 {
+            let auth = provider
+                .authenticate(client, &login_request.email, &login_request.password)
+                .await?;
+            if let Some(a) = auth {
+                return Ok(a);
+            }
+        }
+        warn!("User not found for email {}", login_request.email);
+        Err(ApiErrorType::InvalidCredential)
+    }
+}
+
+impl AuthProvider {
+    // Returns `None` when the provider does not own the account, so the next provider is tried.
+    async fn authenticate(
+        &self,
+        client: &Data<Client>,
+        email: &String,
+        password: &str,
+    ) -> Result<Option<Auth>, ApiErrorType> {
+        match self {
+            AuthProvider::Local => local_authenticate(client, email, password).await,
+            AuthProvider::Ldap(config) => ldap_authenticate(client, config, email, password).await,
+        }
+    }
+}
+
+async fn local_authenticate(
+    client: &Data<Client>,
+    email: &String,
+    password: &str,
+) -> Result<Option<Auth>, ApiErrorType> {
+    match auth_repo::fetch_by_email(client, email).await {
+        Some(a) if a.provider == AuthSource::Local => {
+            match password::verify_password_blocking(&a.password_hash, password).await {
+                Ok(true) => {
+                    upgrade_password_hash(client, &a, password).await;
+                    Ok(Some(a))
+                }
+                Ok(false) => Err(ApiErrorType::InvalidCredential),
+                Err(err) => {
+                    error!("Error verifying password hash for {}: {}", email, err);
+                    Err(ApiErrorType::InternalServerError)
+                }
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+// Re-hash legacy or outdated hashes with the current argon2 settings once the password is known.
+// A failure here is logged only. The login itself already succeeded.
+async fn upgrade_password_hash(client: &Data<Client>, auth: &Auth, password: &str) {
+    if !password::needs_rehash(&auth.password_hash) {
+        return;
+    }
+    match password::hash_password_blocking(password).await {
+        Ok(hash) => match auth_repo::update_password_hash(client, &auth.id, &hash).await {
+            Ok(_) => info!("Upgraded password hash for {}", auth.email),
+            Err(err) => error!("Error upgrading password hash for {}: {}", auth.email, err),
+        },
+        Err(err) => error!("Error hashing password for {}: {}", auth.email, err),
+    }
+}
+
+async fn ldap_authenticate(
+    client: &Data<Client>,
+    config: &LdapConfig,
+    email: &String,
+    password: &str,
+) -> Result<Option<Auth>, ApiErrorType> {
+    let existing = auth_repo::fetch_by_email(client, email).await;
+    // Never let the directory take over an account with credentials owned by another provider.
+    if matches!(&existing, Some(a) if a.provider != AuthSource::Ldap) {
+        return Ok(None);
+    }
+
+    let ldap_user = match ldap::authenticate(config, email, password).await {
+        Ok(Some(u)) => u,
+        Ok(None) => return Ok(None),
+        Err(err) => {
+            error!("LDAP authentication error for {}: {}", email, err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+
+    let current_time = Utc::now();
+    match existing {
+        // Keep the shadow record in sync with the directory on every login.
+        Some(mut a) => {
+            a.first_name = ldap_user.first_name;
+            a.last_name = ldap_user.last_name;
+            a.roles = ldap_user.roles;
+            a.updated_ts = current_time;
+            match auth_repo::update_profile(client, &a).await {
+                Ok(_) => Ok(Some(a)),
+                Err(err) => {
+                    error!("Error: {}", err);
+                    Err(ApiErrorType::InternalServerError)
+                }
+            }
+        }
+        // First login. Create a shadow auth record without a password hash.
+        None => {
+            let mut shadow = Auth {
+                id: nanoid!(),
+                email: email.to_owned(),
+                first_name: ldap_user.first_name,
+                last_name: ldap_user.last_name,
+                password_hash: String::new(),
+                roles: ldap_user.roles,
+                active: true,
+                reset_password: false,
+                password_history: vec![],
+                provider: AuthSource::Ldap,
+                user_id: None,
+                created_ts: current_time,
+                updated_ts: current_time,
+            };
+            let profile = auth_service::linked_profile(&mut shadow);
+            match account_repo::register_with_profile(client, &shadow, &profile).await {
+                Ok(_) => Ok(Some(shadow)),
+                Err(err) => {
+                    error!("Error: {}", err);
+                    Err(ApiErrorType::InternalServerError)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use actix_web::FromRequest;
+
+    use super::*;
+    use crate::handler::validated::{self, ValidatedJson};
+
+    // The directory owns the password policy. A short directory password must reach the
+    // LDAP provider on both the REST and the gRPC login.
+    #[actix_web::test]
+    async fn short_directory_passwords_reach_the_providers() {
+        let (req, mut payload) = TestRequest::post()
+            .set_json(serde_json::json!({"email": "admin@c12.io", "password": "admin"}))
+            .to_http_parts();
+        let login = ValidatedJson::<LoginRequest>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(login.password, "admin");
+        assert!(validated::validate(&login.into_inner()).is_ok());
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+use crate::models::error_model::ApiErrorType;
+
+// Who can register with `/a/register`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    // Anyone can register. An invite code is optional.
+    Open,
+    // Only users with a valid invite code can register.
+    InviteOnly,
+    // Self registration is turned off.
+    Closed,
+}
+
+// Rules applied to every registration request.
+#[derive(Debug, Clone)]
+pub struct RegistrationPolicy {
+    pub mode: RegistrationMode,
+    // When not empty, only these email domains can register.
+    pub allowed_domains: HashSet<String>,
+    pub denied_domains: HashSet<String>,
+    pub disposable_domains: HashSet<String>,
+}
+
+impl RegistrationPolicy {
+    // Check registration mode and email domain rules.
+    pub fn check(&self, email: &str, has_invite_code: bool) -> Result<(), ApiErrorType> {
+        match self.mode {
+            RegistrationMode::Closed => return Err(ApiErrorType::RegistrationDisabled),
+            RegistrationMode::InviteOnly if !has_invite_code => {
+                return Err(ApiErrorType::InvitationRequired)
+            }
+            _ => {}
+        }
+
+        let domain = email
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+            .to_lowercase();
+        if matches_domain(&self.denied_domains, &domain)
+            || (!self.allowed_domains.is_empty() && !matches_domain(&self.allowed_domains, &domain))
+        {
+            return Err(ApiErrorType::EmailDomainNotAllowed { domain });
+        }
+        if matches_domain(&self.disposable_domains, &domain) {
+            return Err(ApiErrorType::DisposableEmail { domain });
+        }
+        Ok(())
+    }
+}
+
+// A domain matches the list when it or any of its parent domains is listed.
+// e.g. `mail.c12.io` matches `c12.io`.
+fn matches_domain(domains: &HashSet<String>, domain: &str) -> bool {
+    let mut candidate = domain;
+    loop {
+        if domains.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => candidate = parent,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domains(list: &[&str]) -> HashSet<String> {
+        list.iter().map(|domain| domain.to_string()).collect()
+    }
+
+    fn policy(mode: RegistrationMode) -> RegistrationPolicy {
+        RegistrationPolicy {
+            mode,
+            allowed_domains: HashSet::new(),
+            denied_domains: domains(&["blocked.io"]),
+            disposable_domains: domains(&["mailinator.com"]),
+        }
+    }
+
+    #[test]
+    fn open_mode_accepts_with_and_without_invite() {
+        let policy = policy(RegistrationMode::Open);
+        assert!(policy.check("john@c12.io", false).is_ok());
+        assert!(policy.check("john@c12.io", true).is_ok());
+    }
+
+    #[test]
+    fn invite_only_mode_needs_invite_code() {
+        let policy = policy(RegistrationMode::InviteOnly);
+        assert!(matches!(
+            policy.check("john@c12.io", false),
+            Err(ApiErrorType::InvitationRequired)
+        ));
+        assert!(policy.check("john@c12.io", true).is_ok());
+    }
+
+    #[test]
+    fn closed_mode_rejects_everyone() {
+        let policy = policy(RegistrationMode::Closed);
+        assert!(matches!(
+            policy.check("john@c12.io", true),
+            Err(ApiErrorType::RegistrationDisabled)
+        ));
+    }
+
+    #[test]
+    fn denied_and_disposable_domains_match_subdomains() {
+        let policy = policy(RegistrationMode::Open);
+        assert!(matches!(
+            policy.check("john@mail.Blocked.io", false),
+            Err(ApiErrorType::EmailDomainNotAllowed { domain }) if domain == "mail.blocked.io"
+        ));
+        assert!(matches!(
+            policy.check("john@mailinator.com", false),
+            Err(ApiErrorType::DisposableEmail { .. })
+        ));
+        assert!(policy.check("john@notblocked.io", false).is_ok());
+    }
+
+    #[test]
+    fn allowed_domains_limit_registration() {
+        let mut policy = policy(RegistrationMode::Open);
+        policy.allowed_domains = domains(&["c12.io"]);
+        assert!(policy.check("john@c12.io", false).is_ok());
+        assert!(policy.check("john@eu.c12.io", false).is_ok());
+        assert!(matches!(
+            policy.check("john@gmail.com", false),
+            Err(ApiErrorType::EmailDomainNotAllowed { .. })
+        ));
+        assert!(matches!(
+            policy.check("no-domain", false),
+            Err(ApiErrorType::EmailDomainNotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn top_level_domain_alone_does_not_match() {
+        assert!(!matches_domain(&domains(&["io"]), "c12.io"));
+        assert!(matches_domain(&domains(&["c12.io"]), "a.b.c12.io"));
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::env;
+
+use log::{error, info, warn};
+
+use crate::auth::ldap::LdapConfig;
+use crate::auth::provider::{AuthProvider, AuthProviders};
+
+const DEFAULT_LDAP_GROUP_FILTER: &str = "(|(member={dn})(uniqueMember={dn}))";
+
+// Authentication providers initialize function.
+// Providers are tried in the order given in `AUTH.PROVIDERS` (comma separated, default `local`).
+pub fn init() -> AuthProviders {
+    let names = env::var("AUTH.PROVIDERS").unwrap_or_else(|_| "local".to_owned());
+    let mut providers = vec![];
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        match name {
+            "local" => providers.push(AuthProvider::Local),
+            "ldap" => match ldap_config() {
+                Some(config) => providers.push(AuthProvider::Ldap(config)),
+                None => error!("LDAP provider enabled but LDAP.URL, LDAP.BIND_DN_TEMPLATE or LDAP.SEARCH_BASE is missing"),
+            },
+            other => warn!("Unknown authentication provider '{}' ignored", other),
+        }
+    }
+    if providers.is_empty() {
+        warn!("No authentication provider configured. Falling back to local");
+        providers.push(AuthProvider::Local);
+    }
+    info!("Authentication providers: {:?}", providers);
+    AuthProviders(providers)
+}
+
+// LDAP settings from environment file.
+// `LDAP.ROLE_MAPPING` is a `;` separated list of `group_dn:ROLE` pairs.
+fn ldap_config() -> Option<LdapConfig> {
+    let role_mapping = env::var("LDAP.ROLE_MAPPING")
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|pair| pair.rsplit_once(':'))
+        .map(|(group, role)| (group.trim().to_lowercase(), role.trim().to_owned()))
+        .collect::<HashMap<String, String>>();
+    let default_roles = env::var("LDAP.DEFAULT_ROLES")
+        .unwrap_or_else(|_| "ROLE_USER".to_owned())
+        .split(',')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    Some(LdapConfig {
+        url: env::var("LDAP.URL").ok()?,
+        bind_dn_template: env::var("LDAP.BIND_DN_TEMPLATE").ok()?,
+        search_base: env::var("LDAP.SEARCH_BASE").ok()?,
+        group_filter: env::var("LDAP.GROUP_FILTER")
+            .unwrap_or_else(|_| DEFAULT_LDAP_GROUP_FILTER.to_owned()),
+        role_mapping,
+        default_roles,
+    })
+}
@@ -0,0 +1,38 @@
+use std::env;
+
+use log::info;
+
+const DEFAULT_MAX_DEPTH: usize = 8;
+const DEFAULT_MAX_COMPLEXITY: usize = 500;
+
+// GraphQL endpoint settings.
+#[derive(Debug, Clone)]
+pub struct GraphqlConfig {
+    // Deepest nesting of fields accepted in a query.
+    pub max_depth: usize,
+    // Highest complexity accepted for a query. Every field counts 1, lists count once per item.
+    pub max_complexity: usize,
+    // Serve the GraphiQL page on `/graphiql` and answer introspection queries. Meant for
+    // development.
+    pub graphiql: bool,
+}
+
+// GraphQL initialize function.
+// Get query limits from environment file.
+pub fn init() -> GraphqlConfig {
+    let config = GraphqlConfig {
+        max_depth: env::var("GRAPHQL.MAX_DEPTH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_DEPTH),
+        max_complexity: env::var("GRAPHQL.MAX_COMPLEXITY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_COMPLEXITY),
+        graphiql: env::var("GRAPHQL.GRAPHIQL")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+    };
+    info!("GraphQL config: {:?}", config);
+    config
+}
@@ -0,0 +1,32 @@
+use std::env;
+
+use log::info;
+
+const DEFAULT_PORT: u16 = 50051;
+
+// gRPC server settings. The server runs next to the HTTP server, on its own port.
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    pub host: String,
+    pub port: u16,
+    // Serve the gRPC reflection service, so clients like `grpcurl` can list the services.
+    // Off unless enabled, it exposes the whole API surface.
+    pub reflection: bool,
+}
+
+// gRPC initialize function.
+// Get host, port and reflection from environment file.
+pub fn init() -> GrpcConfig {
+    let config = GrpcConfig {
+        host: env::var("GRPC.HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
+        port: env::var("GRPC.PORT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_PORT),
+        reflection: env::var("GRPC.REFLECTION")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+    };
+    info!("gRPC config: {:?}", config);
+    config
+}
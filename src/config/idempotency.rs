@@ -0,0 +1,68 @@
+use std::env;
+
+use log::{info, warn};
+
+const DEFAULT_TTL_HOURS: i64 = 24;
+const DEFAULT_LOCK_SECS: i64 = 60;
+// Upper bounds, well inside what `chrono::Duration` can hold.
+const MAX_TTL_HOURS: i64 = 24 * 365;
+const MAX_LOCK_SECS: i64 = 24 * 60 * 60;
+
+// `Idempotency-Key` settings.
+#[derive(Debug, Clone)]
+pub struct IdempotencyConfig {
+    // Hours a key and its response are kept for replay.
+    pub ttl_hours: i64,
+    // Seconds a request holds its key before a retry may take it over.
+    pub lock_secs: i64,
+}
+
+// Idempotency initialize function.
+// Get key retention settings from environment file.
+pub fn init() -> IdempotencyConfig {
+    let config = IdempotencyConfig {
+        ttl_hours: bounded(
+            "IDEMPOTENCY.TTL_HOURS",
+            env::var("IDEMPOTENCY.TTL_HOURS").ok(),
+            DEFAULT_TTL_HOURS,
+            MAX_TTL_HOURS,
+        ),
+        lock_secs: bounded(
+            "IDEMPOTENCY.LOCK_SECS",
+            env::var("IDEMPOTENCY.LOCK_SECS").ok(),
+            DEFAULT_LOCK_SECS,
+            MAX_LOCK_SECS,
+        ),
+    };
+    info!("Idempotency config: {:?}", config);
+    config
+}
+
+// A positive number, at most `max`. Anything else falls back to the default.
+fn bounded(name: &str, value: Option<String>, default: i64, max: i64) -> i64 {
+    match value.and_then(|v| v.parse::<i64>().ok()) {
+        Some(value) if value > max => {
+            warn!("{} is limited to {}", name, max);
+            max
+        }
+        Some(value) if value > 0 => value,
+        _ => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_positive_and_bounded() {
+        let parse = |value: &str| bounded("TEST", Some(value.to_owned()), 24, 100);
+        assert_eq!(parse("12"), 12);
+        assert_eq!(parse("100"), 100);
+        assert_eq!(parse("9223372036854775807"), 100);
+        assert_eq!(parse("0"), 24);
+        assert_eq!(parse("-5"), 24);
+        assert_eq!(parse("a day"), 24);
+        assert_eq!(bounded("TEST", None, 24, 100), 24);
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+use log::{error, info};
+use mongodb::bson::{doc, Document};
+use mongodb::options::IndexOptions;
+use mongodb::{Client, IndexModel};
+
+use crate::constants;
+use crate::repository::index_repo;
+
+// Index management initialize function.
+// Create the indexes declared for each collection. A failed index is logged and skipped.
+pub async fn init(client: &Data<Client>) {
+    for (collection, index) in declared_indexes() {
+        match index_repo::create_index(client, collection, index).await {
+            Ok(result) => info!("Index {} on {} is ready", result.index_name, collection),
+            Err(err) => error!("Error creating index on {}: {}", collection, err),
+        }
+    }
+}
+
+fn declared_indexes() -> Vec<(&'static str, IndexModel)> {
+    vec![
+        // Text search over users. Names weigh more than titles and locations.
+        (
+            constants::MONGO_USER_COLLECTION,
+            IndexModel::builder()
+                .keys(doc! {"name": "text", "title": "text", "location": "text"})
+                .options(
+                    IndexOptions::builder()
+                        .name(constants::USER_SEARCH_INDEX.to_owned())
+                        .weights(doc! {"name": 3, "title": 2, "location": 1})
+                        .build(),
+                )
+                .build(),
+        ),
+        // User list sort orders, with `_id` as the keyset tie breaker.
+        (
+            constants::MONGO_USER_COLLECTION,
+            index(doc! {"name": 1, "_id": 1}),
+        ),
+        (
+            constants::MONGO_USER_COLLECTION,
+            index(doc! {"location": 1, "_id": 1}),
+        ),
+        (
+            constants::MONGO_USER_COLLECTION,
+            index(doc! {"title": 1, "_id": 1}),
+        ),
+        // Profile of an auth account, used by account deletion and the link repair lookups.
+        (constants::MONGO_USER_COLLECTION, index(doc! {"auth_id": 1})),
+        // Deleted user list and purge.
+        (
+            constants::MONGO_USER_COLLECTION,
+            index(doc! {"deleted_at": 1}),
+        ),
+        // Revisions of a user, newest first. One document per version.
+        (
+            constants::MONGO_USER_HISTORY_COLLECTION,
+            IndexModel::builder()
+                .keys(doc! {"user_id": 1, "rev": -1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        ),
+        // Avatar files of a user, newest first.
+        (
+            constants::MONGO_AVATAR_FILES_COLLECTION,
+            index(doc! {"metadata.user_id": 1, "metadata.variant": 1, "uploadDate": -1}),
+        ),
+        // Login and registration look accounts up by email.
+        (
+            constants::MONGO_AUTH_COLLECTION,
+            IndexModel::builder()
+                .keys(doc! {"email": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+        ),
+        // Pending invitations.
+        (
+            constants::MONGO_INVITATION_COLLECTION,
+            index(doc! {"used_by": 1, "expires_ts": 1}),
+        ),
+        // Active grants on login and the expiry sweep.
+        (
+            constants::MONGO_ROLE_GRANT_COLLECTION,
+            index(doc! {"auth_id": 1, "status": 1, "expires_ts": 1}),
+        ),
+        (
+            constants::MONGO_ROLE_GRANT_COLLECTION,
+            index(doc! {"status": 1, "expires_ts": 1}),
+        ),
+        // Password reset tokens are removed by Mongo once `expires_ts` has passed.
+        (
+            constants::MONGO_PASSWORD_RESET_COLLECTION,
+            IndexModel::builder()
+                .keys(doc! {"expires_ts": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(Duration::from_secs(0))
+                        .build(),
+                )
+                .build(),
+        ),
+        (
+            constants::MONGO_PASSWORD_RESET_COLLECTION,
+            index(doc! {"auth_id": 1}),
+        ),
+        // Idempotency keys are removed by Mongo once `expires_at` has passed.
+        (
+            constants::MONGO_IDEMPOTENCY_COLLECTION,
+            IndexModel::builder()
+                .keys(doc! {"expires_at": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(Duration::from_secs(0))
+                        .build(),
+                )
+                .build(),
+        ),
+    ]
+}
+
+fn index(keys: Document) -> IndexModel {
+    IndexModel::builder().keys(keys).build()
+}
@@ -1 +1,11 @@
+pub mod auth;
 pub mod db;
+pub mod graphql;
+pub mod grpc;
+pub mod idempotency;
+pub mod indexes;
+pub mod openapi;
+pub mod password;
+pub mod registration;
+pub mod role_grant;
+pub mod user;
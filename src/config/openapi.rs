@@ -0,0 +1,33 @@
+use std::env;
+
+use log::{info, warn};
+
+// OpenAPI document settings. `/openapi.json` is always served.
+#[derive(Debug, Clone)]
+pub struct OpenApiConfig {
+    // Serve the Redoc page on `/docs`. Off unless enabled, and only with the script integrity.
+    pub docs: bool,
+    // Subresource integrity of the pinned Redoc script, e.g. `sha384-...`.
+    pub redoc_integrity: Option<String>,
+}
+
+// OpenAPI initialize function.
+// Get docs page switch and Redoc script integrity from environment file.
+pub fn init() -> OpenApiConfig {
+    let redoc_integrity = env::var("OPENAPI.REDOC_INTEGRITY")
+        .ok()
+        .filter(|v| !v.trim().is_empty());
+    let mut docs = env::var("OPENAPI.DOCS")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if docs && redoc_integrity.is_none() {
+        warn!("OPENAPI.DOCS needs OPENAPI.REDOC_INTEGRITY, /docs is not served");
+        docs = false;
+    }
+    let config = OpenApiConfig {
+        docs,
+        redoc_integrity,
+    };
+    info!("OpenAPI config: {:?}", config);
+    config
+}
@@ -0,0 +1,46 @@
+use std::env;
+use std::path::PathBuf;
+
+use log::{info, warn};
+
+use crate::auth::password_policy::{PasswordPolicy, PasswordRule};
+
+const DEFAULT_PASSWORD_RULES: &str = "strength,personal_info,breached,history";
+const DEFAULT_MIN_SCORE: u8 = 3;
+const DEFAULT_HISTORY_SIZE: usize = 5;
+
+// Password policy initialize function.
+// Rules are enabled with `PASSWORD.RULES` (comma separated).
+pub fn init() -> PasswordPolicy {
+    let names = env::var("PASSWORD.RULES").unwrap_or_else(|_| DEFAULT_PASSWORD_RULES.to_owned());
+    let mut rules = vec![];
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        match name {
+            "strength" => rules.push(PasswordRule::Strength {
+                min_score: env::var("PASSWORD.MIN_SCORE")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_MIN_SCORE),
+            }),
+            "personal_info" => rules.push(PasswordRule::PersonalInfo),
+            "breached" => match env::var("PASSWORD.BREACHED_DIR") {
+                Ok(dir) => rules.push(PasswordRule::Breached {
+                    dir: PathBuf::from(dir),
+                }),
+                Err(_) => warn!("Breached password rule skipped. PASSWORD.BREACHED_DIR is not set"),
+            },
+            "history" => rules.push(PasswordRule::History),
+            other => warn!("Unknown password rule '{}' ignored", other),
+        }
+    }
+    let history_size = env::var("PASSWORD.HISTORY_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_SIZE);
+
+    info!("Password rules: {:?}", rules);
+    PasswordPolicy {
+        rules,
+        history_size,
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use std::{env, fs};
+
+use log::{info, warn};
+
+use crate::auth::registration::{RegistrationMode, RegistrationPolicy};
+
+const DEFAULT_DISPOSABLE_DOMAINS_FILE: &str = "disposable_domains.txt";
+
+// Registration policy initialize function.
+// Get registration mode and email domain rules from environment file.
+pub fn init() -> RegistrationPolicy {
+    let mode = match env::var("REGISTRATION.MODE")
+        .unwrap_or_else(|_| "open".to_owned())
+        .to_lowercase()
+        .as_str()
+    {
+        "open" => RegistrationMode::Open,
+        "invite" => RegistrationMode::InviteOnly,
+        "closed" => RegistrationMode::Closed,
+        other => {
+            warn!(
+                "Unknown registration mode '{}'. Registration is closed",
+                other
+            );
+            RegistrationMode::Closed
+        }
+    };
+
+    let disposable_file = env::var("REGISTRATION.DISPOSABLE_DOMAINS_FILE")
+        .unwrap_or_else(|_| DEFAULT_DISPOSABLE_DOMAINS_FILE.to_owned());
+    let disposable_domains = match fs::read_to_string(&disposable_file) {
+        Ok(content) => parse_domains(content.lines()),
+        Err(err) => {
+            warn!(
+                "Error loading disposable email domains from {}: {}",
+                disposable_file, err
+            );
+            HashSet::new()
+        }
+    };
+
+    let policy = RegistrationPolicy {
+        mode,
+        allowed_domains: domains_from_env("REGISTRATION.ALLOWED_DOMAINS"),
+        denied_domains: domains_from_env("REGISTRATION.DENIED_DOMAINS"),
+        disposable_domains,
+    };
+    info!(
+        "Registration mode {:?} with {} disposable email domains",
+        policy.mode,
+        policy.disposable_domains.len()
+    );
+    policy
+}
+
+// Comma separated list of domains.
+fn domains_from_env(key: &str) -> HashSet<String> {
+    parse_domains(env::var(key).unwrap_or_default().split(','))
+}
+
+// Ignore blank lines and `#` comments.
+fn parse_domains<'a>(lines: impl Iterator<Item = &'a str>) -> HashSet<String> {
+    lines
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_domains_skips_comments_and_blank_lines() {
+        let domains = parse_domains("# disposable\n Mailinator.com \n\nyopmail.com\n".lines());
+        assert_eq!(
+            domains,
+            HashSet::from(["mailinator.com".to_owned(), "yopmail.com".to_owned()])
+        );
+    }
+}
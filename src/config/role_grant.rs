@@ -0,0 +1,49 @@
+use std::env;
+
+use log::info;
+
+const DEFAULT_GRANTABLE_ROLES: &str = "ROLE_USER";
+
+// Temporary role grant settings.
+#[derive(Debug, Clone)]
+pub struct RoleGrantConfig {
+    // Roles that can be requested with `/api/role-grants`.
+    pub grantable_roles: Vec<String>,
+}
+
+// Role grant initialize function.
+// Get grantable roles from `ROLE_GRANT.GRANTABLE_ROLES` (comma separated).
+pub fn init() -> RoleGrantConfig {
+    let roles = env::var("ROLE_GRANT.GRANTABLE_ROLES")
+        .unwrap_or_else(|_| DEFAULT_GRANTABLE_ROLES.to_owned());
+    let config = RoleGrantConfig {
+        grantable_roles: grantable_roles(&roles),
+    };
+    info!("Role grant config: {:?}", config);
+    config
+}
+
+fn grantable_roles(roles: &str) -> Vec<String> {
+    let mut grantable: Vec<String> = vec![];
+    for role in roles.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        if !grantable.iter().any(|r| r == role) {
+            grantable.push(role.to_owned());
+        }
+    }
+    grantable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grantable_roles_drop_blanks_and_duplicates() {
+        assert_eq!(
+            grantable_roles(" ROLE_AUDITOR,ROLE_ADMIN,,ROLE_SUPPORT,ROLE_AUDITOR "),
+            vec!["ROLE_AUDITOR", "ROLE_ADMIN", "ROLE_SUPPORT"]
+        );
+        assert_eq!(grantable_roles("ROLE_ADMIN"), vec!["ROLE_ADMIN"]);
+        assert!(grantable_roles(" , ").is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use std::env;
+
+use log::{info, warn};
+
+// Clients may keep responses but must revalidate them with the ETag before use.
+const DEFAULT_CACHE_CONTROL: &str = "private, no-cache";
+const DEFAULT_BULK_MAX_OPERATIONS: usize = 1000;
+const DEFAULT_DELETED_RETENTION_DAYS: i64 = 30;
+// Upper bound, well inside what `chrono::Duration` can hold.
+const MAX_DELETED_RETENTION_DAYS: i64 = 10 * 365;
+const DEFAULT_IMPORT_MAX_BYTES: usize = 10 * 1024 * 1024;
+const DEFAULT_AVATAR_MAX_BYTES: usize = 2 * 1024 * 1024;
+const DEFAULT_EVENTS_MAX_SUBSCRIBERS: usize = 100;
+const DEFAULT_EVENTS_HEARTBEAT_SECS: u64 = 15;
+
+// User API settings.
+#[derive(Debug, Clone)]
+pub struct UserConfig {
+    // Reject PUT, PATCH and DELETE without an `If-Match` header.
+    pub require_if_match: bool,
+    // `Cache-Control` of `GET /api/users/{id}`.
+    pub user_cache_control: String,
+    // `Cache-Control` of `GET /api/users`.
+    pub list_cache_control: String,
+    // Most operations accepted by one `POST /api/users/_bulk` request.
+    pub bulk_max_operations: usize,
+    // Days a soft deleted user can be restored before it is purged.
+    pub deleted_retention_days: i64,
+    // Largest upload accepted by `POST /api/users/import`.
+    pub import_max_bytes: usize,
+    // Largest image accepted by `PUT /api/users/{id}/avatar`.
+    pub avatar_max_bytes: usize,
+    // Most `GET /api/users/events` streams open at once.
+    pub events_max_subscribers: usize,
+    // Seconds between heartbeat comments on idle event streams.
+    pub events_heartbeat_secs: u64,
+}
+
+// User API initialize function.
+// Get user API settings from environment file.
+pub fn init() -> UserConfig {
+    let require_if_match = env::var("USER.REQUIRE_IF_MATCH")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    let config = UserConfig {
+        require_if_match,
+        user_cache_control: cache_control("USER.CACHE_CONTROL.GET"),
+        list_cache_control: cache_control("USER.CACHE_CONTROL.LIST"),
+        bulk_max_operations: env::var("USER.BULK_MAX_OPERATIONS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_BULK_MAX_OPERATIONS),
+        deleted_retention_days: retention_days(env::var("USER.DELETED_RETENTION_DAYS").ok()),
+        import_max_bytes: env::var("USER.IMPORT_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_IMPORT_MAX_BYTES),
+        avatar_max_bytes: env::var("USER.AVATAR_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_AVATAR_MAX_BYTES),
+        events_max_subscribers: env::var("USER.EVENTS.MAX_SUBSCRIBERS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_EVENTS_MAX_SUBSCRIBERS),
+        events_heartbeat_secs: env::var("USER.EVENTS.HEARTBEAT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_EVENTS_HEARTBEAT_SECS),
+    };
+    info!("User API config: {:?}", config);
+    config
+}
+
+fn cache_control(name: &str) -> String {
+    env::var(name).unwrap_or_else(|_| DEFAULT_CACHE_CONTROL.to_owned())
+}
+
+// At least a day, so a sweep never purges users that were just deleted, and at most
+// `MAX_DELETED_RETENTION_DAYS`. Anything else falls back to the default.
+fn retention_days(value: Option<String>) -> i64 {
+    let Some(value) = value else {
+        return DEFAULT_DELETED_RETENTION_DAYS;
+    };
+    match value.parse::<i64>() {
+        Ok(days) if days > MAX_DELETED_RETENTION_DAYS => {
+            warn!(
+                "USER.DELETED_RETENTION_DAYS is limited to {}",
+                MAX_DELETED_RETENTION_DAYS
+            );
+            MAX_DELETED_RETENTION_DAYS
+        }
+        Ok(days) if days > 0 => days,
+        _ => {
+            warn!(
+                "USER.DELETED_RETENTION_DAYS must be a positive number of days, using {}",
+                DEFAULT_DELETED_RETENTION_DAYS
+            );
+            DEFAULT_DELETED_RETENTION_DAYS
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_days_are_positive_and_bounded() {
+        let parse = |value: &str| retention_days(Some(value.to_owned()));
+        assert_eq!(parse("7"), 7);
+        assert_eq!(parse("3650"), 3650);
+        assert_eq!(parse("9223372036854775807"), MAX_DELETED_RETENTION_DAYS);
+        assert_eq!(parse("0"), DEFAULT_DELETED_RETENTION_DAYS);
+        assert_eq!(parse("-1"), DEFAULT_DELETED_RETENTION_DAYS);
+        assert_eq!(parse("a month"), DEFAULT_DELETED_RETENTION_DAYS);
+        assert_eq!(retention_days(None), DEFAULT_DELETED_RETENTION_DAYS);
+    }
+}
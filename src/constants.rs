@@ -2,7 +2,86 @@
 pub const MONGO_DATABASE: &str = "rustDB";
 pub const MONGO_USER_COLLECTION: &str = "user";
 pub const MONGO_AUTH_COLLECTION: &str = "auth";
+pub const MONGO_INVITATION_COLLECTION: &str = "invitation";
+pub const MONGO_ROLE_GRANT_COLLECTION: &str = "role_grant";
+pub const MONGO_USER_HISTORY_COLLECTION: &str = "user_history";
+pub const MONGO_IDEMPOTENCY_COLLECTION: &str = "idempotency_key";
+pub const MONGO_PASSWORD_RESET_COLLECTION: &str = "password_reset";
+pub const MONGO_AVATAR_BUCKET: &str = "avatar";
+pub const MONGO_AVATAR_FILES_COLLECTION: &str = "avatar.files";
 
 // Pagination configuration.
 pub const DEFAULT_OFFSET_SIZE: u64 = 0;
 pub const DEFAULT_LIMIT_SIZE: i64 = 20;
+pub const MAX_LIMIT_SIZE: i64 = 1000;
+
+// Roles checked by the handlers. Accounts and invitations can only carry these.
+pub const KNOWN_ROLES: [&str; 2] = ["ROLE_USER", "ROLE_ADMIN"];
+
+// User list filter and sort configuration.
+pub const SORTABLE_USER_FIELDS: [&str; 3] = ["name", "location", "title"];
+pub const DEFAULT_USER_SORT: &str = "name";
+
+// Sparse fieldsets. `_id` is always returned.
+pub const USER_FIELDS: [&str; 9] = [
+    "name",
+    "location",
+    "title",
+    "auth_id",
+    "version",
+    "created_ts",
+    "created_by",
+    "updated_ts",
+    "updated_by",
+];
+pub const USER_LIST_FIELDS: [&str; 4] = ["name", "location", "title", "auth_id"];
+pub const USER_INCLUDES: [&str; 1] = ["account"];
+
+// User search configuration.
+pub const USER_SEARCH_INDEX: &str = "user_search";
+pub const HIGHLIGHT_START: &str = "<em>";
+pub const HIGHLIGHT_END: &str = "</em>";
+
+// PATCH configuration.
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+pub const MAX_PATCH_ATTEMPTS: u32 = 3;
+
+// Account and user profile transactions.
+pub const MAX_TRANSACTION_ATTEMPTS: u32 = 3;
+pub const REPAIR_LINKS_ACTOR: &str = "repair-links";
+
+// User export and import formats.
+pub const CSV_CONTENT_TYPE: &str = "text/csv";
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+// User change events.
+pub const EVENT_STREAM_CONTENT_TYPE: &str = "text/event-stream";
+pub const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+
+// User avatars.
+pub const MULTIPART_CONTENT_TYPE: &str = "multipart/form-data";
+pub const AVATAR_FIELD: &str = "avatar";
+pub const AVATAR_CONTENT_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+pub const AVATAR_MAX_DIMENSION: u32 = 4096;
+pub const AVATAR_THUMBNAIL_SIZE: u32 = 128;
+pub const AVATAR_READ_CHUNK_BYTES: usize = 64 * 1024;
+
+// Idempotent POST requests.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+pub const IDEMPOTENCY_KEY_MAX_LENGTH: usize = 255;
+pub const IDEMPOTENCY_ANONYMOUS_OWNER: &str = "anonymous@";
+
+// Password reset configuration.
+pub const PASSWORD_RESET_EXPIRY_MINUTES: i64 = 30;
+
+// Invitation configuration.
+pub const DEFAULT_INVITATION_EXPIRY_HOURS: i64 = 72;
+
+// Temporary role grant configuration.
+pub const MAX_ROLE_GRANT_HOURS: i64 = 24;
+pub const ROLE_GRANT_SWEEP_INTERVAL_SECS: u64 = 300;
+
+// Soft deleted user configuration.
+pub const USER_PURGE_INTERVAL_SECS: u64 = 3600;
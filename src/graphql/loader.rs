@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use actix_web::web::Data;
+use async_graphql::dataloader::Loader;
+use mongodb::error::Error;
+use mongodb::Client;
+
+use crate::graphql::types::{AccountNode, UserNode};
+use crate::repository::{auth_repo, user_repo};
+
+// Loads the users requested while resolving one query with a single Mongo query.
+pub struct UserLoader(pub Data<Client>);
+
+// Loads the auth accounts requested while resolving one query with a single Mongo query.
+pub struct AccountLoader(pub Data<Client>);
+
+impl Loader<String> for UserLoader {
+    type Value = UserNode;
+    type Error = Error;
+
+    async fn load(&self, ids: &[String]) -> Result<HashMap<String, UserNode>, Error> {
+        let users = user_repo::get_users_by_ids(&self.0, ids).await?;
+        Ok(users
+            .iter()
+            .map(|user| (user.id.clone().unwrap_or_default(), UserNode::from(user)))
+            .collect())
+    }
+}
+
+impl Loader<String> for AccountLoader {
+    type Value = AccountNode;
+    type Error = Error;
+
+    async fn load(&self, ids: &[String]) -> Result<HashMap<String, AccountNode>, Error> {
+        let accounts = auth_repo::fetch_by_ids(&self.0, ids).await?;
+        Ok(accounts
+            .iter()
+            .map(|auth| (auth.id.to_owned(), AccountNode::from(auth)))
+            .collect())
+    }
+}
@@ -0,0 +1,147 @@
+use actix_web::web::Data;
+use async_graphql::{Context, EmptySubscription, ErrorExtensions, Guard, Result, Schema, Value};
+use log::{error, warn};
+use mongodb::Client;
+
+use crate::auth::claims::Claims;
+use crate::config::graphql::GraphqlConfig;
+use crate::config::user::UserConfig;
+use crate::models::error_model::ApiErrorType;
+
+pub mod loader;
+pub mod mutation;
+pub mod query;
+pub mod types;
+
+pub type ApiSchema = Schema<query::QueryRoot, mutation::MutationRoot, EmptySubscription>;
+
+const ROLE_ADMIN: &str = "ROLE_ADMIN";
+const ROLE_USER: &str = "ROLE_USER";
+
+// Build the GraphQL schema. The claims of the caller and the data loaders are added to each
+// request by the handler.
+pub fn schema(
+    config: &GraphqlConfig,
+    client: Data<Client>,
+    user_config: Data<UserConfig>,
+) -> ApiSchema {
+    let builder = Schema::build(query::QueryRoot, mutation::MutationRoot, EmptySubscription)
+        .data(client)
+        .data(user_config)
+        .limit_depth(config.max_depth)
+        .limit_complexity(config.max_complexity);
+    // Introspection is for development, together with GraphiQL.
+    if config.graphiql {
+        builder.finish()
+    } else {
+        builder.disable_introspection().finish()
+    }
+}
+
+// Allows a field to callers with the role, like `has_any_role` on REST handlers.
+pub struct RoleGuard(pub &'static str);
+
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        if caller(ctx).permissions.iter().any(|role| role == self.0) {
+            Ok(())
+        } else {
+            Err(graphql_error(ApiErrorType::AuthorizationError))
+        }
+    }
+}
+
+pub fn caller<'a>(ctx: &Context<'a>) -> &'a Claims {
+    ctx.data_unchecked::<Claims>()
+}
+
+// Fields of an account are for admins and the owner of the account.
+pub fn ensure_owner_or_admin(ctx: &Context<'_>, auth_id: &str) -> Result<()> {
+    let claims = caller(ctx);
+    if claims.sub == auth_id || claims.permissions.iter().any(|role| role == ROLE_ADMIN) {
+        Ok(())
+    } else {
+        warn!("{} is not allowed to read account {}", claims.sub, auth_id);
+        Err(graphql_error(ApiErrorType::AuthorizationError))
+    }
+}
+
+// GraphQL error with the fields of the REST error response as extensions.
+pub fn graphql_error(err: ApiErrorType) -> async_graphql::Error {
+    let api_error = err.api_error();
+    async_graphql::Error::new(api_error.message.to_owned()).extend_with(|_, extensions| {
+        extensions.set("status", api_error.status);
+        if let Some(debug_message) = &api_error.debug_message {
+            extensions.set("debug_message", debug_message.to_owned());
+        }
+        if !api_error.sub_errors.is_empty() {
+            if let Ok(sub_errors) = serde_json::to_value(&api_error.sub_errors) {
+                extensions.set(
+                    "sub_errors",
+                    Value::from_json(sub_errors).unwrap_or_default(),
+                );
+            }
+        }
+    })
+}
+
+pub fn internal_error(err: mongodb::error::Error) -> async_graphql::Error {
+    error!("Error: {}", err);
+    graphql_error(ApiErrorType::InternalServerError)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::Request;
+
+    use super::*;
+
+    async fn test_schema(graphiql: bool) -> ApiSchema {
+        let config = GraphqlConfig {
+            max_depth: 8,
+            max_complexity: 500,
+            graphiql,
+        };
+        let client = Client::with_uri_str("mongodb://localhost:27017")
+            .await
+            .unwrap();
+        let user_config = crate::config::user::init();
+        schema(&config, Data::new(client), Data::new(user_config))
+    }
+
+    fn request(query: &str) -> Request {
+        Request::new(query).data(Claims::new(
+            &"usr_1".to_owned(),
+            &vec![ROLE_USER.to_owned()],
+            &[],
+        ))
+    }
+
+    #[actix_web::test]
+    async fn introspection_follows_graphiql() {
+        let query = "{ __schema { queryType { name } } }";
+        let schema = |response: async_graphql::Response| {
+            response.data.into_json().unwrap()["__schema"].clone()
+        };
+        let response = test_schema(false).await.execute(request(query)).await;
+        assert_eq!(schema(response), serde_json::Value::Null);
+        let response = test_schema(true).await.execute(request(query)).await;
+        assert_eq!(schema(response)["queryType"]["name"], "Query");
+    }
+
+    #[actix_web::test]
+    async fn invalid_input_is_a_validation_error() {
+        let query = r#"mutation { createUser(input: {name: "J", location: "Chennai", title: "Engineer"}) { id } }"#;
+        let response = test_schema(false).await.execute(request(query)).await;
+        assert_eq!(response.errors.len(), 1);
+        let extensions = response.errors[0].extensions.as_ref().unwrap();
+        assert_eq!(extensions.get("status"), Some(&Value::from(422)));
+    }
+
+    #[test]
+    fn errors_carry_the_rest_status() {
+        let error = graphql_error(ApiErrorType::UserNotFound);
+        let extensions = error.extensions.unwrap();
+        assert_eq!(extensions.get("status"), Some(&Value::from(404)));
+    }
+}
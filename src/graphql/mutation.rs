@@ -0,0 +1,66 @@
+use actix_web::web::Data;
+use async_graphql::{Context, Object, Result, ID};
+use mongodb::Client;
+
+use crate::config::user::UserConfig;
+use crate::graphql::types::{UserInput, UserNode};
+use crate::graphql::{caller, graphql_error};
+use crate::handler::validated;
+use crate::models::error_model::ApiErrorType;
+use crate::models::user_model::User;
+use crate::services::user_service;
+
+pub struct MutationRoot;
+
+// Same writes as the REST user endpoints. `version` plays the role of the `If-Match` header.
+#[Object(name = "Mutation")]
+impl MutationRoot {
+    async fn create_user(&self, ctx: &Context<'_>, input: UserInput) -> Result<UserNode> {
+        let client = ctx.data_unchecked::<Data<Client>>();
+        let user = User::from(input);
+        validated::validate(&user).map_err(graphql_error)?;
+        let user = user_service::add_user(client, &caller(ctx).sub, user)
+            .await
+            .map_err(graphql_error)?;
+        Ok(UserNode::from(&user))
+    }
+
+    async fn update_user(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        input: UserInput,
+        version: Option<i64>,
+    ) -> Result<UserNode> {
+        let client = ctx.data_unchecked::<Data<Client>>();
+        let versions = accepted_version(ctx, version)?;
+        let user = User::from(input);
+        validated::validate(&user).map_err(graphql_error)?;
+        let user =
+            user_service::replace_user(client, &id, user, versions.as_deref(), &caller(ctx).sub)
+                .await
+                .map_err(graphql_error)?;
+        Ok(UserNode::from(&user))
+    }
+
+    // Soft delete a user. Returns true once the user is deleted.
+    async fn delete_user(&self, ctx: &Context<'_>, id: ID, version: Option<i64>) -> Result<bool> {
+        let client = ctx.data_unchecked::<Data<Client>>();
+        let versions = accepted_version(ctx, version)?;
+        user_service::remove_user(client, &id, versions.as_deref(), &caller(ctx).sub)
+            .await
+            .map_err(graphql_error)?;
+        Ok(true)
+    }
+}
+
+// Version a write is conditional on. Required when `USER.REQUIRE_IF_MATCH` is set.
+fn accepted_version(ctx: &Context<'_>, version: Option<i64>) -> Result<Option<Vec<i64>>> {
+    match version {
+        Some(version) => Ok(Some(vec![version])),
+        None if ctx.data_unchecked::<Data<UserConfig>>().require_if_match => {
+            Err(graphql_error(ApiErrorType::PreconditionRequired))
+        }
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,75 @@
+use actix_web::web::Data;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{Context, Object, Result, ID};
+use mongodb::bson::doc;
+use mongodb::Client;
+
+use crate::api::user_api::UserFilter;
+use crate::graphql::loader::{AccountLoader, UserLoader};
+use crate::graphql::types::{AccountNode, UserFilterInput, UserNode};
+use crate::graphql::{caller, internal_error, RoleGuard, ROLE_ADMIN, ROLE_USER};
+use crate::repository::user_repo;
+use crate::services::user_service;
+
+pub struct QueryRoot;
+
+#[Object(name = "Query")]
+impl QueryRoot {
+    // Account of the signed in caller.
+    async fn me(&self, ctx: &Context<'_>) -> Result<Option<AccountNode>> {
+        ctx.data_unchecked::<DataLoader<AccountLoader>>()
+            .load_one(caller(ctx).sub.to_owned())
+            .await
+            .map_err(internal_error)
+    }
+
+    #[graphql(guard = "RoleGuard(ROLE_USER)")]
+    async fn user(&self, ctx: &Context<'_>, id: ID) -> Result<Option<UserNode>> {
+        ctx.data_unchecked::<DataLoader<UserLoader>>()
+            .load_one(id.0)
+            .await
+            .map_err(internal_error)
+    }
+
+    // Users matching the filter, sorted by name. Each user counts once per item towards the
+    // query complexity.
+    #[graphql(
+        guard = "RoleGuard(ROLE_USER)",
+        complexity = "limit.clamp(1, 1000) as usize * child_complexity"
+    )]
+    async fn users(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<UserFilterInput>,
+        #[graphql(default = 0)] offset: u64,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 1000))] limit: i64,
+    ) -> Result<Vec<UserNode>> {
+        let client = ctx.data_unchecked::<Data<Client>>();
+        let filter = UserFilter::from(filter.unwrap_or_default());
+        let page = user_repo::get_all_users(
+            client,
+            user_service::filter_document(&filter, ""),
+            doc! {"name": 1, "_id": 1},
+            offset,
+            limit,
+            doc! {"_id": 1},
+        )
+        .await
+        .map_err(internal_error)?;
+        let ids: Vec<String> = page.into_iter().map(|user| user.id).collect();
+        let mut users = ctx
+            .data_unchecked::<DataLoader<UserLoader>>()
+            .load_many(ids.iter().cloned())
+            .await
+            .map_err(internal_error)?;
+        Ok(ids.iter().filter_map(|id| users.remove(id)).collect())
+    }
+
+    #[graphql(guard = "RoleGuard(ROLE_ADMIN)")]
+    async fn account(&self, ctx: &Context<'_>, id: ID) -> Result<Option<AccountNode>> {
+        ctx.data_unchecked::<DataLoader<AccountLoader>>()
+            .load_one(id.0)
+            .await
+            .map_err(internal_error)
+    }
+}
@@ -0,0 +1,178 @@
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{ComplexObject, Context, InputObject, Result, SimpleObject, ID};
+use chrono::SecondsFormat;
+
+use crate::api::user_api::UserFilter;
+use crate::graphql::loader::{AccountLoader, UserLoader};
+use crate::graphql::{ensure_owner_or_admin, internal_error};
+use crate::models::auth_model::{Auth, AuthSource};
+use crate::models::user_model::User;
+
+// User profile, as returned by `GET /api/users/{id}`.
+#[derive(Clone, SimpleObject)]
+#[graphql(name = "User", complex)]
+pub struct UserNode {
+    pub id: ID,
+    pub name: String,
+    pub location: String,
+    pub title: String,
+    pub version: i64,
+    pub created_ts: Option<String>,
+    pub created_by: Option<String>,
+    pub updated_ts: Option<String>,
+    pub updated_by: Option<String>,
+    #[graphql(skip)]
+    pub auth_id: Option<String>,
+}
+
+#[ComplexObject]
+impl UserNode {
+    // Linked auth account. Only for admins and the owner of the account.
+    async fn account(&self, ctx: &Context<'_>) -> Result<Option<AccountNode>> {
+        let Some(auth_id) = &self.auth_id else {
+            return Ok(None);
+        };
+        ensure_owner_or_admin(ctx, auth_id)?;
+        ctx.data_unchecked::<DataLoader<AccountLoader>>()
+            .load_one(auth_id.to_owned())
+            .await
+            .map_err(internal_error)
+    }
+}
+
+impl From<&User> for UserNode {
+    fn from(user: &User) -> Self {
+        let timestamp = |ts: Option<chrono::DateTime<chrono::Utc>>| {
+            ts.map(|ts| ts.to_rfc3339_opts(SecondsFormat::Micros, true))
+        };
+        UserNode {
+            id: ID(user.id.clone().unwrap_or_default()),
+            name: user.name.to_owned(),
+            location: user.location.to_owned(),
+            title: user.title.to_owned(),
+            version: user.version,
+            created_ts: timestamp(user.created_ts),
+            created_by: user.created_by.to_owned(),
+            updated_ts: timestamp(user.updated_ts),
+            updated_by: user.updated_by.to_owned(),
+            auth_id: user.auth_id.to_owned(),
+        }
+    }
+}
+
+// Auth account. Email and roles are only for admins and the owner of the account.
+#[derive(Clone, SimpleObject)]
+#[graphql(name = "Account", complex)]
+pub struct AccountNode {
+    pub id: ID,
+    pub first_name: String,
+    pub last_name: String,
+    pub active: bool,
+    pub provider: String,
+    #[graphql(skip)]
+    pub email: String,
+    #[graphql(skip)]
+    pub roles: Vec<String>,
+    #[graphql(skip)]
+    pub user_id: Option<String>,
+}
+
+#[ComplexObject]
+impl AccountNode {
+    async fn email(&self, ctx: &Context<'_>) -> Result<&str> {
+        ensure_owner_or_admin(ctx, &self.id)?;
+        Ok(&self.email)
+    }
+
+    async fn roles(&self, ctx: &Context<'_>) -> Result<&[String]> {
+        ensure_owner_or_admin(ctx, &self.id)?;
+        Ok(&self.roles)
+    }
+
+    // Linked user profile.
+    async fn user(&self, ctx: &Context<'_>) -> Result<Option<UserNode>> {
+        let Some(user_id) = &self.user_id else {
+            return Ok(None);
+        };
+        ctx.data_unchecked::<DataLoader<UserLoader>>()
+            .load_one(user_id.to_owned())
+            .await
+            .map_err(internal_error)
+    }
+}
+
+impl From<&Auth> for AccountNode {
+    fn from(auth: &Auth) -> Self {
+        AccountNode {
+            id: ID(auth.id.to_owned()),
+            first_name: auth.first_name.to_owned(),
+            last_name: auth.last_name.to_owned(),
+            active: auth.active,
+            provider: match auth.provider {
+                AuthSource::Local => "local".to_owned(),
+                AuthSource::Ldap => "ldap".to_owned(),
+            },
+            email: auth.email.to_owned(),
+            roles: auth.roles.to_owned(),
+            user_id: auth.user_id.to_owned(),
+        }
+    }
+}
+
+// Fields of a created or updated user.
+#[derive(InputObject)]
+pub struct UserInput {
+    pub name: String,
+    pub location: String,
+    pub title: String,
+}
+
+impl From<UserInput> for User {
+    fn from(input: UserInput) -> Self {
+        User {
+            id: None,
+            name: input.name,
+            location: input.location,
+            title: input.title,
+            auth_id: None,
+            version: 0,
+            created_ts: None,
+            created_by: None,
+            updated_ts: None,
+            updated_by: None,
+            deleted_at: None,
+            deleted_by: None,
+        }
+    }
+}
+
+// Filters of the user list, like the query parameters of `GET /api/users`.
+#[derive(InputObject, Default)]
+pub struct UserFilterInput {
+    pub name: Option<String>,
+    pub name_prefix: Option<String>,
+    pub name_contains: Option<String>,
+    pub location: Option<String>,
+    pub location_prefix: Option<String>,
+    pub location_contains: Option<String>,
+    pub title: Option<String>,
+    pub title_prefix: Option<String>,
+    pub title_contains: Option<String>,
+}
+
+impl From<UserFilterInput> for UserFilter {
+    fn from(input: UserFilterInput) -> Self {
+        UserFilter {
+            name: input.name,
+            name_prefix: input.name_prefix,
+            name_contains: input.name_contains,
+            location: input.location,
+            location_prefix: input.location_prefix,
+            location_contains: input.location_contains,
+            title: input.title,
+            title_prefix: input.title_prefix,
+            title_contains: input.title_contains,
+            sort: None,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use actix_web::web::Data;
+use chrono::DateTime;
+use mongodb::Client;
+use tonic::{Request, Response, Status};
+
+use crate::api::auth_api::LoginRequest;
+use crate::auth::claims::Claims;
+use crate::auth::provider::AuthProviders;
+use crate::grpc::proto::auth_service_server::AuthService;
+use crate::grpc::proto::{self, ValidateTokenRequest, ValidateTokenResponse};
+use crate::grpc::{status, timestamp};
+use crate::handler::validated;
+use crate::models::error_model::ApiErrorType;
+use crate::services::auth_service;
+
+pub struct AuthGrpc {
+    pub client: Data<Client>,
+    pub providers: Data<AuthProviders>,
+}
+
+#[tonic::async_trait]
+impl AuthService for AuthGrpc {
+    async fn login(
+        &self,
+        request: Request<proto::LoginRequest>,
+    ) -> Result<Response<proto::LoginResponse>, Status> {
+        let request = request.into_inner();
+        let login_request = LoginRequest {
+            email: request.email,
+            password: request.password,
+        };
+        validated::validate(&login_request).map_err(status)?;
+        let token = auth_service::issue_token(&self.client, &self.providers, login_request)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(proto::LoginResponse {
+            access_token: token.access_token,
+            token_type: token.token_type,
+        }))
+    }
+
+    // Check the signature and expiry of a token, for services that do not share the secret.
+    async fn validate_token(
+        &self,
+        request: Request<ValidateTokenRequest>,
+    ) -> Result<Response<ValidateTokenResponse>, Status> {
+        let claims = Claims::decode_jwt(&request.into_inner().access_token)
+            .map_err(|_| status(ApiErrorType::AuthenticationError))?;
+        Ok(Response::new(ValidateTokenResponse {
+            expires_at: DateTime::from_timestamp(claims.expires_at(), 0).map(timestamp),
+            subject: claims.sub,
+            roles: claims.permissions,
+        }))
+    }
+}
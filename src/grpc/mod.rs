@@ -0,0 +1,257 @@
+use std::io;
+
+use actix_web::web::Data;
+use actix_web::ResponseError;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use mongodb::Client;
+use tonic::service::Interceptor;
+use tonic::transport::Server;
+use tonic::{Code, Request, Status};
+
+use crate::auth::claims::Claims;
+use crate::auth::provider::AuthProviders;
+use crate::config::grpc::GrpcConfig;
+use crate::config::user::UserConfig;
+use crate::models::error_model::ApiErrorType;
+
+pub mod auth_grpc;
+pub mod user_grpc;
+
+pub mod proto {
+    tonic::include_proto!("actix_api.v1");
+
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("user_descriptor");
+}
+
+use proto::auth_service_server::AuthServiceServer;
+use proto::user_service_server::UserServiceServer;
+
+// Run the gRPC server until it fails. Users need a bearer token, login, token validation,
+// health and reflection do not.
+pub async fn serve(
+    config: GrpcConfig,
+    client: Data<Client>,
+    providers: Data<AuthProviders>,
+    user_config: Data<UserConfig>,
+) -> io::Result<()> {
+    let address = format!("{}:{}", config.host, config.port)
+        .parse()
+        .map_err(io::Error::other)?;
+
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter
+        .set_serving::<UserServiceServer<user_grpc::UserGrpc>>()
+        .await;
+    health_reporter
+        .set_serving::<AuthServiceServer<auth_grpc::AuthGrpc>>()
+        .await;
+
+    let users = user_grpc::UserGrpc {
+        client: client.clone(),
+        user_config,
+    };
+    let auth = auth_grpc::AuthGrpc { client, providers };
+    let reflection = if config.reflection {
+        let service = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+            .build_v1()
+            .map_err(io::Error::other)?;
+        Some(service)
+    } else {
+        None
+    };
+
+    info!("Starting gRPC server in {}", address);
+    Server::builder()
+        .add_service(health_service)
+        .add_optional_service(reflection)
+        .add_service(AuthServiceServer::new(auth))
+        .add_service(UserServiceServer::with_interceptor(users, Authenticate))
+        .serve(address)
+        .await
+        .map_err(io::Error::other)
+}
+
+// Bearer token interceptor, like the `HttpAuthentication` of `/api`. The claims are kept in
+// the request extensions for the handlers.
+#[derive(Clone)]
+pub struct Authenticate;
+
+impl Interceptor for Authenticate {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        debug!("Validate gRPC jwt auth");
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| status(ApiErrorType::AuthenticationError))?;
+        let claims =
+            Claims::decode_jwt(token).map_err(|_| status(ApiErrorType::AuthenticationError))?;
+        request.extensions_mut().insert(claims);
+        Ok(request)
+    }
+}
+
+// Claims of the caller, set by `Authenticate`.
+pub fn caller<T>(request: &Request<T>) -> Result<&Claims, ApiErrorType> {
+    request
+        .extensions()
+        .get::<Claims>()
+        .ok_or(ApiErrorType::AuthenticationError)
+}
+
+// Allows a call to callers with the role, like `has_any_role` on REST handlers.
+pub fn ensure_role(claims: &Claims, role: &str) -> Result<(), ApiErrorType> {
+    if claims
+        .permissions
+        .iter()
+        .any(|permission| permission == role)
+    {
+        Ok(())
+    } else {
+        Err(ApiErrorType::AuthorizationError)
+    }
+}
+
+// gRPC status of an API error, from its HTTP status. The details carry the REST error body
+// as JSON.
+pub fn status(err: ApiErrorType) -> Status {
+    let code = match err.status_code().as_u16() {
+        400 | 415 | 422 => Code::InvalidArgument,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        409 => Code::Aborted,
+        412 | 428 => Code::FailedPrecondition,
+        413 => Code::ResourceExhausted,
+        503 => Code::Unavailable,
+        500 => Code::Internal,
+        _ => Code::Unknown,
+    };
+    let api_error = err.api_error();
+    match serde_json::to_vec(&api_error) {
+        Ok(details) => Status::with_details(code, api_error.message, details.into()),
+        Err(err) => {
+            error!("Error: {}", err);
+            Status::new(code, api_error.message)
+        }
+    }
+}
+
+pub fn timestamp(ts: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: ts.timestamp(),
+        nanos: ts.timestamp_subsec_nanos() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::auth_model::{Auth, AuthSource};
+
+    fn token(roles: &[&str]) -> String {
+        let auth = Auth {
+            id: "auth_1".to_owned(),
+            email: "john@example.com".to_owned(),
+            first_name: "John".to_owned(),
+            last_name: "Doe".to_owned(),
+            password_hash: String::new(),
+            password_history: Vec::new(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            active: true,
+            reset_password: false,
+            provider: AuthSource::default(),
+            user_id: None,
+            created_ts: Utc::now(),
+            updated_ts: Utc::now(),
+        };
+        Claims::create_jwt_token(&auth, &[]).unwrap().access_token
+    }
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(value) = authorization {
+            request
+                .metadata_mut()
+                .insert("authorization", value.parse().unwrap());
+        }
+        request
+    }
+
+    #[test]
+    fn status_codes_follow_http_statuses() {
+        let code = |err: ApiErrorType| status(err).code();
+        assert_eq!(code(ApiErrorType::BadRequest), Code::InvalidArgument);
+        assert_eq!(
+            code(ApiErrorType::InvalidPatch {
+                detail: "bad".to_owned()
+            }),
+            Code::InvalidArgument
+        );
+        assert_eq!(
+            code(ApiErrorType::AuthenticationError),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            code(ApiErrorType::AuthorizationError),
+            Code::PermissionDenied
+        );
+        assert_eq!(code(ApiErrorType::UserNotFound), Code::NotFound);
+        assert_eq!(code(ApiErrorType::UpdateConflict), Code::Aborted);
+        assert_eq!(
+            code(ApiErrorType::PreconditionFailed),
+            Code::FailedPrecondition
+        );
+        assert_eq!(
+            code(ApiErrorType::PreconditionRequired),
+            Code::FailedPrecondition
+        );
+        assert_eq!(
+            code(ApiErrorType::BatchTooLarge { max: 10 }),
+            Code::ResourceExhausted
+        );
+        assert_eq!(code(ApiErrorType::InternalServerError), Code::Internal);
+    }
+
+    #[test]
+    fn status_details_carry_the_api_error() {
+        let status = status(ApiErrorType::UserNotFound);
+        let details: serde_json::Value = serde_json::from_slice(status.details()).unwrap();
+        assert_eq!(details["status"], 404);
+        assert_eq!(details["message"], status.message());
+        assert_eq!(details["debug_message"], "User not found for given ID");
+    }
+
+    #[test]
+    fn ensure_role_needs_the_permission() {
+        let claims = Claims::new(&"usr_1".to_owned(), &vec!["ROLE_ADMIN".to_owned()], &[]);
+        assert!(ensure_role(&claims, "ROLE_ADMIN").is_ok());
+        assert!(matches!(
+            ensure_role(&claims, "ROLE_SUPER"),
+            Err(ApiErrorType::AuthorizationError)
+        ));
+    }
+
+    #[test]
+    fn authenticate_keeps_the_claims_of_a_valid_token() {
+        let bearer = format!("Bearer {}", token(&["ROLE_ADMIN"]));
+        let request = Authenticate.call(request(Some(&bearer))).unwrap();
+        let claims = caller(&request).unwrap();
+        assert_eq!(claims.sub, "auth_1");
+        assert_eq!(claims.permissions, vec!["ROLE_ADMIN".to_owned()]);
+    }
+
+    #[test]
+    fn authenticate_refuses_missing_or_bad_tokens() {
+        let token = token(&[]);
+        for authorization in [None, Some("Bearer not-a-jwt"), Some(token.as_str())] {
+            let err = Authenticate.call(request(authorization)).unwrap_err();
+            assert_eq!(err.code(), Code::Unauthenticated);
+        }
+        assert!(caller(&request(None)).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use actix_web::web::Data;
+use mongodb::Client;
+use tonic::{Request, Response, Status};
+
+use crate::api::user_api::UserFilter;
+use crate::config::user::UserConfig;
+use crate::constants;
+use crate::grpc::proto::user_service_server::UserService;
+use crate::grpc::proto::{
+    self, CreateUserRequest, DeleteUserRequest, DeleteUserResponse, GetUserRequest,
+    ListUsersRequest, ListUsersResponse, UpdateUserRequest,
+};
+use crate::grpc::{caller, ensure_role, status, timestamp};
+use crate::handler::validated;
+use crate::models::error_model::ApiErrorType;
+use crate::models::user_model::User;
+use crate::services::user_service;
+
+const ROLE_USER: &str = "ROLE_USER";
+
+pub struct UserGrpc {
+    pub client: Data<Client>,
+    pub user_config: Data<UserConfig>,
+}
+
+impl UserGrpc {
+    // Version a write is conditional on. Required when `USER.REQUIRE_IF_MATCH` is set.
+    fn accepted_version(&self, version: Option<i64>) -> Result<Option<Vec<i64>>, ApiErrorType> {
+        match version {
+            Some(version) => Ok(Some(vec![version])),
+            None if self.user_config.require_if_match => Err(ApiErrorType::PreconditionRequired),
+            None => Ok(None),
+        }
+    }
+}
+
+// Same calls as the REST user endpoints, through the same service functions.
+#[tonic::async_trait]
+impl UserService for UserGrpc {
+    async fn create_user(
+        &self,
+        request: Request<CreateUserRequest>,
+    ) -> Result<Response<proto::User>, Status> {
+        let created_by = caller(&request).map_err(status)?.sub.to_owned();
+        let request = request.into_inner();
+        let user = new_user(request.name, request.location, request.title);
+        validated::validate(&user).map_err(status)?;
+        let user = user_service::add_user(&self.client, &created_by, user)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(to_proto(user)))
+    }
+
+    async fn get_user(
+        &self,
+        request: Request<GetUserRequest>,
+    ) -> Result<Response<proto::User>, Status> {
+        let user = user_service::find_user(&self.client, &request.into_inner().id)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(to_proto(user)))
+    }
+
+    async fn update_user(
+        &self,
+        request: Request<UpdateUserRequest>,
+    ) -> Result<Response<proto::User>, Status> {
+        let updated_by = caller(&request).map_err(status)?.sub.to_owned();
+        let request = request.into_inner();
+        let versions = self.accepted_version(request.version).map_err(status)?;
+        let user = new_user(request.name, request.location, request.title);
+        validated::validate(&user).map_err(status)?;
+        let user = user_service::replace_user(
+            &self.client,
+            &request.id,
+            user,
+            versions.as_deref(),
+            &updated_by,
+        )
+        .await
+        .map_err(status)?;
+        Ok(Response::new(to_proto(user)))
+    }
+
+    async fn delete_user(
+        &self,
+        request: Request<DeleteUserRequest>,
+    ) -> Result<Response<DeleteUserResponse>, Status> {
+        let deleted_by = caller(&request).map_err(status)?.sub.to_owned();
+        let request = request.into_inner();
+        let versions = self.accepted_version(request.version).map_err(status)?;
+        user_service::remove_user(&self.client, &request.id, versions.as_deref(), &deleted_by)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(DeleteUserResponse {}))
+    }
+
+    async fn list_users(
+        &self,
+        request: Request<ListUsersRequest>,
+    ) -> Result<Response<ListUsersResponse>, Status> {
+        caller(&request)
+            .and_then(|claims| ensure_role(claims, ROLE_USER))
+            .map_err(status)?;
+        let request = request.into_inner();
+        let limit = request.limit.unwrap_or(constants::DEFAULT_LIMIT_SIZE);
+        if !(1..=constants::MAX_LIMIT_SIZE).contains(&limit) {
+            return Err(status(ApiErrorType::InvalidQueryParameter {
+                parameter: "limit".to_owned(),
+                value: limit.to_string(),
+                message: format!("limit must be between 1 and {}", constants::MAX_LIMIT_SIZE),
+            }));
+        }
+        let filter = UserFilter {
+            name: request.name,
+            name_prefix: request.name_prefix,
+            name_contains: request.name_contains,
+            location: request.location,
+            location_prefix: request.location_prefix,
+            location_contains: request.location_contains,
+            title: request.title,
+            title_prefix: request.title_prefix,
+            title_contains: request.title_contains,
+            sort: None,
+        };
+        let (users, total) = user_service::find_users(&self.client, &filter, request.offset, limit)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(ListUsersResponse {
+            users: users.into_iter().map(to_proto).collect(),
+            offset: request.offset,
+            limit,
+            total,
+        }))
+    }
+}
+
+fn new_user(name: String, location: String, title: String) -> User {
+    User {
+        id: None,
+        name,
+        location,
+        title,
+        auth_id: None,
+        version: 0,
+        created_ts: None,
+        created_by: None,
+        updated_ts: None,
+        updated_by: None,
+        deleted_at: None,
+        deleted_by: None,
+    }
+}
+
+fn to_proto(user: User) -> proto::User {
+    proto::User {
+        id: user.id.unwrap_or_default(),
+        name: user.name,
+        location: user.location,
+        title: user.title,
+        version: user.version,
+        auth_id: user.auth_id,
+        created_ts: user.created_ts.map(timestamp),
+        created_by: user.created_by,
+        updated_ts: user.updated_ts.map(timestamp),
+        updated_by: user.updated_by,
+    }
+}
@@ -0,0 +1,314 @@
+use std::error::Error as StdError;
+
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web::{self, Bytes, Data};
+use actix_web::{Error, HttpMessage, HttpResponse};
+use chrono::{Duration, Utc};
+use log::{error, info, warn};
+use mongodb::bson::{spec::BinarySubtype, Binary};
+use mongodb::Client;
+use sha2::{Digest, Sha256};
+
+use crate::auth::claims::Claims;
+use crate::config::idempotency::IdempotencyConfig;
+use crate::config::user::UserConfig;
+use crate::constants;
+use crate::models::error_model::ApiErrorType;
+use crate::models::idempotency_model::{IdempotencyRecord, IdempotencyState, StoredResponse};
+use crate::repository::idempotency_repo;
+
+// Run a POST request sent with an `Idempotency-Key` at most once. Retries with the same key
+// and request get the stored response, a different request with the key gets `409`, and so
+// does a retry while the first request is still running.
+// Wrap it inside the authentication, so keys are kept per caller.
+pub async fn idempotency(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let key = match req.headers().get(constants::IDEMPOTENCY_KEY_HEADER) {
+        Some(key) if req.method() == Method::POST => key,
+        _ => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_boxed_body)
+        }
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= constants::IDEMPOTENCY_KEY_MAX_LENGTH => {
+            key.to_owned()
+        }
+        _ => {
+            return Err(ApiErrorType::InvalidIdempotencyKey {
+                max: constants::IDEMPOTENCY_KEY_MAX_LENGTH,
+            }
+            .into())
+        }
+    };
+    let (Some(client), Some(config), Some(user_config)) = (
+        req.app_data::<Data<Client>>().cloned(),
+        req.app_data::<Data<IdempotencyConfig>>().cloned(),
+        req.app_data::<Data<UserConfig>>().cloned(),
+    ) else {
+        error!("Error: idempotency middleware is missing its app data");
+        return Err(ApiErrorType::InternalServerError.into());
+    };
+
+    // Buffer the body for the fingerprint and hand it on to the handler. The largest body of
+    // a wrapped route is an import upload, the handlers apply their own limits.
+    let body = read_body(&mut req, user_config.import_max_bytes).await?;
+    let fingerprint = fingerprint(&req, &body);
+    req.set_payload(Payload::from(body));
+
+    let id = format!("{}:{}", key_owner(&req), key);
+    let now = Utc::now();
+    let claimed = idempotency_repo::claim(
+        &client,
+        &id,
+        &fingerprint,
+        now + Duration::seconds(config.lock_secs),
+        now + Duration::hours(config.ttl_hours),
+    )
+    .await;
+    match claimed {
+        Ok(None) => {}
+        Ok(Some(record)) => {
+            return replay(&id, &fingerprint, record).map(|res| req.into_response(res))
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError.into());
+        }
+    }
+
+    let res = match next.call(req).await {
+        Ok(res) => res,
+        Err(err) => {
+            release(&client, &id).await;
+            return Err(err);
+        }
+    };
+    // Server errors are not kept, the request may succeed when it is retried.
+    if res.status().is_server_error() {
+        release(&client, &id).await;
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let body = match body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => {
+            let err: Box<dyn StdError> = err.into();
+            error!("Error reading response: {}", err);
+            release(&client, &id).await;
+            return Err(ApiErrorType::InternalServerError.into());
+        }
+    };
+    let stored = StoredResponse {
+        status: res.status().as_u16(),
+        headers: res
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect(),
+        body: Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: body.to_vec(),
+        },
+    };
+    // The request is done, a response that can not be kept is only logged.
+    if let Err(err) = idempotency_repo::complete(&client, &id, &stored).await {
+        error!("Error storing response of idempotency key {}: {}", id, err);
+    }
+    Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(body))))
+}
+
+// Namespace of the keys of a caller. Keys sent without a token, like the ones of
+// `POST /a/register`, are kept per client address instead of in one shared namespace.
+fn key_owner(req: &ServiceRequest) -> String {
+    if let Some(claims) = req.extensions().get::<Claims>() {
+        return claims.sub.to_owned();
+    }
+    format!(
+        "{}{}",
+        constants::IDEMPOTENCY_ANONYMOUS_OWNER,
+        req.connection_info()
+            .realip_remote_addr()
+            .unwrap_or_default()
+    )
+}
+
+async fn read_body(req: &mut ServiceRequest, max: usize) -> Result<Bytes, ApiErrorType> {
+    let payload = req.extract::<web::Payload>().await.map_err(|err| {
+        warn!("Error reading request: {}", err);
+        ApiErrorType::BadRequest
+    })?;
+    match payload.to_bytes_limited(max).await {
+        Ok(Ok(body)) => Ok(body),
+        Ok(Err(err)) => {
+            warn!("Error reading request: {}", err);
+            Err(ApiErrorType::BadRequest)
+        }
+        Err(_) => Err(ApiErrorType::ImportTooLarge { max }),
+    }
+}
+
+// Hash of the method, path, query and body of a request.
+fn fingerprint(req: &ServiceRequest, body: &Bytes) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{} {}?{}\n",
+        req.method(),
+        req.path(),
+        req.query_string()
+    ));
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn replay(id: &str, fingerprint: &str, record: IdempotencyRecord) -> Result<HttpResponse, Error> {
+    if record.fingerprint != fingerprint {
+        warn!("Idempotency key {} was sent with a different request", id);
+        return Err(ApiErrorType::IdempotencyKeyReused.into());
+    }
+    let stored = match (record.state, record.response) {
+        (IdempotencyState::Completed, Some(stored)) => stored,
+        _ => {
+            warn!("Idempotency key {} is in use by a running request", id);
+            return Err(ApiErrorType::IdempotencyKeyInProgress.into());
+        }
+    };
+    info!("Replaying response of idempotency key {}", id);
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut response = HttpResponse::build(status);
+    for (name, value) in &stored.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            response.append_header((name, value));
+        }
+    }
+    response.insert_header((constants::IDEMPOTENT_REPLAYED_HEADER, "true"));
+    Ok(response.body(stored.body.bytes))
+}
+
+async fn release(client: &Data<Client>, id: &str) {
+    if let Err(err) = idempotency_repo::release(client, id).await {
+        error!("Error releasing idempotency key {}: {}", id, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn fingerprint_covers_method_path_query_and_body() {
+        let req = TestRequest::post()
+            .uri("/api/users?dry_run=true")
+            .to_srv_request();
+        let body = Bytes::from_static(b"{}");
+        let same = TestRequest::post()
+            .uri("/api/users?dry_run=true")
+            .to_srv_request();
+        assert_eq!(fingerprint(&req, &body), fingerprint(&same, &body));
+        for other in [
+            "/api/users",
+            "/api/users?dry_run=false",
+            "/api/users/import?dry_run=true",
+        ] {
+            let other = TestRequest::post().uri(other).to_srv_request();
+            assert_ne!(fingerprint(&req, &body), fingerprint(&other, &body));
+        }
+        let put = TestRequest::put()
+            .uri("/api/users?dry_run=true")
+            .to_srv_request();
+        assert_ne!(fingerprint(&req, &body), fingerprint(&put, &body));
+        assert_ne!(
+            fingerprint(&req, &body),
+            fingerprint(&req, &Bytes::from_static(b"{ }"))
+        );
+    }
+
+    #[actix_web::test]
+    async fn body_is_read_up_to_the_limit() {
+        let mut req = TestRequest::post()
+            .set_payload(vec![b'a'; 11])
+            .to_srv_request();
+        assert!(matches!(
+            read_body(&mut req, 10).await,
+            Err(ApiErrorType::ImportTooLarge { max: 10 })
+        ));
+
+        let mut req = TestRequest::post()
+            .set_payload(vec![b'a'; 11])
+            .to_srv_request();
+        assert_eq!(read_body(&mut req, 11).await.unwrap().len(), 11);
+    }
+
+    #[test]
+    fn keys_are_owned_by_the_caller_or_the_client_address() {
+        let req = TestRequest::post()
+            .peer_addr("10.0.0.1:4321".parse().unwrap())
+            .to_srv_request();
+        assert_eq!(key_owner(&req), "anonymous@10.0.0.1");
+        let other = TestRequest::post()
+            .peer_addr("10.0.0.2:4321".parse().unwrap())
+            .to_srv_request();
+        assert_ne!(key_owner(&req), key_owner(&other));
+
+        let claims = Claims::new(&"usr_1".to_owned(), &vec![], &[]);
+        req.extensions_mut().insert(claims);
+        assert_eq!(key_owner(&req), "usr_1");
+    }
+
+    #[test]
+    fn replay_needs_the_same_request_and_a_stored_response() {
+        let record = |state, response| IdempotencyRecord {
+            id: "usr_1:key".to_owned(),
+            fingerprint: "abc".to_owned(),
+            state,
+            response,
+            created_ts: Utc::now(),
+            locked_until: Utc::now(),
+            expires_at: Utc::now(),
+        };
+        let stored = || StoredResponse {
+            status: 201,
+            headers: vec![("content-type".to_owned(), "application/json".to_owned())],
+            body: Binary {
+                subtype: BinarySubtype::Generic,
+                bytes: b"{}".to_vec(),
+            },
+        };
+        let replayed = replay(
+            "usr_1:key",
+            "abc",
+            record(IdempotencyState::Completed, Some(stored())),
+        )
+        .unwrap();
+        assert_eq!(replayed.status(), StatusCode::CREATED);
+        assert_eq!(
+            replayed
+                .headers()
+                .get(constants::IDEMPOTENT_REPLAYED_HEADER)
+                .unwrap(),
+            "true"
+        );
+        assert!(replay(
+            "usr_1:key",
+            "def",
+            record(IdempotencyState::Completed, Some(stored()))
+        )
+        .is_err());
+        assert!(replay("usr_1:key", "abc", record(IdempotencyState::Pending, None)).is_err());
+    }
+}
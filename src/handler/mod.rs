@@ -1 +1,3 @@
 pub mod error_handler_middleware;
+pub mod idempotency_middleware;
+pub mod validated;
@@ -0,0 +1,204 @@
+use std::ops::Deref;
+
+use actix_web::dev::Payload;
+use actix_web::web::{Json, Query};
+use actix_web::{Error, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use log::warn;
+use serde::de::DeserializeOwned;
+use validator::{Validate, ValidationError};
+
+use crate::constants;
+use crate::models::error_model::ApiErrorType;
+
+// JSON body that is validated on extraction. Parse errors are answered like `Json`, through
+// the `JsonConfig` error handler, failed validations with `ValidationError`.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+// Query string that is validated on extraction. Parse errors are answered like `Query`.
+#[derive(Debug)]
+pub struct ValidatedQuery<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Deref for ValidatedQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let value = json.await?.into_inner();
+            validate(&value)?;
+            Ok(ValidatedJson(value))
+        })
+    }
+}
+
+impl<T: DeserializeOwned + Validate> FromRequest for ValidatedQuery<T> {
+    type Error = Error;
+    type Future = std::future::Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        std::future::ready(
+            Query::<T>::from_query(req.query_string())
+                .map_err(Error::from)
+                .and_then(|query| {
+                    let value = query.into_inner();
+                    validate(&value)?;
+                    Ok(ValidatedQuery(value))
+                }),
+        )
+    }
+}
+
+// Custom rule for role lists. Unknown roles, and typos like `ADMIN`, are rejected.
+pub fn known_roles(roles: &[String]) -> Result<(), ValidationError> {
+    let Some(role) = roles
+        .iter()
+        .find(|role| !constants::KNOWN_ROLES.contains(&role.as_str()))
+    else {
+        return Ok(());
+    };
+    let mut error = ValidationError::new("known_roles").with_message(
+        format!(
+            "roles must be one of [{}]",
+            constants::KNOWN_ROLES.join(", ")
+        )
+        .into(),
+    );
+    error.add_param("value".into(), role);
+    Err(error)
+}
+
+// The object of the validation errors is the type name without its module path.
+pub fn validate<T: Validate>(value: &T) -> Result<(), ApiErrorType> {
+    value.validate().map_err(|err| {
+        let object = std::any::type_name::<T>()
+            .rsplit("::")
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        warn!("Payload validation Error on {}: {}", object, err);
+        ApiErrorType::ValidationError {
+            validation_error: err,
+            object,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct Page {
+        #[validate(range(min = 1, max = 10))]
+        limit: i64,
+    }
+
+    #[actix_web::test]
+    async fn valid_json_is_extracted() {
+        let (req, mut payload) = TestRequest::post()
+            .set_json(serde_json::json!({"limit": 5}))
+            .to_http_parts();
+        let page = ValidatedJson::<Page>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(page.limit, 5);
+    }
+
+    #[actix_web::test]
+    async fn invalid_json_fails_validation() {
+        let (req, mut payload) = TestRequest::post()
+            .set_json(serde_json::json!({"limit": 50}))
+            .to_http_parts();
+        let err = ValidatedJson::<Page>::from_request(&req, &mut payload)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[actix_web::test]
+    async fn unreadable_json_is_answered_like_json() {
+        let (req, mut payload) = TestRequest::post()
+            .insert_header(("content-type", "application/json"))
+            .set_payload("{")
+            .to_http_parts();
+        let err = ValidatedJson::<Page>::from_request(&req, &mut payload)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn query_is_parsed_and_validated() {
+        let req = TestRequest::get().uri("/?limit=3").to_http_request();
+        let page = ValidatedQuery::<Page>::extract(&req).await.unwrap();
+        assert_eq!(page.limit, 3);
+
+        let req = TestRequest::get().uri("/?limit=0").to_http_request();
+        let err = ValidatedQuery::<Page>::extract(&req).await.unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+
+        let req = TestRequest::get().uri("/?limit=ten").to_http_request();
+        let err = ValidatedQuery::<Page>::extract(&req).await.unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn validation_object_is_the_type_name() {
+        match validate(&Page { limit: 0 }) {
+            Err(ApiErrorType::ValidationError { object, .. }) => assert_eq!(object, "Page"),
+            other => panic!("unexpected {:?}", other.map_err(|err| err.to_string())),
+        }
+    }
+
+    #[test]
+    fn roles_must_be_known() {
+        assert!(known_roles(&["ROLE_USER".to_owned(), "ROLE_ADMIN".to_owned()]).is_ok());
+        assert!(known_roles(&[]).is_ok());
+        for role in ["ADMIN", "ROLE_ROOT", "role_admin"] {
+            let err = known_roles(&["ROLE_USER".to_owned(), role.to_owned()]).unwrap_err();
+            assert_eq!(err.code, "known_roles");
+            assert_eq!(err.params["value"], role);
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::env;
 
 use actix_cors::Cors;
 use actix_web::dev::ServiceRequest;
+use actix_web::HttpMessage;
 use actix_web::{
     error::Error, error::InternalError, error::JsonPayloadError, http, web, HttpRequest,
     HttpResponse,
@@ -12,17 +13,26 @@ use actix_web_httpauth::extractors::bearer::BearerAuth;
 use actix_web_httpauth::middleware::HttpAuthentication;
 use chrono::{SecondsFormat, Utc};
 use dotenvy::dotenv;
-use log::{info, warn};
+use futures::future::{self, Either};
+use log::{error, info, warn};
 
 use models::error_model::ApiError;
 
 use crate::auth::claims::Claims;
-use crate::config::db;
+use crate::config::{
+    auth as auth_config, db, graphql as graphql_config, grpc as grpc_config, idempotency, indexes,
+    openapi, password, registration, role_grant as role_grant_config, user as user_config,
+};
+use crate::handler::idempotency_middleware;
+use crate::services::user_event_service::EventSubscribers;
+use crate::services::{auth_service, role_grant_service, user_service};
 
 mod api;
 mod auth;
 mod config;
 mod constants;
+mod graphql;
+mod grpc;
 mod handler;
 mod models;
 mod repository;
@@ -39,6 +49,50 @@ async fn main() -> std::io::Result<()> {
 
     // Initialize MongoDB connection
     let client = db::init().await;
+    indexes::init(&Data::new(client.clone())).await;
+
+    // `repair-links [--dry-run]` fixes broken account and profile links instead of serving.
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("repair-links") {
+        let dry_run = args.iter().any(|arg| arg == "--dry-run");
+        return auth_service::repair_links(&Data::new(client), dry_run)
+            .await
+            .map_err(std::io::Error::other);
+    }
+
+    // Initialize authentication providers used on login.
+    let auth_providers = Data::new(auth_config::init());
+
+    // Initialize registration policy.
+    let registration_policy = Data::new(registration::init());
+
+    // Initialize password policy used on register and password change.
+    let password_policy = Data::new(password::init());
+    let role_grant_settings = Data::new(role_grant_config::init());
+    let user_settings = Data::new(user_config::init());
+    let idempotency_settings = Data::new(idempotency::init());
+    let graphql_settings = graphql_config::init();
+    let openapi_settings = openapi::init();
+    let graphql_schema = Data::new(graphql::schema(
+        &graphql_settings,
+        Data::new(client.clone()),
+        user_settings.clone(),
+    ));
+    let user_event_subscribers =
+        Data::new(EventSubscribers::new(user_settings.events_max_subscribers));
+
+    // Expire temporary role grants in the background.
+    role_grant_service::spawn_expiry_sweeper(
+        Data::new(client.clone()),
+        constants::ROLE_GRANT_SWEEP_INTERVAL_SECS,
+    );
+
+    // Purge soft deleted users after the retention period.
+    user_service::spawn_purge_sweeper(
+        Data::new(client.clone()),
+        user_settings.deleted_retention_days,
+        constants::USER_PURGE_INTERVAL_SECS,
+    );
 
     // Get Server host and port number from environment file.
     let server_host = match env::var("SERVER.HOST") {
@@ -55,8 +109,17 @@ async fn main() -> std::io::Result<()> {
         server_host, server_port
     );
 
+    // gRPC server settings. It shares the database and settings with the HTTP server.
+    let grpc_settings = grpc_config::init();
+    let grpc_server = grpc::serve(
+        grpc_settings,
+        Data::new(client.clone()),
+        auth_providers.clone(),
+        user_settings.clone(),
+    );
+
     // Config and start Actix-web server.
-    HttpServer::new(move || {
+    let http_server = HttpServer::new(move || {
         let auth = HttpAuthentication::bearer(validator);
         App::new()
             // Configure CORS
@@ -70,6 +133,20 @@ async fn main() -> std::io::Result<()> {
                         http::header::AUTHORIZATION,
                         http::header::ACCEPT,
                         http::header::CONTENT_TYPE,
+                        http::header::IF_MATCH,
+                        http::header::IF_NONE_MATCH,
+                        http::header::IF_MODIFIED_SINCE,
+                        http::header::RANGE,
+                        http::header::HeaderName::from_static("last-event-id"),
+                        http::header::HeaderName::from_static(constants::IDEMPOTENCY_KEY_HEADER),
+                    ])
+                    .expose_headers(vec![
+                        http::header::ETAG,
+                        http::header::LAST_MODIFIED,
+                        http::header::CONTENT_RANGE,
+                        http::header::HeaderName::from_static(
+                            constants::IDEMPOTENT_REPLAYED_HEADER,
+                        ),
                     ])
                     .max_age(3600),
             )
@@ -77,18 +154,48 @@ async fn main() -> std::io::Result<()> {
             .wrap(middleware::Compress::default())
             // configure app data
             .app_data(Data::new(client.clone()))
+            .app_data(auth_providers.clone())
+            .app_data(registration_policy.clone())
+            .app_data(password_policy.clone())
+            .app_data(role_grant_settings.clone())
+            .app_data(user_settings.clone())
+            .app_data(user_event_subscribers.clone())
+            .app_data(idempotency_settings.clone())
+            .app_data(graphql_schema.clone())
             .app_data(JsonConfig::default().error_handler(json_error_handler))
             // Configure un-secure controller
             .configure(api::init_auth_api)
             .configure(api::init_ping_api)
             .configure(api::init_location_api)
+            .configure(api::init_openapi_api)
+            .configure(|cfg| {
+                if openapi_settings.docs {
+                    api::openapi_api::init_docs(cfg, &openapi_settings);
+                }
+            })
             // Configure secure controller with prefix '/api'
             .service(
                 web::scope("/api")
+                    // Inside the authentication, which runs first and sets the caller.
+                    .wrap(middleware::from_fn(idempotency_middleware::idempotency))
                     .wrap(auth)
                     .configure(api::init_user_api)
+                    .configure(api::init_account_api)
+                    .configure(api::init_invitation_api)
+                    .configure(api::init_role_grant_api)
                     .configure(api::init_hello_api),
             )
+            // Configure GraphQL endpoint, secured like '/api'
+            .service(
+                web::scope("/graphql")
+                    .wrap(HttpAuthentication::bearer(validator))
+                    .configure(api::init_graphql_api),
+            )
+            .configure(|cfg| {
+                if graphql_settings.graphiql {
+                    api::graphql_api::init_graphiql(cfg);
+                }
+            })
             // configure controller
             .wrap(middleware::DefaultHeaders::new().add(("X-Version", "0.3.0")))
             // enable logger - always register actix-web Logger handler last
@@ -96,8 +203,23 @@ async fn main() -> std::io::Result<()> {
     })
     .bind((server_host, server_port))
     .unwrap_or_else(|_| panic!("error binding to port '{:?}'", stringify!($server_port)))
-    .run()
-    .await
+    .run();
+
+    // The HTTP server handles the shutdown signals. The gRPC server stops with it.
+    // A gRPC server that fails or stops on its own stops the HTTP server and fails the process.
+    let http_handle = http_server.handle();
+    match future::select(http_server, Box::pin(grpc_server)).await {
+        Either::Left((result, _)) => result,
+        Either::Right((result, http_server)) => {
+            let err = result
+                .err()
+                .unwrap_or_else(|| std::io::Error::other("gRPC server stopped"));
+            error!("gRPC server failed: {}", err);
+            http_handle.stop(true).await;
+            http_server.await?;
+            Err(err)
+        }
+    }
 }
 
 // Handle json parser errors.
@@ -137,11 +259,12 @@ async fn validator(
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
     warn!("Validate jwt auth");
-    // We just get permissions from JWT
+    // We get permissions from JWT and keep the claims for handlers that need the caller identity.
     let result = Claims::decode_jwt(credentials.token());
     match result {
         Ok(claims) => {
-            req.attach(claims.permissions);
+            req.attach(claims.permissions.clone());
+            req.extensions_mut().insert(claims);
             Ok(req)
         }
         // required by `actix-web-httpauth` validator signature
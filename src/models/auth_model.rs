@@ -1,6 +1,7 @@
 use bson::serde_helpers::chrono_datetime_as_bson_datetime;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Auth {
@@ -9,14 +10,60 @@ pub struct Auth {
     pub email: String,
     pub first_name: String,
     pub last_name: String,
-    // Password hash using Argon2
+    // Password hash using Argon2. Empty for accounts authenticated by an external provider.
     pub password_hash: String,
+    // Previous password hashes, newest first. Used to refuse reuse of recent passwords.
+    #[serde(default)]
+    pub password_history: Vec<String>,
     // User roles
     pub roles: Vec<String>,
     pub active: bool,
     pub reset_password: bool,
+    // Authentication provider that owns the credentials of this account.
+    #[serde(default)]
+    pub provider: AuthSource,
+    // Id of the linked user profile.
+    #[serde(default)]
+    pub user_id: Option<String>,
     #[serde(with = "chrono_datetime_as_bson_datetime")]
     pub created_ts: DateTime<Utc>,
     #[serde(with = "chrono_datetime_as_bson_datetime")]
     pub updated_ts: DateTime<Utc>,
 }
+
+// Where the credentials of an auth account are verified.
+// Records created before providers existed have no value and default to `Local`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthSource {
+    #[default]
+    Local,
+    Ldap,
+}
+
+// Public part of an auth account. Embedded in user responses with `include=account`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AccountSummary {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub email: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub roles: Vec<String>,
+    pub active: bool,
+    pub provider: AuthSource,
+}
+
+impl From<&Auth> for AccountSummary {
+    fn from(auth: &Auth) -> Self {
+        AccountSummary {
+            id: auth.id.to_owned(),
+            email: auth.email.to_owned(),
+            first_name: auth.first_name.to_owned(),
+            last_name: auth.last_name.to_owned(),
+            roles: auth.roles.to_owned(),
+            active: auth.active,
+            provider: auth.provider,
+        }
+    }
+}
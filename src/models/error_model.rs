@@ -2,6 +2,7 @@ use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
 use chrono::{SecondsFormat, Utc};
 use derive_more::{Display, Error};
 use serde::Serialize;
+use utoipa::ToSchema;
 use validator::ValidationErrors;
 
 // -- Error handing.
@@ -16,10 +17,19 @@ pub enum ApiErrorType {
     #[display(fmt = "User not found for the given ID")]
     UserNotFound,
 
+    #[display(fmt = "Account not found for the given ID")]
+    AccountNotFound,
+
+    #[display(fmt = "User has no avatar")]
+    AvatarNotFound,
+
+    #[display(fmt = "Revision not found")]
+    RevisionNotFound,
+
     #[display(fmt = "Authentication error.")]
     AuthenticationError,
 
-    // Figure out a way to use this when JWT does not have authorization.
+    // JWT is valid but the caller is not allowed to perform the action.
     #[display(fmt = "Authorization error.")]
     AuthorizationError,
 
@@ -31,9 +41,96 @@ pub enum ApiErrorType {
 
     #[display(fmt = "Invalid credential.")]
     InvalidCredential,
+
+    #[display(fmt = "Invalid query parameter.")]
+    InvalidQueryParameter {
+        parameter: String,
+        value: String,
+        message: String,
+    },
+
+    // Lists the content types the endpoint accepts.
+    #[display(fmt = "Unsupported media type.")]
+    UnsupportedMediaType { supported: String },
+
+    #[display(fmt = "Record can not be read.")]
+    MalformedRecord { detail: String },
+
+    #[display(fmt = "Upload is too large.")]
+    ImportTooLarge { max: usize },
+
+    #[display(fmt = "Patch can not be applied.")]
+    InvalidPatch { detail: String },
+
+    #[display(fmt = "Resource was modified concurrently. Try again.")]
+    UpdateConflict,
+
+    #[display(fmt = "Precondition failed.")]
+    PreconditionFailed,
+
+    #[display(fmt = "Precondition required.")]
+    PreconditionRequired,
+
+    #[display(fmt = "Too many operations in the batch.")]
+    BatchTooLarge { max: usize },
+
+    #[display(fmt = "Avatar is too large.")]
+    AvatarTooLarge { max: usize },
+
+    #[display(fmt = "Image can not be read.")]
+    InvalidImage { detail: String },
+
+    #[display(fmt = "Invalid event id.")]
+    InvalidEventId,
+
+    #[display(fmt = "Too many event subscribers.")]
+    TooManySubscribers { max: usize },
+
+    #[display(fmt = "Invalid idempotency key.")]
+    InvalidIdempotencyKey { max: usize },
+
+    #[display(fmt = "Idempotency key was used for a different request.")]
+    IdempotencyKeyReused,
+
+    #[display(fmt = "A request with the same idempotency key is in progress.")]
+    IdempotencyKeyInProgress,
+
+    #[display(fmt = "Self registration is disabled.")]
+    RegistrationDisabled,
+
+    #[display(fmt = "Registration is by invitation only.")]
+    InvitationRequired,
+
+    #[display(fmt = "Invitation code is invalid, expired or already used.")]
+    InvalidInvitation,
+
+    #[display(fmt = "Invitation not found for the given code")]
+    InvitationNotFound,
+
+    #[display(fmt = "Password reset token is invalid or expired.")]
+    InvalidResetToken,
+
+    #[display(fmt = "Email domain is not allowed for registration.")]
+    EmailDomainNotAllowed { domain: String },
+
+    #[display(fmt = "Disposable email addresses are not allowed for registration.")]
+    DisposableEmail { domain: String },
+
+    #[display(fmt = "Role grant not found for the given ID")]
+    RoleGrantNotFound,
+
+    #[display(fmt = "Role grant is already decided.")]
+    RoleGrantNotPending,
+
+    #[display(fmt = "Password does not meet the password policy.")]
+    PasswordPolicyError {
+        object: String,
+        field: String,
+        violations: Vec<String>,
+    },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ValidationError {
     object: String,
     field: String,
@@ -41,7 +138,7 @@ pub struct ValidationError {
     message: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ApiError {
     pub status: u16,
     pub time: String,
@@ -61,6 +158,11 @@ impl ApiErrorType {
                 "Bad request. Missing parameter or wrong payload.".to_owned()
             }
             ApiErrorType::UserNotFound => "User not found for given ID".to_owned(),
+            ApiErrorType::AccountNotFound => "Account not found for given ID".to_owned(),
+            ApiErrorType::AvatarNotFound => "No avatar was uploaded for the user".to_owned(),
+            ApiErrorType::RevisionNotFound => {
+                "User has no revision with the given number".to_owned()
+            }
             ApiErrorType::AuthenticationError => {
                 "User not authenticated. Please reauthenticate and try again.".to_owned()
             }
@@ -71,27 +173,85 @@ impl ApiErrorType {
             ApiErrorType::InvalidCredential => {
                 "Invalid Credential. Checking email address and password".to_owned()
             }
-        }
-    }
-}
-
-// Global error handling with actix-web ResponseError.
-impl ResponseError for ApiErrorType {
-    // Global error handler status code.
-    fn status_code(&self) -> StatusCode {
-        match *self {
-            ApiErrorType::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
-            ApiErrorType::BadRequest => StatusCode::BAD_REQUEST,
-            ApiErrorType::UserNotFound => StatusCode::NOT_FOUND,
-            ApiErrorType::AuthenticationError => StatusCode::UNAUTHORIZED,
-            ApiErrorType::AuthorizationError => StatusCode::FORBIDDEN,
-            ApiErrorType::ValidationError { .. } => StatusCode::UNPROCESSABLE_ENTITY,
-            ApiErrorType::InvalidCredential => StatusCode::UNAUTHORIZED,
+            ApiErrorType::InvalidQueryParameter { parameter, .. } => {
+                format!("Invalid value for query parameter {}", parameter)
+            }
+            ApiErrorType::UnsupportedMediaType { supported } => {
+                format!("Use {} content type.", supported)
+            }
+            ApiErrorType::MalformedRecord { detail } => format!("Malformed record: {}", detail),
+            ApiErrorType::ImportTooLarge { max } => {
+                format!("Upload a file of at most {} bytes.", max)
+            }
+            ApiErrorType::InvalidPatch { detail } => format!("Invalid patch: {}", detail),
+            ApiErrorType::UpdateConflict => {
+                "Resource kept changing while applying the update.".to_owned()
+            }
+            ApiErrorType::PreconditionFailed => {
+                "Resource was changed since it was read. Fetch it again to get the current ETag."
+                    .to_owned()
+            }
+            ApiErrorType::PreconditionRequired => {
+                "Send the ETag of the resource in the If-Match header.".to_owned()
+            }
+            ApiErrorType::BatchTooLarge { max } => {
+                format!(
+                    "Split the request into batches of at most {} operations.",
+                    max
+                )
+            }
+            ApiErrorType::AvatarTooLarge { max } => {
+                format!("Upload an image of at most {} bytes.", max)
+            }
+            ApiErrorType::InvalidImage { detail } => format!("Invalid image: {}", detail),
+            ApiErrorType::InvalidEventId => {
+                "Last-Event-ID must be the id of an event sent by this stream.".to_owned()
+            }
+            ApiErrorType::TooManySubscribers { max } => {
+                format!(
+                    "At most {} event streams can be open at once. Try again later.",
+                    max
+                )
+            }
+            ApiErrorType::InvalidIdempotencyKey { max } => {
+                format!("Idempotency-Key must be 1 to {} visible characters.", max)
+            }
+            ApiErrorType::IdempotencyKeyReused => {
+                "The key was sent before with a different request. Use a new key.".to_owned()
+            }
+            ApiErrorType::IdempotencyKeyInProgress => {
+                "Wait for the first request with the key to finish, then retry.".to_owned()
+            }
+            ApiErrorType::RegistrationDisabled => {
+                "Registration of new users is turned off. Contact an administrator.".to_owned()
+            }
+            ApiErrorType::InvitationRequired => {
+                "A valid invite code is required to register.".to_owned()
+            }
+            ApiErrorType::InvalidInvitation => {
+                "Invite code is unknown, expired, already used or issued for another email."
+                    .to_owned()
+            }
+            ApiErrorType::InvitationNotFound => "Invitation not found for given code".to_owned(),
+            ApiErrorType::InvalidResetToken => {
+                "Reset token is unknown, expired or already used. Request a new one.".to_owned()
+            }
+            ApiErrorType::EmailDomainNotAllowed { domain } => {
+                format!("Email domain {} is not allowed for registration", domain)
+            }
+            ApiErrorType::DisposableEmail { domain } => {
+                format!("Email domain {} is a disposable email provider", domain)
+            }
+            ApiErrorType::RoleGrantNotFound => "Role grant not found for given ID".to_owned(),
+            ApiErrorType::RoleGrantNotPending => {
+                "Only pending role grants can be approved or denied.".to_owned()
+            }
+            ApiErrorType::PasswordPolicyError { .. } => "Password policy error".to_owned(),
         }
     }
 
-    // Global error handler Http Response payload
-    fn error_response(&self) -> HttpResponse {
+    // Error payload, also reported per item by bulk requests.
+    pub fn api_error(&self) -> ApiError {
         let mut validation_sub_errs = vec![];
         match self {
             // Iterate thru validation error object
@@ -110,16 +270,101 @@ impl ResponseError for ApiErrorType {
                     }
                 }
             }
+            // Registration policy errors point at the rejected email domain.
+            ApiErrorType::EmailDomainNotAllowed { domain }
+            | ApiErrorType::DisposableEmail { domain } => {
+                validation_sub_errs.push(ValidationError {
+                    object: "RegisterRequest".to_string(),
+                    field: "email".to_string(),
+                    rejected_value: domain.to_string(),
+                    message: self.to_string(),
+                })
+            }
+            ApiErrorType::InvalidQueryParameter {
+                parameter,
+                value,
+                message,
+            } => validation_sub_errs.push(ValidationError {
+                object: "Query".to_string(),
+                field: parameter.to_string(),
+                rejected_value: value.to_string(),
+                message: message.to_string(),
+            }),
+            // One sub error per broken password rule. The password itself is never echoed back.
+            ApiErrorType::PasswordPolicyError {
+                object,
+                field,
+                violations,
+            } => {
+                for violation in violations {
+                    validation_sub_errs.push(ValidationError {
+                        object: object.to_string(),
+                        field: field.to_string(),
+                        rejected_value: "".to_string(),
+                        message: violation.to_string(),
+                    })
+                }
+            }
             _ => {
                 validation_sub_errs = vec![];
             }
         }
-        HttpResponse::build(self.status_code()).json(ApiError {
+        ApiError {
             status: self.status_code().as_u16(),
             time: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
             message: self.to_string(),
             debug_message: Some(self.debug_message()),
             sub_errors: validation_sub_errs,
-        })
+        }
+    }
+}
+
+// Global error handling with actix-web ResponseError.
+impl ResponseError for ApiErrorType {
+    // Global error handler status code.
+    fn status_code(&self) -> StatusCode {
+        match *self {
+            ApiErrorType::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiErrorType::BadRequest => StatusCode::BAD_REQUEST,
+            ApiErrorType::UserNotFound => StatusCode::NOT_FOUND,
+            ApiErrorType::AccountNotFound => StatusCode::NOT_FOUND,
+            ApiErrorType::AvatarNotFound => StatusCode::NOT_FOUND,
+            ApiErrorType::RevisionNotFound => StatusCode::NOT_FOUND,
+            ApiErrorType::AuthenticationError => StatusCode::UNAUTHORIZED,
+            ApiErrorType::AuthorizationError => StatusCode::FORBIDDEN,
+            ApiErrorType::ValidationError { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiErrorType::InvalidCredential => StatusCode::UNAUTHORIZED,
+            ApiErrorType::InvalidQueryParameter { .. } => StatusCode::BAD_REQUEST,
+            ApiErrorType::UnsupportedMediaType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiErrorType::MalformedRecord { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiErrorType::ImportTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiErrorType::InvalidPatch { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiErrorType::UpdateConflict => StatusCode::CONFLICT,
+            ApiErrorType::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            ApiErrorType::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            ApiErrorType::BatchTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiErrorType::AvatarTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiErrorType::InvalidImage { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiErrorType::InvalidEventId => StatusCode::BAD_REQUEST,
+            ApiErrorType::TooManySubscribers { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ApiErrorType::InvalidIdempotencyKey { .. } => StatusCode::BAD_REQUEST,
+            ApiErrorType::IdempotencyKeyReused => StatusCode::CONFLICT,
+            ApiErrorType::IdempotencyKeyInProgress => StatusCode::CONFLICT,
+            ApiErrorType::RegistrationDisabled => StatusCode::FORBIDDEN,
+            ApiErrorType::InvitationRequired => StatusCode::FORBIDDEN,
+            ApiErrorType::InvalidInvitation => StatusCode::FORBIDDEN,
+            ApiErrorType::InvitationNotFound => StatusCode::NOT_FOUND,
+            ApiErrorType::InvalidResetToken => StatusCode::BAD_REQUEST,
+            ApiErrorType::EmailDomainNotAllowed { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiErrorType::DisposableEmail { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiErrorType::RoleGrantNotFound => StatusCode::NOT_FOUND,
+            ApiErrorType::RoleGrantNotPending => StatusCode::CONFLICT,
+            ApiErrorType::PasswordPolicyError { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    // Global error handler Http Response payload
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.api_error())
     }
 }
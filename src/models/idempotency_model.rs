@@ -0,0 +1,40 @@
+use bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use bson::Binary;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// A POST request sent with an `Idempotency-Key`, and its response once it is done.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    // `<sub>:<key>`, or `anonymous@<client address>:<key>` without a token. Keys of different
+    // callers do not collide.
+    #[serde(rename = "_id")]
+    pub id: String,
+    // Hash of the method, path, query and body of the request.
+    pub fingerprint: String,
+    pub state: IdempotencyState,
+    pub response: Option<StoredResponse>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_ts: DateTime<Utc>,
+    // A pending request is given up after this time, so a crashed request does not hold the
+    // key until it expires.
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub locked_until: DateTime<Utc>,
+    // Removed by the TTL index after this time.
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdempotencyState {
+    Pending,
+    Completed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Binary,
+}
@@ -0,0 +1,22 @@
+use bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Invitation {
+    // Invite code sent to the invited user.
+    #[serde(rename = "_id")]
+    pub id: String,
+    // When set, only this email can register with the code.
+    pub email: Option<String>,
+    // Roles given to the user registered with the code.
+    pub roles: Vec<String>,
+    // Auth id of the admin who created the invitation.
+    pub created_by: String,
+    // Auth id of the user who registered with the code.
+    pub used_by: Option<String>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_ts: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub expires_ts: DateTime<Utc>,
+}
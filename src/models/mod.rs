@@ -1,5 +1,10 @@
 pub mod auth_model;
 pub mod error_model;
+pub mod idempotency_model;
+pub mod invitation_model;
 pub mod location_model;
+pub mod password_reset_model;
+pub mod role_grant_model;
+pub mod user_history_model;
 pub mod user_list_response;
 pub mod user_model;
@@ -0,0 +1,16 @@
+use bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordReset {
+    // SHA-256 of the reset token sent to the user. The token itself is not stored.
+    #[serde(rename = "_id")]
+    pub token_hash: String,
+    // Auth id of the account the token resets.
+    pub auth_id: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_ts: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub expires_ts: DateTime<Utc>,
+}
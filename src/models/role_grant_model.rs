@@ -0,0 +1,79 @@
+use bson::serde_helpers::{
+    chrono_datetime_as_bson_datetime, chrono_datetime_as_bson_datetime_optional,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// Temporary role requested by a user and approved or denied by an admin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleGrant {
+    #[serde(rename = "_id")]
+    pub id: String,
+    // Auth id of the user the role is granted to.
+    pub auth_id: String,
+    pub role: String,
+    pub justification: String,
+    // How long the role is active once approved.
+    pub duration_hours: i64,
+    pub status: GrantStatus,
+    // Auth id of the admin who approved or denied the request.
+    pub decided_by: Option<String>,
+    pub decision_note: Option<String>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub requested_ts: DateTime<Utc>,
+    #[serde(with = "chrono_datetime_as_bson_datetime_optional")]
+    pub decided_ts: Option<DateTime<Utc>>,
+    #[serde(with = "chrono_datetime_as_bson_datetime_optional")]
+    pub expires_ts: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GrantStatus {
+    Pending,
+    Approved,
+    Denied,
+    Expired,
+}
+
+impl RoleGrant {
+    // Approved and not yet expired at the given time.
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        self.status == GrantStatus::Approved && self.expires_ts.is_some_and(|exp| exp > at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn grant(status: GrantStatus, expires_ts: Option<DateTime<Utc>>) -> RoleGrant {
+        RoleGrant {
+            id: "grant".to_owned(),
+            auth_id: "auth".to_owned(),
+            role: "ROLE_AUDITOR".to_owned(),
+            justification: "quarterly audit".to_owned(),
+            duration_hours: 2,
+            status,
+            decided_by: None,
+            decision_note: None,
+            requested_ts: Utc::now(),
+            decided_ts: None,
+            expires_ts,
+        }
+    }
+
+    #[test]
+    fn only_approved_unexpired_grants_are_active() {
+        let now = Utc::now();
+        let later = Some(now + Duration::hours(1));
+        assert!(grant(GrantStatus::Approved, later).is_active(now));
+        assert!(!grant(GrantStatus::Approved, Some(now)).is_active(now));
+        assert!(!grant(GrantStatus::Approved, None).is_active(now));
+        assert!(!grant(GrantStatus::Pending, later).is_active(now));
+        assert!(!grant(GrantStatus::Expired, later).is_active(now));
+    }
+}
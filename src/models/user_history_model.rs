@@ -0,0 +1,141 @@
+use bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::user_model::User;
+
+// One stored version of a user, written by the user repository on every change.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserRevision {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub user_id: String,
+    // Version of the user after the change.
+    pub rev: i64,
+    pub operation: RevisionOperation,
+    // `sub` of the caller that made the change.
+    pub changed_by: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub changed_ts: DateTime<Utc>,
+    // The user as it was after the change.
+    pub user: User,
+    // Fields changed from the previous version.
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionOperation {
+    // The user as it was before its first recorded change.
+    Original,
+    Updated,
+    Deleted,
+    Restored,
+    Reverted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl FieldChange {
+    // Changes of the user fields and the soft delete state between two versions.
+    pub fn between(before: &User, after: &User) -> Vec<FieldChange> {
+        let timestamp = |ts: Option<DateTime<Utc>>| {
+            ts.map(|ts| ts.to_rfc3339_opts(SecondsFormat::Micros, true))
+        };
+        [
+            ("name", Some(before.name.clone()), Some(after.name.clone())),
+            (
+                "location",
+                Some(before.location.clone()),
+                Some(after.location.clone()),
+            ),
+            (
+                "title",
+                Some(before.title.clone()),
+                Some(after.title.clone()),
+            ),
+            (
+                "deleted_at",
+                timestamp(before.deleted_at),
+                timestamp(after.deleted_at),
+            ),
+            (
+                "deleted_by",
+                before.deleted_by.clone(),
+                after.deleted_by.clone(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, from, to)| from != to)
+        .map(|(field, from, to)| FieldChange {
+            field: field.to_owned(),
+            from,
+            to,
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn user() -> User {
+        User {
+            id: Some("usr_1".to_owned()),
+            name: "John Doe".to_owned(),
+            location: "Chennai".to_owned(),
+            title: "Engineer".to_owned(),
+            auth_id: None,
+            version: 1,
+            created_ts: None,
+            created_by: None,
+            updated_ts: None,
+            updated_by: None,
+            deleted_at: None,
+            deleted_by: None,
+        }
+    }
+
+    #[test]
+    fn only_changed_fields_are_listed() {
+        let before = user();
+        let mut after = user();
+        after.title = "Architect".to_owned();
+        after.version = 2;
+        after.updated_by = Some("usr_2".to_owned());
+        let changes = FieldChange::between(&before, &after);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "title");
+        assert_eq!(changes[0].from.as_deref(), Some("Engineer"));
+        assert_eq!(changes[0].to.as_deref(), Some("Architect"));
+        assert!(FieldChange::between(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn soft_delete_and_restore_are_listed() {
+        let before = user();
+        let mut deleted = user();
+        deleted.deleted_at = Some(Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap());
+        deleted.deleted_by = Some("usr_2".to_owned());
+        let changes = FieldChange::between(&before, &deleted);
+        let fields: Vec<&str> = changes.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(fields, vec!["deleted_at", "deleted_by"]);
+        assert_eq!(
+            changes[0].to.as_deref(),
+            Some("2026-01-02T03:04:05.000000Z")
+        );
+
+        let restored = FieldChange::between(&deleted, &before);
+        assert_eq!(restored[1].from.as_deref(), Some("usr_2"));
+        assert_eq!(restored[1].to, None);
+    }
+}
@@ -1,39 +1,83 @@
-use serde::Serialize;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Serialize)]
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::auth_model::AccountSummary;
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct Users {
     pub id: String,
     pub name: String,
     pub location: String,
     pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_id: Option<String>,
+    // Linked auth account. Only set with `include=account`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<AccountSummary>,
+    // Text search relevance. Only set by search.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    // Fields with the matched words wrapped in `<em>`. Only set by search with `highlight`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlights: Option<BTreeMap<String, String>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct UserListResponse {
     pub data: Vec<Users>,
     pub meta: Meta,
     pub _link: Link,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct Meta {
-    pub offset: u64,
+    // Not set for cursor pagination.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
     pub limit: i64,
-    pub total_results: u64,
+    // Not set when counting is turned off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_results: Option<u64>,
+    pub count: CountMode,
     pub search_criteria: Option<String>,
     pub sort_by: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+// How `total_results` is counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMode {
+    // Count documents matching the filters. Slow on large collections.
+    Exact,
+    // Collection size from metadata. Fast, but ignores the filters.
+    Estimated,
+    // Do not count.
+    None,
+}
+
+impl CountMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CountMode::Exact => "exact",
+            CountMode::Estimated => "estimated",
+            CountMode::None => "none",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct Link {
     pub first: LinkHref,
-    pub last: LinkHref,
+    // Not set when the total is unknown or for cursor pagination.
+    pub last: Option<LinkHref>,
     pub previous: Option<LinkHref>,
     pub next: Option<LinkHref>,
     pub self_link: LinkHref,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct LinkHref {
     pub href: String,
 }
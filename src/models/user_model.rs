@@ -1,17 +1,79 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize, Validate)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
     pub id: Option<String>,
     #[validate(length(min = 2, message = "Name must be have minimum of 3 characters"))]
+    #[schema(min_length = 2)]
     pub name: String,
     #[validate(length(
         min = 2,
         max = 15,
         message = "Location character length between 2 and 15"
     ))]
+    #[schema(min_length = 2, max_length = 15)]
     pub location: String,
     pub title: String,
+    // Id of the linked auth account. Not set for profiles created through the user API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub auth_id: Option<String>,
+    // Incremented on every write. Sent as the `ETag` of the user.
+    #[serde(default)]
+    #[schema(read_only)]
+    pub version: i64,
+    // Audit fields, maintained by the user repository. `*_by` is the `sub` of the caller.
+    #[serde(default, with = "timestamp", skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub created_ts: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub created_by: Option<String>,
+    // Time of the last write. Sent as the `Last-Modified` of the user.
+    #[serde(default, with = "timestamp", skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub updated_ts: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub updated_by: Option<String>,
+    // Set when the user is soft deleted. Deleted users are hidden until restored or purged.
+    #[serde(default, with = "timestamp", skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(read_only)]
+    pub deleted_by: Option<String>,
+}
+
+// Timestamps are BSON dates in Mongo. Request bodies may echo the RFC 3339 string of a
+// response, which is accepted and ignored by the writes.
+mod timestamp {
+    use bson::serde_helpers::chrono_datetime_as_bson_datetime_optional;
+    use bson::Bson;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        chrono_datetime_as_bson_datetime_optional::serialize(value, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(match Option::<Bson>::deserialize(deserializer)? {
+            Some(Bson::DateTime(value)) => Some(value.to_chrono()),
+            Some(Bson::String(value)) => DateTime::parse_from_rfc3339(&value)
+                .ok()
+                .map(|value| value.with_timezone(&Utc)),
+            _ => None,
+        })
+    }
 }
@@ -0,0 +1,254 @@
+use actix_web::web::Data;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson},
+    error::Error,
+    Client, ClientSession, Collection,
+};
+
+use crate::repository::{transaction, user_repo};
+use crate::{
+    constants,
+    models::{auth_model::Auth, user_model::User},
+};
+
+// Writes spanning the auth and user collections. They run in a multi-document transaction,
+// which needs Mongo running as a replica set.
+
+// Store an auth account and its linked user profile together.
+pub async fn register_with_profile(
+    client: &Data<Client>,
+    auth: &Auth,
+    profile: &User,
+) -> Result<(), Error> {
+    transaction::run(
+        client,
+        (client, auth, profile),
+        |session, (client, auth, profile)| Box::pin(insert_account(client, session, auth, profile)),
+    )
+    .await
+}
+
+// Store imported auth accounts and their linked user profiles together.
+pub async fn import_with_profiles(
+    client: &Data<Client>,
+    accounts: &[Auth],
+    profiles: &[User],
+) -> Result<usize, Error> {
+    transaction::run(
+        client,
+        (client, accounts, profiles),
+        |session, (client, accounts, profiles)| {
+            Box::pin(insert_accounts(client, session, accounts, profiles))
+        },
+    )
+    .await
+}
+
+// Add a linked user profile to an existing auth account that has none.
+pub async fn link_profile(
+    client: &Data<Client>,
+    auth_id: &str,
+    profile: &User,
+) -> Result<(), Error> {
+    transaction::run(
+        client,
+        (client, auth_id, profile),
+        |session, (client, auth_id, profile)| {
+            Box::pin(insert_link(client, session, auth_id, profile))
+        },
+    )
+    .await
+}
+
+// Delete an auth account and soft delete its linked user profile.
+// Returns false when there is no account with the id.
+pub async fn delete_with_profile(
+    client: &Data<Client>,
+    auth_id: &str,
+    deleted_by: &str,
+) -> Result<bool, Error> {
+    transaction::run(
+        client,
+        (client, auth_id, deleted_by),
+        |session, (client, auth_id, deleted_by)| {
+            Box::pin(remove_account(client, session, auth_id, deleted_by))
+        },
+    )
+    .await
+}
+
+// Fetch auth accounts that no live user profile points back to.
+pub async fn get_accounts_without_profile(client: &Data<Client>) -> Result<Vec<Auth>, Error> {
+    let pipeline = vec![
+        doc! {"$lookup": {
+            "from": constants::MONGO_USER_COLLECTION,
+            "localField": "_id",
+            "foreignField": "auth_id",
+            "as": "profiles",
+        }},
+        doc! {"$match": {"profiles": {"$not": {"$elemMatch": {"deleted_at": Bson::Null}}}}},
+        doc! {"$project": {"profiles": 0}},
+    ];
+    let collection: Collection<Auth> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_AUTH_COLLECTION);
+    let cursor = collection.aggregate(pipeline, None).await?;
+    cursor
+        .try_filter_map(|document| async move { Ok(bson::from_document::<Auth>(document).ok()) })
+        .try_collect()
+        .await
+}
+
+// Fetch live user profiles whose auth account no longer exists.
+pub async fn get_orphaned_profiles(client: &Data<Client>) -> Result<Vec<User>, Error> {
+    let pipeline = vec![
+        doc! {"$match": {"auth_id": {"$type": "string"}, "deleted_at": Bson::Null}},
+        doc! {"$lookup": {
+            "from": constants::MONGO_AUTH_COLLECTION,
+            "localField": "auth_id",
+            "foreignField": "_id",
+            "as": "accounts",
+        }},
+        doc! {"$match": {"accounts": {"$size": 0}}},
+        doc! {"$project": {"accounts": 0}},
+    ];
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    let cursor = collection.aggregate(pipeline, None).await?;
+    cursor
+        .try_filter_map(|document| async move { Ok(bson::from_document::<User>(document).ok()) })
+        .try_collect()
+        .await
+}
+
+// Fetch (auth id, user id) pairs where a live profile references an auth account that does
+// not reference it back.
+pub async fn get_missing_user_ids(client: &Data<Client>) -> Result<Vec<(String, String)>, Error> {
+    let pipeline = vec![
+        doc! {"$lookup": {
+            "from": constants::MONGO_USER_COLLECTION,
+            "localField": "_id",
+            "foreignField": "auth_id",
+            "as": "profiles",
+        }},
+        doc! {"$unwind": "$profiles"},
+        doc! {"$match": {"profiles.deleted_at": Bson::Null}},
+        doc! {"$match": {"$expr": {"$ne": ["$user_id", "$profiles._id"]}}},
+        doc! {"$project": {"_id": 1, "user_id": "$profiles._id"}},
+    ];
+    let collection: Collection<Auth> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_AUTH_COLLECTION);
+    let cursor = collection.aggregate(pipeline, None).await?;
+    cursor
+        .try_filter_map(|document| async move {
+            Ok(
+                match (document.get_str("_id"), document.get_str("user_id")) {
+                    (Ok(auth_id), Ok(user_id)) => Some((auth_id.to_owned(), user_id.to_owned())),
+                    _ => None,
+                },
+            )
+        })
+        .try_collect()
+        .await
+}
+
+// Set the user profile of an auth account.
+pub async fn set_user_id(
+    client: &Data<Client>,
+    auth_id: &str,
+    user_id: &str,
+) -> Result<u64, Error> {
+    let collection: Collection<Auth> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_AUTH_COLLECTION);
+    let update = doc! {"$set": {"user_id": user_id, "updated_ts": Utc::now()}};
+    collection
+        .update_one(doc! {"_id": auth_id}, update, None)
+        .await
+        .map(|result| result.modified_count)
+}
+
+async fn insert_account(
+    client: &Data<Client>,
+    session: &mut ClientSession,
+    auth: &Auth,
+    profile: &User,
+) -> Result<(), Error> {
+    let database = client.database(constants::MONGO_DATABASE);
+    database
+        .collection::<Auth>(constants::MONGO_AUTH_COLLECTION)
+        .insert_one_with_session(auth, None, session)
+        .await?;
+    database
+        .collection::<User>(constants::MONGO_USER_COLLECTION)
+        .insert_one_with_session(profile, None, session)
+        .await?;
+    Ok(())
+}
+
+async fn insert_accounts(
+    client: &Data<Client>,
+    session: &mut ClientSession,
+    accounts: &[Auth],
+    profiles: &[User],
+) -> Result<usize, Error> {
+    let database = client.database(constants::MONGO_DATABASE);
+    let result = database
+        .collection::<Auth>(constants::MONGO_AUTH_COLLECTION)
+        .insert_many_with_session(accounts, None, session)
+        .await?;
+    database
+        .collection::<User>(constants::MONGO_USER_COLLECTION)
+        .insert_many_with_session(profiles, None, session)
+        .await?;
+    Ok(result.inserted_ids.len())
+}
+
+async fn insert_link(
+    client: &Data<Client>,
+    session: &mut ClientSession,
+    auth_id: &str,
+    profile: &User,
+) -> Result<(), Error> {
+    let database = client.database(constants::MONGO_DATABASE);
+    database
+        .collection::<User>(constants::MONGO_USER_COLLECTION)
+        .insert_one_with_session(profile, None, session)
+        .await?;
+    let update = doc! {"$set": {"user_id": &profile.id, "updated_ts": Utc::now()}};
+    database
+        .collection::<Auth>(constants::MONGO_AUTH_COLLECTION)
+        .update_one_with_session(doc! {"_id": auth_id}, update, None, session)
+        .await?;
+    Ok(())
+}
+
+async fn remove_account(
+    client: &Data<Client>,
+    session: &mut ClientSession,
+    auth_id: &str,
+    deleted_by: &str,
+) -> Result<bool, Error> {
+    let database = client.database(constants::MONGO_DATABASE);
+    let auth = database
+        .collection::<Auth>(constants::MONGO_AUTH_COLLECTION)
+        .find_one_and_delete_with_session(doc! {"_id": auth_id}, None, session)
+        .await?;
+    let Some(auth) = auth else {
+        return Ok(false);
+    };
+    // Profiles are linked both ways, the user repository follows either side.
+    user_repo::delete_linked_users(
+        client,
+        session,
+        auth_id,
+        auth.user_id.as_deref(),
+        deleted_by,
+    )
+    .await?;
+    Ok(true)
+}
@@ -1,21 +1,12 @@
 use actix_web::web::Data;
 use bson::doc;
-use mongodb::results::InsertOneResult;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::results::UpdateResult;
 use mongodb::{error::Error, Client, Collection};
 
 use crate::{constants, models::auth_model::Auth};
 
-// Add a user to auth table with hash password.
-pub async fn auth_register(
-    client: &Data<Client>,
-    register_user: Auth,
-) -> Result<InsertOneResult, Error> {
-    let collection = client
-        .database(constants::MONGO_DATABASE)
-        .collection(constants::MONGO_AUTH_COLLECTION);
-    collection.insert_one(register_user, None).await
-}
-
 // Check if user with email already esitst in auth table or not.
 pub async fn check_email(client: &Data<Client>, email: &String) -> bool {
     let collection: Collection<Auth> = client
@@ -36,8 +27,82 @@ pub async fn fetch_by_email(client: &Data<Client>, email: &String) -> Option<Aut
         .database(constants::MONGO_DATABASE)
         .collection(constants::MONGO_AUTH_COLLECTION);
     let auth = collection.find_one(doc! {"email": email}, None).await;
-    match auth {
-        Ok(a) => a,
-        Err(_) => None,
-    }
+    auth.unwrap_or_default()
+}
+
+// Fetch user from auth table by unique auth id.
+pub async fn fetch_by_id(client: &Data<Client>, id: &String) -> Result<Option<Auth>, Error> {
+    let collection: Collection<Auth> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_AUTH_COLLECTION);
+    collection.find_one(doc! {"_id": id}, None).await
+}
+
+// Fetch the auth users with the given ids.
+pub async fn fetch_by_ids(client: &Data<Client>, ids: &[String]) -> Result<Vec<Auth>, Error> {
+    let collection: Collection<Auth> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_AUTH_COLLECTION);
+    collection
+        .find(doc! {"_id": {"$in": ids}}, None)
+        .await?
+        .try_collect()
+        .await
+}
+
+// Update name and roles of an auth user synced from an external provider.
+pub async fn update_profile(client: &Data<Client>, auth: &Auth) -> Result<UpdateResult, Error> {
+    let collection: Collection<Auth> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_AUTH_COLLECTION);
+    let update = doc! {
+        "$set": {
+            "first_name": &auth.first_name,
+            "last_name": &auth.last_name,
+            "roles": &auth.roles,
+            "updated_ts": auth.updated_ts,
+        }
+    };
+    collection
+        .update_one(doc! {"_id": &auth.id}, update, None)
+        .await
+}
+
+// Replace the password hash of an auth user.
+pub async fn update_password_hash(
+    client: &Data<Client>,
+    id: &String,
+    password_hash: &String,
+) -> Result<UpdateResult, Error> {
+    let collection: Collection<Auth> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_AUTH_COLLECTION);
+    let update = doc! {
+        "$set": {
+            "password_hash": password_hash,
+            "updated_ts": Utc::now(),
+        }
+    };
+    collection.update_one(doc! {"_id": id}, update, None).await
+}
+
+// Set a new password hash and keep the previous hashes as password history.
+pub async fn update_password(
+    client: &Data<Client>,
+    id: &String,
+    password_hash: &String,
+    password_history: &Vec<String>,
+) -> Result<UpdateResult, Error> {
+    let collection: Collection<Auth> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_AUTH_COLLECTION);
+    let update = doc! {
+        "$set": {
+            "password_hash": password_hash,
+            "password_history": password_history,
+            "reset_password": false,
+            "updated_ts": Utc::now(),
+        }
+    };
+    collection.update_one(doc! {"_id": id}, update, None).await
 }
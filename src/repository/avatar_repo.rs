@@ -0,0 +1,103 @@
+use actix_web::web::Data;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::Error;
+use mongodb::gridfs::FilesCollectionDocument;
+use mongodb::options::{GridFsBucketOptions, GridFsFindOptions, GridFsUploadOptions};
+use mongodb::{Client, GridFsBucket, GridFsDownloadStream};
+use nanoid::nanoid;
+
+use crate::constants;
+
+// Avatars are GridFS files. The metadata keeps the user id, the variant (`original` or
+// `thumbnail`), the content type and the ETag of the content.
+fn bucket(client: &Data<Client>) -> GridFsBucket {
+    let options = GridFsBucketOptions::builder()
+        .bucket_name(constants::MONGO_AVATAR_BUCKET.to_owned())
+        .build();
+    client
+        .database(constants::MONGO_DATABASE)
+        .gridfs_bucket(options)
+}
+
+// Store one variant of an avatar. Returns the id of the new file.
+pub async fn upload(
+    client: &Data<Client>,
+    user_id: &str,
+    variant: &str,
+    content_type: &str,
+    etag: &str,
+    content: &[u8],
+) -> Result<String, Error> {
+    let id = nanoid!();
+    let options = GridFsUploadOptions::builder()
+        .metadata(doc! {
+            "user_id": user_id,
+            "variant": variant,
+            "content_type": content_type,
+            "etag": etag,
+        })
+        .build();
+    bucket(client)
+        .upload_from_futures_0_3_reader_with_id(
+            Bson::String(id.to_owned()),
+            format!("{}-{}", user_id, variant),
+            content,
+            options,
+        )
+        .await?;
+    Ok(id)
+}
+
+// Find the newest file of an avatar variant.
+pub async fn find(
+    client: &Data<Client>,
+    user_id: &str,
+    variant: &str,
+) -> Result<Option<FilesCollectionDocument>, Error> {
+    let options = GridFsFindOptions::builder()
+        .sort(doc! {"uploadDate": -1})
+        .limit(1)
+        .build();
+    let filter = doc! {"metadata.user_id": user_id, "metadata.variant": variant};
+    bucket(client).find(filter, options).await?.try_next().await
+}
+
+pub async fn open(client: &Data<Client>, id: Bson) -> Result<GridFsDownloadStream, Error> {
+    bucket(client).open_download_stream(id).await
+}
+
+// Ids of the avatar files of a user.
+pub async fn file_ids(client: &Data<Client>, user_id: &str) -> Result<Vec<String>, Error> {
+    let files: Vec<FilesCollectionDocument> = bucket(client)
+        .find(doc! {"metadata.user_id": user_id}, None)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(files
+        .into_iter()
+        .filter_map(|file| file.id.as_str().map(str::to_owned))
+        .collect())
+}
+
+// Delete the avatar files of a user. Returns the number deleted.
+pub async fn delete_all(client: &Data<Client>, user_id: &str) -> Result<u64, Error> {
+    delete_matching(client, doc! {"metadata.user_id": user_id}).await
+}
+
+// Delete avatar files by id.
+pub async fn delete_files(client: &Data<Client>, ids: &[String]) -> Result<u64, Error> {
+    delete_matching(client, doc! {"_id": {"$in": ids}}).await
+}
+
+async fn delete_matching(client: &Data<Client>, filter: Document) -> Result<u64, Error> {
+    let bucket = bucket(client);
+    let files: Vec<FilesCollectionDocument> =
+        bucket.find(filter, None).await?.try_collect().await?;
+    let mut deleted = 0;
+    for file in files {
+        bucket.delete(file.id).await?;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
@@ -0,0 +1,105 @@
+use actix_web::web::Data;
+use chrono::{DateTime, Utc};
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+use mongodb::options::UpdateOptions;
+use mongodb::{
+    bson::{doc, to_bson, Bson},
+    Client, Collection,
+};
+
+use crate::constants;
+use crate::models::idempotency_model::{IdempotencyRecord, IdempotencyState, StoredResponse};
+
+// Mongo error code of a unique index violation.
+const DUPLICATE_KEY: i32 = 11000;
+const MAX_CLAIM_ATTEMPTS: u32 = 3;
+
+// Take a key for a new request. A key is free when it is unknown or expired, or when its
+// request with the same fingerprint stopped holding it. Returns the stored record when the
+// key is taken.
+pub async fn claim(
+    client: &Data<Client>,
+    id: &str,
+    fingerprint: &str,
+    locked_until: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+) -> Result<Option<IdempotencyRecord>, Error> {
+    let now = Utc::now();
+    let filter = doc! {
+        "_id": id,
+        "$or": [
+            {"expires_at": {"$lte": now}},
+            {
+                "state": to_bson(&IdempotencyState::Pending)?,
+                "fingerprint": fingerprint,
+                "locked_until": {"$lte": now},
+            },
+        ],
+    };
+    let update = doc! {"$set": {
+        "fingerprint": fingerprint,
+        "state": to_bson(&IdempotencyState::Pending)?,
+        "response": Bson::Null,
+        "created_ts": now,
+        "locked_until": locked_until,
+        "expires_at": expires_at,
+    }};
+    let options = UpdateOptions::builder().upsert(true).build();
+    let collection: Collection<IdempotencyRecord> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_IDEMPOTENCY_COLLECTION);
+    let mut attempt = 1;
+    loop {
+        match collection
+            .update_one(filter.clone(), update.clone(), options.clone())
+            .await
+        {
+            Ok(_) => return Ok(None),
+            // The upsert collides with the `_id` of a live key. When the key is removed by the
+            // TTL index before it is read, it is free again and the claim is tried once more.
+            Err(err) if is_duplicate_key(&err) => {
+                match collection.find_one(doc! {"_id": id}, None).await? {
+                    Some(record) => return Ok(Some(record)),
+                    None if attempt < MAX_CLAIM_ATTEMPTS => attempt += 1,
+                    None => return Err(err),
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Store the response of a claimed key for replay.
+pub async fn complete(
+    client: &Data<Client>,
+    id: &str,
+    response: &StoredResponse,
+) -> Result<(), Error> {
+    let filter = doc! {"_id": id, "state": to_bson(&IdempotencyState::Pending)?};
+    let update = doc! {"$set": {
+        "state": to_bson(&IdempotencyState::Completed)?,
+        "response": to_bson(response)?,
+    }};
+    let collection: Collection<IdempotencyRecord> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_IDEMPOTENCY_COLLECTION);
+    collection.update_one(filter, update, None).await?;
+    Ok(())
+}
+
+// Free a claimed key without a response, so the request can be retried.
+pub async fn release(client: &Data<Client>, id: &str) -> Result<(), Error> {
+    let filter = doc! {"_id": id, "state": to_bson(&IdempotencyState::Pending)?};
+    let collection: Collection<IdempotencyRecord> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_IDEMPOTENCY_COLLECTION);
+    collection.delete_one(filter, None).await?;
+    Ok(())
+}
+
+fn is_duplicate_key(err: &Error) -> bool {
+    matches!(
+        err.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == DUPLICATE_KEY
+    )
+}
@@ -0,0 +1,18 @@
+use actix_web::web::Data;
+use mongodb::{
+    bson::Document, error::Error, results::CreateIndexResult, Client, Collection, IndexModel,
+};
+
+use crate::constants;
+
+// Create an index on the given collection. Existing indexes with the same definition are kept.
+pub async fn create_index(
+    client: &Data<Client>,
+    collection_name: &str,
+    index: IndexModel,
+) -> Result<CreateIndexResult, Error> {
+    let collection: Collection<Document> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(collection_name);
+    collection.create_index(index, None).await
+}
@@ -0,0 +1,80 @@
+use actix_web::web::Data;
+use bson::doc;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
+use mongodb::{error::Error, Client, Collection};
+
+use crate::{constants, models::invitation_model::Invitation};
+
+// Add a new invitation.
+pub async fn create_invitation(
+    client: &Data<Client>,
+    invitation: &Invitation,
+) -> Result<InsertOneResult, Error> {
+    let collection: Collection<Invitation> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_INVITATION_COLLECTION);
+    collection.insert_one(invitation, None).await
+}
+
+// Mark an unused and unexpired invitation as used by the given auth id.
+// Returns `None` when the code can not be redeemed for the email.
+pub async fn redeem(
+    client: &Data<Client>,
+    code: &String,
+    email: &str,
+    auth_id: &String,
+) -> Result<Option<Invitation>, Error> {
+    let collection: Collection<Invitation> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_INVITATION_COLLECTION);
+    let filter = doc! {
+        "_id": code,
+        "used_by": null,
+        "expires_ts": {"$gt": Utc::now()},
+        "$or": [{"email": null}, {"email": email.to_lowercase()}],
+    };
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+    collection
+        .find_one_and_update(filter, doc! {"$set": {"used_by": auth_id}}, options)
+        .await
+}
+
+// Make a redeemed invitation usable again after the registration failed.
+pub async fn release(client: &Data<Client>, code: &String) -> Result<UpdateResult, Error> {
+    let collection: Collection<Invitation> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_INVITATION_COLLECTION);
+    collection
+        .update_one(doc! {"_id": code}, doc! {"$set": {"used_by": null}}, None)
+        .await
+}
+
+// Fetch all invitations that are not used and not expired.
+pub async fn get_pending_invitations(client: &Data<Client>) -> Result<Vec<Invitation>, Error> {
+    let collection: Collection<Invitation> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_INVITATION_COLLECTION);
+    let filter = doc! {"used_by": null, "expires_ts": {"$gt": Utc::now()}};
+    let find_options = FindOptions::builder().sort(doc! {"created_ts": -1}).build();
+    collection
+        .find(filter, find_options)
+        .await?
+        .try_collect()
+        .await
+}
+
+// Delete an invitation for the given code.
+pub async fn delete_invitation(
+    client: &Data<Client>,
+    code: &String,
+) -> Result<DeleteResult, Error> {
+    let collection: Collection<Invitation> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_INVITATION_COLLECTION);
+    collection.delete_one(doc! {"_id": code}, None).await
+}
@@ -1,2 +1,11 @@
+pub mod account_repo;
 pub mod auth_repo;
+pub mod avatar_repo;
+pub mod idempotency_repo;
+pub mod index_repo;
+pub mod invitation_repo;
+pub mod password_reset_repo;
+pub mod role_grant_repo;
+pub mod transaction;
+pub mod user_history_repo;
 pub mod user_repo;
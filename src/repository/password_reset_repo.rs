@@ -0,0 +1,35 @@
+use actix_web::web::Data;
+use bson::doc;
+use chrono::Utc;
+use mongodb::results::InsertOneResult;
+use mongodb::{error::Error, Client, Collection};
+use sha2::{Digest, Sha256};
+
+use crate::{constants, models::password_reset_model::PasswordReset};
+
+// Add a reset token for an account. Earlier tokens of the account stop working.
+pub async fn create_reset(
+    client: &Data<Client>,
+    reset: &PasswordReset,
+) -> Result<InsertOneResult, Error> {
+    let collection: Collection<PasswordReset> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_PASSWORD_RESET_COLLECTION);
+    collection
+        .delete_many(doc! {"auth_id": &reset.auth_id}, None)
+        .await?;
+    collection.insert_one(reset, None).await
+}
+
+// Remove and return an unexpired reset token, so it can only be used once.
+pub async fn redeem(client: &Data<Client>, token: &str) -> Result<Option<PasswordReset>, Error> {
+    let collection: Collection<PasswordReset> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_PASSWORD_RESET_COLLECTION);
+    let filter = doc! {"_id": token_hash(token), "expires_ts": {"$gt": Utc::now()}};
+    collection.find_one_and_delete(filter, None).await
+}
+
+pub fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
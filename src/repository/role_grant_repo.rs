@@ -0,0 +1,105 @@
+use actix_web::web::Data;
+use bson::doc;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::options::FindOptions;
+use mongodb::results::{InsertOneResult, UpdateResult};
+use mongodb::{error::Error, Client, Collection};
+
+use crate::constants;
+use crate::models::role_grant_model::{GrantStatus, RoleGrant};
+
+// Add a new role grant request.
+pub async fn create_grant(
+    client: &Data<Client>,
+    grant: &RoleGrant,
+) -> Result<InsertOneResult, Error> {
+    let collection: Collection<RoleGrant> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_ROLE_GRANT_COLLECTION);
+    collection.insert_one(grant, None).await
+}
+
+// Get a role grant by given id.
+pub async fn get_grant(client: &Data<Client>, id: &String) -> Result<Option<RoleGrant>, Error> {
+    let collection: Collection<RoleGrant> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_ROLE_GRANT_COLLECTION);
+    collection.find_one(doc! {"_id": id}, None).await
+}
+
+// Fetch role grants, optionally only with the given status. Newest first.
+pub async fn get_grants(
+    client: &Data<Client>,
+    status: Option<GrantStatus>,
+) -> Result<Vec<RoleGrant>, Error> {
+    let collection: Collection<RoleGrant> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_ROLE_GRANT_COLLECTION);
+    let filter = match status {
+        Some(s) => doc! {"status": bson::to_bson(&s)?},
+        None => doc! {},
+    };
+    let find_options = FindOptions::builder()
+        .sort(doc! {"requested_ts": -1})
+        .build();
+    collection
+        .find(filter, find_options)
+        .await?
+        .try_collect()
+        .await
+}
+
+// Fetch approved and unexpired role grants of an auth user.
+pub async fn get_active_grants(
+    client: &Data<Client>,
+    auth_id: &String,
+) -> Result<Vec<RoleGrant>, Error> {
+    let collection: Collection<RoleGrant> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_ROLE_GRANT_COLLECTION);
+    let filter = doc! {
+        "auth_id": auth_id,
+        "status": bson::to_bson(&GrantStatus::Approved)?,
+        "expires_ts": {"$gt": Utc::now()},
+    };
+    collection.find(filter, None).await?.try_collect().await
+}
+
+// Approve or deny a role grant. Only pending requests are updated.
+pub async fn decide_grant(
+    client: &Data<Client>,
+    id: &String,
+    status: GrantStatus,
+    decided_by: &String,
+    decision_note: Option<String>,
+    expires_ts: Option<DateTime<Utc>>,
+) -> Result<UpdateResult, Error> {
+    let collection: Collection<RoleGrant> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_ROLE_GRANT_COLLECTION);
+    let filter = doc! {"_id": id, "status": bson::to_bson(&GrantStatus::Pending)?};
+    let update = doc! {
+        "$set": {
+            "status": bson::to_bson(&status)?,
+            "decided_by": decided_by,
+            "decision_note": decision_note,
+            "decided_ts": Utc::now(),
+            "expires_ts": expires_ts,
+        }
+    };
+    collection.update_one(filter, update, None).await
+}
+
+// Mark approved role grants past their expiry as expired.
+pub async fn expire_grants(client: &Data<Client>) -> Result<UpdateResult, Error> {
+    let collection: Collection<RoleGrant> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_ROLE_GRANT_COLLECTION);
+    let filter = doc! {
+        "status": bson::to_bson(&GrantStatus::Approved)?,
+        "expires_ts": {"$lte": Utc::now()},
+    };
+    let update = doc! {"$set": {"status": bson::to_bson(&GrantStatus::Expired)?}};
+    collection.update_many(filter, update, None).await
+}
@@ -0,0 +1,55 @@
+use actix_web::web::Data;
+use futures::future::BoxFuture;
+use mongodb::error::{Error, TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use mongodb::{Client, ClientSession};
+
+use crate::constants;
+
+// Multi-document transactions. They need Mongo running as a replica set.
+
+// Run the writes in a transaction and commit them. A write error aborts the transaction.
+// The whole transaction is run again on transient errors, like a replica set election.
+// Data borrowed by the writes is passed in `context`, like `ClientSession::with_transaction`.
+pub async fn run<C, T, F>(client: &Data<Client>, mut context: C, mut writes: F) -> Result<T, Error>
+where
+    F: for<'s> FnMut(&'s mut ClientSession, &'s mut C) -> BoxFuture<'s, Result<T, Error>>,
+{
+    let mut session = client.start_session(None).await?;
+    let mut attempt = 1;
+    loop {
+        session.start_transaction(None).await?;
+        let result = match writes(&mut session, &mut context).await {
+            Ok(value) => commit(&mut session).await.map(|_| value),
+            Err(err) => {
+                session.abort_transaction().await.ok();
+                Err(err)
+            }
+        };
+        match result {
+            Err(err) if is_transient(&err) && attempt < constants::MAX_TRANSACTION_ATTEMPTS => {
+                attempt += 1
+            }
+            result => return result,
+        }
+    }
+}
+
+// Commit, retrying while the outcome of the commit is unknown.
+async fn commit(session: &mut ClientSession) -> Result<(), Error> {
+    let mut attempt = 1;
+    loop {
+        match session.commit_transaction().await {
+            Err(err)
+                if err.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT)
+                    && attempt < constants::MAX_TRANSACTION_ATTEMPTS =>
+            {
+                attempt += 1
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(err: &Error) -> bool {
+    err.contains_label(TRANSIENT_TRANSACTION_ERROR)
+}
@@ -0,0 +1,117 @@
+use actix_web::web::Data;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::{
+    bson::{doc, to_bson},
+    error::Error,
+    Client, ClientSession, Collection,
+};
+use nanoid::nanoid;
+
+use crate::constants;
+use crate::models::user_history_model::{FieldChange, RevisionOperation, UserRevision};
+use crate::models::user_model::User;
+
+// Record a change of a user. The version before the change is stored too, unless it already
+// is, so the history starts with the user as it was before its first recorded change.
+pub async fn record(
+    client: &Data<Client>,
+    session: &mut ClientSession,
+    before: &User,
+    after: &User,
+    operation: RevisionOperation,
+    changed_by: &str,
+) -> Result<(), Error> {
+    let collection: Collection<UserRevision> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_HISTORY_COLLECTION);
+    let user_id = after.id.clone().unwrap_or_default();
+    let upsert = UpdateOptions::builder().upsert(true).build();
+
+    let original = doc! {"$setOnInsert": {
+        "_id": nanoid!(),
+        "operation": to_bson(&RevisionOperation::Original)?,
+        "changed_by": before
+            .updated_by
+            .as_ref()
+            .or(before.created_by.as_ref())
+            .cloned()
+            .unwrap_or_default(),
+        "changed_ts": before.updated_ts.or(before.created_ts).unwrap_or_else(Utc::now),
+        "user": to_bson(before)?,
+        "changes": [],
+    }};
+    collection
+        .update_one_with_session(
+            doc! {"user_id": &user_id, "rev": before.version},
+            original,
+            upsert.clone(),
+            &mut *session,
+        )
+        .await?;
+
+    // An upsert, as a concurrent change may have stored this version as its original.
+    let revision = doc! {
+        "$set": {
+            "operation": to_bson(&operation)?,
+            "changed_by": changed_by,
+            "changed_ts": Utc::now(),
+            "user": to_bson(after)?,
+            "changes": to_bson(&FieldChange::between(before, after))?,
+        },
+        "$setOnInsert": {"_id": nanoid!()},
+    };
+    collection
+        .update_one_with_session(
+            doc! {"user_id": &user_id, "rev": after.version},
+            revision,
+            upsert,
+            session,
+        )
+        .await?;
+    Ok(())
+}
+
+// Fetch the revisions of a user, newest first.
+pub async fn get_history(
+    client: &Data<Client>,
+    user_id: &str,
+    offset: u64,
+    limit: i64,
+) -> Result<Vec<UserRevision>, Error> {
+    let collection: Collection<UserRevision> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_HISTORY_COLLECTION);
+    let find_options = FindOptions::builder()
+        .skip(offset)
+        .limit(limit)
+        .sort(doc! {"rev": -1})
+        .build();
+    let cursor = collection
+        .find(doc! {"user_id": user_id}, find_options)
+        .await?;
+    cursor.try_collect().await
+}
+
+pub async fn get_history_size(client: &Data<Client>, user_id: &str) -> Result<u64, Error> {
+    let collection: Collection<UserRevision> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_HISTORY_COLLECTION);
+    collection
+        .count_documents(doc! {"user_id": user_id}, None)
+        .await
+}
+
+pub async fn get_revision(
+    client: &Data<Client>,
+    user_id: &str,
+    rev: i64,
+) -> Result<Option<UserRevision>, Error> {
+    let collection: Collection<UserRevision> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_HISTORY_COLLECTION);
+    collection
+        .find_one(doc! {"user_id": user_id, "rev": rev}, None)
+        .await
+}
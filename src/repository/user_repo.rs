@@ -1,25 +1,32 @@
 use actix_web::web::Data;
+use chrono::{DateTime, Utc};
 use futures::TryStreamExt;
-use mongodb::options::FindOptions;
+use mongodb::change_stream::event::{ChangeStreamEvent, ResumeToken};
+use mongodb::options::{
+    ChangeStreamOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions, FullDocumentType,
+    InsertManyOptions, ReturnDocument,
+};
 use mongodb::{
-    bson::doc,
+    bson::{doc, Bson, Document},
+    change_stream::ChangeStream,
     error::Error,
-    results::{DeleteResult, UpdateResult},
-    Client, Collection,
+    results::InsertManyResult,
+    Client, ClientSession, Collection, Cursor,
 };
 use nanoid::nanoid;
 
+use crate::models::user_history_model::RevisionOperation;
 use crate::models::user_list_response::Users;
+use crate::repository::{transaction, user_history_repo};
 use crate::{constants, models::user_model::User};
 
 // Add a new user to Mongo DB.
-pub async fn create_user(client: &Data<Client>, new_user: User) -> Result<Option<User>, Error> {
-    let new_doc = User {
-        id: Some(nanoid!()),
-        name: new_user.name,
-        location: new_user.location,
-        title: new_user.title,
-    };
+pub async fn create_user(
+    client: &Data<Client>,
+    new_user: User,
+    created_by: &str,
+) -> Result<Option<User>, Error> {
+    let new_doc = new_user_document(new_user, created_by);
     let collection = client
         .database(constants::MONGO_DATABASE)
         .collection(constants::MONGO_USER_COLLECTION);
@@ -31,79 +38,576 @@ pub async fn create_user(client: &Data<Client>, new_user: User) -> Result<Option
     }
 }
 
-// Get a user by given id from MongoDB database
+// Add a batch of users prepared with `new_user_document`.
+// Ordered inserts stop at the first failed user.
+pub async fn insert_users(
+    client: &Data<Client>,
+    new_users: &[User],
+    ordered: bool,
+) -> Result<InsertManyResult, Error> {
+    let options = InsertManyOptions::builder().ordered(ordered).build();
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    collection.insert_many(new_users, options).await
+}
+
+// Give a new user its id, first version and audit fields.
+pub fn new_user_document(new_user: User, created_by: &str) -> User {
+    let now = Utc::now();
+    User {
+        id: Some(nanoid!()),
+        name: new_user.name,
+        location: new_user.location,
+        title: new_user.title,
+        // Only set when the profile is created together with its auth account.
+        auth_id: None,
+        version: 1,
+        created_ts: Some(now),
+        created_by: Some(created_by.to_owned()),
+        updated_ts: Some(now),
+        updated_by: Some(created_by.to_owned()),
+        deleted_at: None,
+        deleted_by: None,
+    }
+}
+
+// Get only the projected fields of a user. Fields left out of the projection are empty.
+pub async fn get_user_fields(
+    client: &Data<Client>,
+    id: &str,
+    projection: Document,
+) -> Result<Option<User>, Error> {
+    let collection: Collection<Document> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    let options = FindOneOptions::builder().projection(projection).build();
+    let user = collection
+        .find_one(not_deleted(doc! {"_id": id}), options)
+        .await?;
+    match user {
+        Some(mut user) => {
+            for field in ["name", "location", "title"] {
+                if !user.contains_key(field) {
+                    user.insert(field, "");
+                }
+            }
+            Ok(Some(bson::from_document(user)?))
+        }
+        None => Ok(None),
+    }
+}
+
+// Get a user by given id from MongoDB database. Soft deleted users are not found.
 pub async fn get_user(client: &Data<Client>, id: &String) -> Result<Option<User>, Error> {
     let obj_id = String::from(id);
-    let filter = doc! {"_id": obj_id};
+    let filter = not_deleted(doc! {"_id": obj_id});
     let collection = client
         .database(constants::MONGO_DATABASE)
         .collection(constants::MONGO_USER_COLLECTION);
     collection.find_one(filter, None).await
 }
 
+// Get users by their ids, in no particular order. Soft deleted users are not found.
+pub async fn get_users_by_ids(client: &Data<Client>, ids: &[String]) -> Result<Vec<User>, Error> {
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    let cursor = collection
+        .find(not_deleted(doc! {"_id": {"$in": ids}}), None)
+        .await?;
+    cursor.try_collect().await
+}
+
 // Update a user for give unique user id.
+// With `versions`, the user is updated only when its stored version is one of them.
+// Returns the updated user, or `None` when no user matched.
 pub async fn update_user(
     client: &Data<Client>,
-    id: &String,
+    id: &str,
     new_user: User,
-) -> Result<UpdateResult, Error> {
-    let obj_id = String::from(id);
-    let filter = doc! {"_id": obj_id};
-    let new_doc = doc! {
-        "$set":
-            {
-                "_id": new_user.id,
-                "name": new_user.name,
-                "location": new_user.location,
-                "title": new_user.title
-            },
+    versions: Option<&[i64]>,
+    updated_by: &str,
+) -> Result<Option<User>, Error> {
+    let filter = version_filter(id, versions);
+    let set = doc! {
+        "_id": new_user.id,
+        "name": new_user.name,
+        "location": new_user.location,
+        "title": new_user.title,
+        "updated_ts": Utc::now(),
+        "updated_by": updated_by
+    };
+    write_user(
+        client,
+        filter,
+        set,
+        &[],
+        RevisionOperation::Updated,
+        updated_by,
+    )
+    .await
+}
+
+// Replace user fields only when the stored user still has the version of `current`.
+// Returns `None` when the user was changed or deleted in the meantime.
+pub async fn patch_user(
+    client: &Data<Client>,
+    current: &User,
+    patched: &User,
+    updated_by: &str,
+) -> Result<Option<User>, Error> {
+    let id = current.id.clone().unwrap_or_default();
+    let filter = version_filter(&id, Some(&[current.version]));
+    let set = doc! {
+        "name": &patched.name,
+        "location": &patched.location,
+        "title": &patched.title,
+        "updated_ts": Utc::now(),
+        "updated_by": updated_by
+    };
+    write_user(
+        client,
+        filter,
+        set,
+        &[],
+        RevisionOperation::Updated,
+        updated_by,
+    )
+    .await
+}
+
+// Soft delete a user for given unique user id.
+// With `versions`, the user is deleted only when its stored version is one of them.
+pub async fn delete_user(
+    client: &Data<Client>,
+    id: &str,
+    versions: Option<&[i64]>,
+    deleted_by: &str,
+) -> Result<Option<User>, Error> {
+    let filter = version_filter(id, versions);
+    let set = doc! {"deleted_at": Utc::now(), "deleted_by": deleted_by};
+    write_user(
+        client,
+        filter,
+        set,
+        &[],
+        RevisionOperation::Deleted,
+        deleted_by,
+    )
+    .await
+}
+
+// Record a change kept outside the user document, like the avatar. Bumps the version and
+// the audit fields like an update.
+pub async fn touch_user(
+    client: &Data<Client>,
+    id: &str,
+    versions: Option<&[i64]>,
+    updated_by: &str,
+) -> Result<Option<User>, Error> {
+    let filter = version_filter(id, versions);
+    let set = doc! {"updated_ts": Utc::now(), "updated_by": updated_by};
+    write_user(
+        client,
+        filter,
+        set,
+        &[],
+        RevisionOperation::Updated,
+        updated_by,
+    )
+    .await
+}
+
+// Bring back a soft deleted user.
+pub async fn restore_user(
+    client: &Data<Client>,
+    id: &str,
+    updated_by: &str,
+) -> Result<Option<User>, Error> {
+    let filter = doc! {"_id": id, "deleted_at": {"$ne": null}};
+    let set = doc! {"updated_ts": Utc::now(), "updated_by": updated_by};
+    write_user(
+        client,
+        filter,
+        set,
+        &["deleted_at", "deleted_by"],
+        RevisionOperation::Restored,
+        updated_by,
+    )
+    .await
+}
+
+// Set the user fields back to the ones of an older revision.
+// With `versions`, the user is reverted only when its stored version is one of them.
+pub async fn revert_user(
+    client: &Data<Client>,
+    id: &str,
+    revision: &User,
+    versions: Option<&[i64]>,
+    updated_by: &str,
+) -> Result<Option<User>, Error> {
+    let filter = version_filter(id, versions);
+    let set = doc! {
+        "name": &revision.name,
+        "location": &revision.location,
+        "title": &revision.title,
+        "updated_ts": Utc::now(),
+        "updated_by": updated_by
     };
+    write_user(
+        client,
+        filter,
+        set,
+        &[],
+        RevisionOperation::Reverted,
+        updated_by,
+    )
+    .await
+}
+
+// Apply a change to the user matching the filter, bump its version and record the change in
+// the user history, in one transaction. Returns the user after the change.
+async fn write_user(
+    client: &Data<Client>,
+    filter: Document,
+    set: Document,
+    unset: &[&str],
+    operation: RevisionOperation,
+    changed_by: &str,
+) -> Result<Option<User>, Error> {
+    transaction::run(
+        client,
+        (client, filter, set, unset, operation, changed_by),
+        |session, (client, filter, set, unset, operation, changed_by)| {
+            Box::pin(write_user_in(
+                client,
+                session,
+                filter.clone(),
+                set.clone(),
+                unset,
+                *operation,
+                changed_by,
+            ))
+        },
+    )
+    .await
+}
+
+// `write_user` in the transaction of the session.
+async fn write_user_in(
+    client: &Data<Client>,
+    session: &mut ClientSession,
+    filter: Document,
+    set: Document,
+    unset: &[&str],
+    operation: RevisionOperation,
+    changed_by: &str,
+) -> Result<Option<User>, Error> {
+    let mut update = doc! {"$set": set.clone(), "$inc": {"version": 1}};
+    if !unset.is_empty() {
+        let fields: Document = unset
+            .iter()
+            .map(|field| (field.to_string(), "".into()))
+            .collect();
+        update.insert("$unset", fields);
+    }
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::Before)
+        .build();
     let collection: Collection<User> = client
         .database(constants::MONGO_DATABASE)
         .collection(constants::MONGO_USER_COLLECTION);
-    collection.update_one(filter, new_doc, None).await
+    let Some(before) = collection
+        .find_one_and_update_with_session(filter, update, options, &mut *session)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let mut after = bson::to_document(&before)?;
+    for (field, value) in set {
+        after.insert(field, value);
+    }
+    for field in unset {
+        after.remove(*field);
+    }
+    after.insert("version", before.version + 1);
+    let after: User = bson::from_document(after)?;
+
+    user_history_repo::record(client, session, &before, &after, operation, changed_by).await?;
+    Ok(Some(after))
 }
 
-// Delete a user for given unique user id.
-pub async fn delete_user(client: &Data<Client>, id: &String) -> Result<DeleteResult, Error> {
-    let obj_id = String::from(id);
-    let filter = doc! {"_id": obj_id};
+// Soft delete the live users linked to an auth account, following the link from either side.
+// Part of the transaction of the session. Returns the number of deleted users.
+pub async fn delete_linked_users(
+    client: &Data<Client>,
+    session: &mut ClientSession,
+    auth_id: &str,
+    user_id: Option<&str>,
+    deleted_by: &str,
+) -> Result<usize, Error> {
+    let mut linked = vec![doc! {"auth_id": auth_id}];
+    if let Some(user_id) = user_id {
+        linked.push(doc! {"_id": user_id});
+    }
     let collection: Collection<User> = client
         .database(constants::MONGO_DATABASE)
         .collection(constants::MONGO_USER_COLLECTION);
-    collection.delete_one(filter, None).await
+    let users: Vec<User> = collection
+        .find_with_session(not_deleted(doc! {"$or": linked}), None, &mut *session)
+        .await?
+        .stream(&mut *session)
+        .try_collect()
+        .await?;
+    for user in &users {
+        let id = user.id.clone().unwrap_or_default();
+        let set = doc! {"deleted_at": Utc::now(), "deleted_by": deleted_by};
+        write_user_in(
+            client,
+            session,
+            not_deleted(doc! {"_id": id}),
+            set,
+            &[],
+            RevisionOperation::Deleted,
+            deleted_by,
+        )
+        .await?;
+    }
+    Ok(users.len())
 }
 
-// Fetch all users from the database
+// Fetch soft deleted users, most recently deleted first.
+pub async fn get_deleted_users(
+    client: &Data<Client>,
+    offset: u64,
+    limit: i64,
+) -> Result<Vec<User>, Error> {
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    let find_options = FindOptions::builder()
+        .skip(offset)
+        .limit(limit)
+        .sort(doc! {"deleted_at": -1, "_id": 1})
+        .build();
+    let cursor = collection
+        .find(doc! {"deleted_at": {"$ne": null}}, find_options)
+        .await?;
+    cursor.try_collect().await
+}
+
+// Fetch the ids of users soft deleted before the given time.
+pub async fn get_purgeable_user_ids(
+    client: &Data<Client>,
+    deleted_before: DateTime<Utc>,
+) -> Result<Vec<String>, Error> {
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    let find_options = FindOptions::builder().projection(doc! {"_id": 1}).build();
+    let cursor = collection
+        .clone_with_type::<Document>()
+        .find(doc! {"deleted_at": {"$lt": deleted_before}}, find_options)
+        .await?;
+    cursor
+        .try_filter_map(
+            |document| async move { Ok(document.get_str("_id").ok().map(str::to_owned)) },
+        )
+        .try_collect()
+        .await
+}
+
+// Permanently remove a user soft deleted before the given time.
+// Returns false when the user was restored or purged in the meantime.
+pub async fn purge_user(
+    client: &Data<Client>,
+    id: &str,
+    deleted_before: DateTime<Utc>,
+) -> Result<bool, Error> {
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    collection
+        .delete_one(
+            doc! {"_id": id, "deleted_at": {"$lt": deleted_before}},
+            None,
+        )
+        .await
+        .map(|result| result.deleted_count == 1)
+}
+
+// Filter a user that is not deleted by id and optionally by version.
+// Users stored before versioning have no version field and match version 0.
+fn version_filter(id: &str, versions: Option<&[i64]>) -> Document {
+    let mut filter = not_deleted(doc! {"_id": id});
+    if let Some(versions) = versions {
+        let mut accepted: Vec<Bson> = versions.iter().map(|v| Bson::Int64(*v)).collect();
+        if versions.contains(&0) {
+            accepted.push(Bson::Null);
+        }
+        filter.insert("version", doc! {"$in": accepted});
+    }
+    filter
+}
+
+// Restrict a filter to users that are not soft deleted.
+fn not_deleted(mut filter: Document) -> Document {
+    filter.insert("deleted_at", Bson::Null);
+    filter
+}
+
+// Fetch users matching the filter from the database. Soft deleted users are left out.
 pub async fn get_all_users(
     client: &Data<Client>,
+    filter: Document,
+    sort: Document,
     offset: u64,
     limit: i64,
+    projection: Document,
 ) -> Result<Vec<Users>, Error> {
+    let collection: Collection<Document> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    let find_options = FindOptions::builder()
+        .projection(projection)
+        .skip(offset)
+        .limit(limit)
+        .sort(sort)
+        .build();
+    let mut cursors = collection.find(not_deleted(filter), find_options).await?;
+    let mut users: Vec<Users> = Vec::new();
+    while let Some(user) = cursors.try_next().await? {
+        users.push(Users {
+            id: user.get_str("_id").unwrap_or_default().to_owned(),
+            name: user.get_str("name").unwrap_or_default().to_owned(),
+            location: user.get_str("location").unwrap_or_default().to_owned(),
+            title: user.get_str("title").unwrap_or_default().to_owned(),
+            auth_id: user.get_str("auth_id").ok().map(str::to_owned),
+            account: None,
+            score: None,
+            highlights: None,
+        })
+    }
+    Ok(users)
+}
+
+// Fetch a page of full users matching the filter. Soft deleted users are left out.
+pub async fn get_user_page(
+    client: &Data<Client>,
+    filter: Document,
+    sort: Document,
+    offset: u64,
+    limit: i64,
+) -> Result<Vec<User>, Error> {
     let collection: Collection<User> = client
         .database(constants::MONGO_DATABASE)
         .collection(constants::MONGO_USER_COLLECTION);
     let find_options = FindOptions::builder()
         .skip(offset)
         .limit(limit)
-        .sort(doc! {"name": 1})
+        .sort(sort)
         .build();
-    let mut cursors = collection.find(None, find_options).await?;
+    let cursor = collection.find(not_deleted(filter), find_options).await?;
+    cursor.try_collect().await
+}
+
+// Full text search over users, most relevant first. Soft deleted users are left out.
+pub async fn search_users(
+    client: &Data<Client>,
+    search: &str,
+    offset: u64,
+    limit: i64,
+) -> Result<Vec<Users>, Error> {
+    let collection: Collection<Document> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    let find_options = FindOptions::builder()
+        .projection(doc! {
+            "name": 1,
+            "location": 1,
+            "title": 1,
+            "score": {"$meta": "textScore"},
+        })
+        .sort(doc! {"score": {"$meta": "textScore"}, "_id": 1})
+        .skip(offset)
+        .limit(limit)
+        .build();
+    let mut cursors = collection.find(text_filter(search), find_options).await?;
     let mut users: Vec<Users> = Vec::new();
     while let Some(user) = cursors.try_next().await? {
         users.push(Users {
-            id: user.id.unwrap_or("".to_string()),
-            name: user.name,
-            location: user.location,
-            title: user.title,
+            id: user.get_str("_id").unwrap_or_default().to_owned(),
+            name: user.get_str("name").unwrap_or_default().to_owned(),
+            location: user.get_str("location").unwrap_or_default().to_owned(),
+            title: user.get_str("title").unwrap_or_default().to_owned(),
+            auth_id: None,
+            account: None,
+            score: user.get_f64("score").ok(),
+            highlights: None,
         })
     }
     Ok(users)
 }
 
-pub async fn get_users_size(client: &Data<Client>) -> Result<u64, Error> {
+pub async fn search_users_size(client: &Data<Client>, search: &str) -> Result<u64, Error> {
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    collection.count_documents(text_filter(search), None).await
+}
+
+fn text_filter(search: &str) -> Document {
+    not_deleted(doc! {"$text": {"$search": search}})
+}
+
+// Open a cursor over users matching the filter. Soft deleted users are left out.
+pub async fn stream_users(
+    client: &Data<Client>,
+    filter: Document,
+    sort: Document,
+) -> Result<Cursor<User>, Error> {
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    let find_options = FindOptions::builder().sort(sort).build();
+    collection.find(not_deleted(filter), find_options).await
+}
+
+// Watch inserts and updates of users. `filter` matches on `fullDocument`, the user after the
+// change. Deletes are left out as only purged users are removed.
+pub async fn watch_users(
+    client: &Data<Client>,
+    filter: Document,
+    resume_after: Option<ResumeToken>,
+) -> Result<ChangeStream<ChangeStreamEvent<User>>, Error> {
+    let pipeline = vec![doc! {"$match": {
+        "operationType": {"$in": ["insert", "update", "replace"]},
+        "$and": [filter],
+    }}];
+    let options = ChangeStreamOptions::builder()
+        .full_document(Some(FullDocumentType::UpdateLookup))
+        .resume_after(resume_after)
+        .build();
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    collection.watch(pipeline, options).await
+}
+
+pub async fn get_users_size(client: &Data<Client>, filter: Document) -> Result<u64, Error> {
+    let collection: Collection<User> = client
+        .database(constants::MONGO_DATABASE)
+        .collection(constants::MONGO_USER_COLLECTION);
+    collection.count_documents(not_deleted(filter), None).await
+}
+
+// Collection size from metadata. Fast at any size but ignores filters and counts soft deleted
+// users.
+pub async fn get_estimated_users_size(client: &Data<Client>) -> Result<u64, Error> {
     let collection: Collection<User> = client
         .database(constants::MONGO_DATABASE)
         .collection(constants::MONGO_USER_COLLECTION);
-    collection.count_documents(doc! {}, None).await
+    collection.estimated_document_count(None).await
 }
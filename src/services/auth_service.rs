@@ -1,52 +1,85 @@
 use actix_web::web::Data;
 use actix_web::HttpResponse;
-use argon2::{Config, Variant, Version};
-use chrono::Utc;
-use log::{error, warn};
-use mongodb::Client;
+use chrono::{Duration, Utc};
+use log::{debug, error, info, warn};
+use mongodb::{error::Error, Client};
 use nanoid::nanoid;
-use rand::Rng;
+use validator::Validate;
 
+use crate::api::account_api::{ImportAccountsRequest, ImportAccountsResponse, ImportSkipped};
 use crate::auth::claims::Claims;
+use crate::auth::password;
+use crate::auth::password_policy::{PasswordContext, PasswordPolicy};
+use crate::auth::provider::AuthProviders;
+use crate::auth::registration::RegistrationPolicy;
+use crate::models::auth_model::AuthSource;
 use crate::{
-    api::auth_api::{LoginRequest, RegisterRequest, RegisterResponse},
+    api::auth_api::{
+        ForgotPasswordRequest, LoginRequest, LoginResponse, RegisterRequest, RegisterResponse,
+        ResetPasswordRequest, UpdatePasswordRequest,
+    },
+    constants,
     models::auth_model::Auth,
     models::error_model::ApiErrorType,
-    repository::auth_repo,
+    models::password_reset_model::PasswordReset,
+    models::user_model::User,
+    repository::{
+        account_repo, auth_repo, invitation_repo, password_reset_repo, role_grant_repo, user_repo,
+    },
+    services::invitation_service,
 };
 
 pub async fn create_user(
     client: &Data<Client>,
+    policy: &RegistrationPolicy,
+    password_policy: &PasswordPolicy,
     register_user: RegisterRequest,
 ) -> Result<HttpResponse, ApiErrorType> {
-    // Step 1: Hash password with argon2.
-    // Generate a random 16-byte salt using the rand crate
-    let mut rng = rand::rng();
-    let salt: [u8; 16] = rng.random();
-    let config = Config {
-        variant: Variant::Argon2id,
-        version: Version::Version13,
-        mem_cost: 65536,
-        time_cost: 10,
-        lanes: 4,
-        secret: &[],
-        ad: &[],
-        hash_length: 64,
-    };
-    let hash = argon2::hash_encoded(register_user.password.as_bytes(), &salt, &config);
+    // Step 1: Check registration mode, email domain rules and password policy.
+    policy.check(&register_user.email, register_user.invite_code.is_some())?;
+    password_policy
+        .check_blocking(
+            "RegisterRequest",
+            "password",
+            &register_user.password,
+            PasswordContext {
+                email: register_user.email.to_owned(),
+                first_name: register_user.first_name.to_owned(),
+                last_name: register_user.last_name.to_owned(),
+                previous_hashes: vec![],
+            },
+        )
+        .await?;
+
+    // Step 2: Hash password with argon2.
+    let hash = password::hash_password_blocking(&register_user.password).await;
 
-    // Step 2: Verify user email does not already exists.
+    // Step 3: Verify user email does not already exists.
     if auth_repo::check_email(client, &register_user.email).await {
-        // Step 3: Store user to MongoDB.
+        // Step 4: Redeem invite code. Roles come from the invitation.
+        let id = nanoid!();
+        let roles = match &register_user.invite_code {
+            Some(code) => {
+                invitation_service::redeem(client, code, &register_user.email, &id)
+                    .await?
+                    .roles
+            }
+            None => vec![String::from("ROLE_USER")],
+        };
+
+        // Step 5: Store user and the linked profile to MongoDB.
         let current_time = Utc::now();
-        let user = Auth {
-            id: nanoid!(),
+        let mut user = Auth {
+            id,
             first_name: register_user.first_name,
             last_name: register_user.last_name,
             email: register_user.email,
-            roles: vec![String::from("ROLE_USER")],
+            roles,
             active: true,
             reset_password: false,
+            password_history: vec![],
+            provider: AuthSource::Local,
+            user_id: None,
             password_hash: match hash {
                 Ok(pwd_hash) => pwd_hash,
                 Err(_) => return Err(ApiErrorType::InternalServerError),
@@ -54,8 +87,9 @@ pub async fn create_user(
             created_ts: current_time,
             updated_ts: current_time,
         };
+        let profile = linked_profile(&mut user);
 
-        let registered_user = auth_repo::auth_register(client, user).await;
+        let registered_user = account_repo::register_with_profile(client, &user, &profile).await;
         match registered_user {
             // User Registered successfully.
             Ok(_) => Ok(HttpResponse::Created().json(RegisterResponse {
@@ -65,6 +99,12 @@ pub async fn create_user(
             // Internal Server Error.
             Err(err) => {
                 error!("Error: {}", err);
+                // Let the invite code be used again.
+                if let Some(code) = &register_user.invite_code {
+                    if let Err(err) = invitation_repo::release(client, code).await {
+                        error!("Error releasing invite code: {}", err);
+                    }
+                }
                 Err(ApiErrorType::InternalServerError)
             }
         }
@@ -77,35 +117,340 @@ pub async fn create_user(
     }
 }
 
+// Build the user profile of a new auth account and link the two together.
+pub fn linked_profile(auth: &mut Auth) -> User {
+    let mut profile = user_repo::new_user_document(
+        User {
+            id: None,
+            name: format!("{} {}", auth.first_name, auth.last_name),
+            location: String::new(),
+            title: String::new(),
+            auth_id: None,
+            version: 0,
+            created_ts: None,
+            created_by: None,
+            updated_ts: None,
+            updated_by: None,
+            deleted_at: None,
+            deleted_by: None,
+        },
+        &auth.id,
+    );
+    profile.auth_id = Some(auth.id.to_owned());
+    auth.user_id = profile.id.to_owned();
+    profile
+}
+
+// Delete an auth account. The linked user profile is soft deleted in the same transaction.
+pub async fn delete_account(
+    client: &Data<Client>,
+    claims: &Claims,
+    id: &str,
+) -> Result<HttpResponse, ApiErrorType> {
+    match account_repo::delete_with_profile(client, id, &claims.sub).await {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => {
+            warn!("Account not found for id: {}", id);
+            Err(ApiErrorType::AccountNotFound)
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Find and fix auth accounts and user profiles whose link is broken. Run with `repair-links`.
+// - Accounts referenced by a profile but not pointing back get their `user_id` set.
+// - Accounts without a live profile get a new linked profile.
+// - Live profiles of a deleted account are soft deleted.
+pub async fn repair_links(client: &Data<Client>, dry_run: bool) -> Result<(), Error> {
+    let missing_user_ids = account_repo::get_missing_user_ids(client).await?;
+    for (auth_id, user_id) in &missing_user_ids {
+        info!(
+            "Account {} is not linked to its profile {}",
+            auth_id, user_id
+        );
+        if !dry_run {
+            account_repo::set_user_id(client, auth_id, user_id).await?;
+        }
+    }
+
+    let accounts = account_repo::get_accounts_without_profile(client).await?;
+    let without_profile = accounts.len();
+    for mut auth in accounts {
+        info!("Account {} has no profile", auth.id);
+        if !dry_run {
+            let profile = linked_profile(&mut auth);
+            account_repo::link_profile(client, &auth.id, &profile).await?;
+        }
+    }
+
+    let orphans = account_repo::get_orphaned_profiles(client).await?;
+    for profile in &orphans {
+        let id = profile.id.clone().unwrap_or_default();
+        info!("Profile {} belongs to a deleted account", id);
+        if !dry_run {
+            user_repo::delete_user(client, &id, None, constants::REPAIR_LINKS_ACTOR).await?;
+        }
+    }
+
+    info!(
+        "{} {} unlinked accounts, {} accounts without profile, {} orphaned profiles",
+        if dry_run { "Found" } else { "Repaired" },
+        missing_user_ids.len(),
+        without_profile,
+        orphans.len()
+    );
+    Ok(())
+}
+
 // Login with credentials and generate JWT token after successful login.
 pub async fn login(
     client: &Data<Client>,
+    providers: &AuthProviders,
     login_request: LoginRequest,
 ) -> Result<HttpResponse, ApiErrorType> {
-    // Step 1: Get auth user from MongoDB by email id.
-    let auth_user = auth_repo::fetch_by_email(client, &login_request.email).await;
-
-    // Check if the user
-    match &auth_user {
-        Some(a) => {
-            // Step 2: Check password with hashed password from Database.
-            let pwd_match =
-                argon2::verify_encoded(&a.password_hash, login_request.password.as_bytes())
-                    .unwrap();
-            if pwd_match {
-                // Credentials verified successfully.
-                // Step 3: Generate JWT token with auth information.
-                match Claims::create_jwt_token(a) {
-                    Ok(response) => Ok(HttpResponse::Ok().json(response)),
-                    Err(_) => Err(ApiErrorType::AuthenticationError),
-                }
-            } else {
-                Err(ApiErrorType::InvalidCredential)
-            }
+    let token = issue_token(client, providers, login_request).await?;
+    Ok(HttpResponse::Ok().json(token))
+}
+
+// Verify credentials and generate the JWT token. Shared by the REST and gRPC logins.
+pub async fn issue_token(
+    client: &Data<Client>,
+    providers: &AuthProviders,
+    login_request: LoginRequest,
+) -> Result<LoginResponse, ApiErrorType> {
+    // Step 1: Verify credentials with the configured authentication providers.
+    let auth_user = providers.authenticate(client, &login_request).await?;
+
+    // Step 2: Get temporary role grants. Login still works without them if this fails.
+    let grants = role_grant_repo::get_active_grants(client, &auth_user.id)
+        .await
+        .unwrap_or_else(|err| {
+            error!(
+                "Error fetching role grants for {}: {}",
+                auth_user.email, err
+            );
+            vec![]
+        });
+
+    // Step 3: Generate JWT token with auth information.
+    Claims::create_jwt_token(&auth_user, &grants).map_err(|_| ApiErrorType::AuthenticationError)
+}
+
+// Change password of a local account after verifying the current password.
+pub async fn update_password(
+    client: &Data<Client>,
+    password_policy: &PasswordPolicy,
+    update_request: UpdatePasswordRequest,
+) -> Result<HttpResponse, ApiErrorType> {
+    // Step 1: Verify current credentials. Passwords of external accounts are managed by their provider.
+    let auth = match auth_repo::fetch_by_email(client, &update_request.email).await {
+        Some(a) if a.provider == AuthSource::Local => a,
+        _ => {
+            warn!("Local user not found for email {}", update_request.email);
+            return Err(ApiErrorType::InvalidCredential);
+        }
+    };
+    match password::verify_password_blocking(&auth.password_hash, &update_request.current_password)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return Err(ApiErrorType::InvalidCredential),
+        Err(err) => {
+            error!("Error verifying password hash for {}: {}", auth.email, err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    }
+
+    // Step 2: Check the new password against the policy and store it.
+    set_password(
+        client,
+        password_policy,
+        &auth,
+        "UpdatePasswordRequest",
+        &update_request.new_password,
+    )
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Start the password reset of a local account. The response is the same whether the account
+// exists or not, so it can not be used to find registered emails.
+pub async fn forgot_password(
+    client: &Data<Client>,
+    forgot_request: ForgotPasswordRequest,
+) -> Result<HttpResponse, ApiErrorType> {
+    let auth = match auth_repo::fetch_by_email(client, &forgot_request.email).await {
+        Some(a) if a.provider == AuthSource::Local && a.active => a,
+        _ => {
+            warn!(
+                "Password reset requested for unknown email {}",
+                forgot_request.email
+            );
+            return Ok(HttpResponse::Accepted().finish());
         }
-        None => {
-            warn!("User not found for email {}", login_request.email);
-            Err(ApiErrorType::InvalidCredential)
+    };
+
+    // Only a hash of the token is stored. A new request replaces the previous token.
+    let token = nanoid!(32);
+    let current_time = Utc::now();
+    let reset = PasswordReset {
+        token_hash: password_reset_repo::token_hash(&token),
+        auth_id: auth.id.to_owned(),
+        created_ts: current_time,
+        expires_ts: current_time + Duration::minutes(constants::PASSWORD_RESET_EXPIRY_MINUTES),
+    };
+    if let Err(err) = password_reset_repo::create_reset(client, &reset).await {
+        error!("Error: {}", err);
+        return Err(ApiErrorType::InternalServerError);
+    }
+    // There is no mail sender yet. The token is only logged at debug level for local testing.
+    debug!("Password reset token for {}: {}", auth.email, token);
+    Ok(HttpResponse::Accepted().finish())
+}
+
+// Set a new password with a reset token from `forgot_password`. The token can be used once.
+pub async fn reset_password(
+    client: &Data<Client>,
+    password_policy: &PasswordPolicy,
+    reset_request: ResetPasswordRequest,
+) -> Result<HttpResponse, ApiErrorType> {
+    let reset = match password_reset_repo::redeem(client, &reset_request.token).await {
+        Ok(Some(reset)) => reset,
+        Ok(None) => {
+            warn!("Invalid password reset token used");
+            return Err(ApiErrorType::InvalidResetToken);
         }
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    let auth = match auth_repo::fetch_by_id(client, &reset.auth_id).await {
+        Ok(Some(a)) if a.provider == AuthSource::Local && a.active => a,
+        Ok(_) => return Err(ApiErrorType::InvalidResetToken),
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+
+    set_password(
+        client,
+        password_policy,
+        &auth,
+        "ResetPasswordRequest",
+        &reset_request.new_password,
+    )
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Check the new password against the policy, then store its hash and keep the current one in the
+// password history. Shared by password change and reset.
+async fn set_password(
+    client: &Data<Client>,
+    password_policy: &PasswordPolicy,
+    auth: &Auth,
+    object: &str,
+    new_password: &str,
+) -> Result<(), ApiErrorType> {
+    let mut previous_hashes = vec![auth.password_hash.to_owned()];
+    previous_hashes.extend(auth.password_history.iter().cloned());
+    password_policy
+        .check_blocking(
+            object,
+            "new_password",
+            new_password,
+            PasswordContext {
+                email: auth.email.to_owned(),
+                first_name: auth.first_name.to_owned(),
+                last_name: auth.last_name.to_owned(),
+                previous_hashes: previous_hashes.to_owned(),
+            },
+        )
+        .await?;
+
+    let hash = match password::hash_password_blocking(new_password).await {
+        Ok(pwd_hash) => pwd_hash,
+        Err(_) => return Err(ApiErrorType::InternalServerError),
+    };
+    previous_hashes.truncate(password_policy.history_size);
+    match auth_repo::update_password(client, &auth.id, &hash, &previous_hashes).await {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Bulk import accounts with password hashes from another system.
+// Hashes are stored as is and re-hashed with argon2 on the first successful login.
+pub async fn import_accounts(
+    client: &Data<Client>,
+    import_request: ImportAccountsRequest,
+) -> Result<HttpResponse, ApiErrorType> {
+    let current_time = Utc::now();
+    let mut users: Vec<Auth> = vec![];
+    let mut profiles: Vec<User> = vec![];
+    let mut skipped: Vec<ImportSkipped> = vec![];
+
+    for account in import_request.accounts {
+        let reason = if let Err(err) = account.validate() {
+            Some(format!("Validation error: {}", err))
+        } else if let Err(err) = password::check_hash(&account.password_hash) {
+            Some(err.to_string())
+        } else if users.iter().any(|u| u.email == account.email)
+            || !auth_repo::check_email(client, &account.email).await
+        {
+            Some("User already exists with email".to_owned())
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            warn!("Skipping import of {}: {}", account.email, reason);
+            skipped.push(ImportSkipped {
+                email: account.email,
+                reason,
+            });
+            continue;
+        }
+
+        let mut auth = Auth {
+            id: nanoid!(),
+            email: account.email,
+            first_name: account.first_name,
+            last_name: account.last_name,
+            password_hash: account.password_hash,
+            roles: account
+                .roles
+                .unwrap_or_else(|| vec![String::from("ROLE_USER")]),
+            active: account.active.unwrap_or(true),
+            reset_password: false,
+            password_history: vec![],
+            provider: AuthSource::Local,
+            user_id: None,
+            created_ts: current_time,
+            updated_ts: current_time,
+        };
+        // Imported accounts get a linked profile like registered ones.
+        profiles.push(linked_profile(&mut auth));
+        users.push(auth);
     }
+
+    let imported = if users.is_empty() {
+        0
+    } else {
+        match account_repo::import_with_profiles(client, &users, &profiles).await {
+            Ok(imported) => imported,
+            Err(err) => {
+                error!("Error: {}", err);
+                return Err(ApiErrorType::InternalServerError);
+            }
+        }
+    };
+    Ok(HttpResponse::Ok().json(ImportAccountsResponse { imported, skipped }))
 }
@@ -0,0 +1,453 @@
+use std::fmt::Display;
+use std::io::Cursor;
+use std::time::SystemTime;
+
+use actix_multipart::Multipart;
+use actix_web::http::header::{
+    self, ContentEncoding, ContentRangeSpec, ETag, EntityTag, HttpDate, IfMatch, IfNoneMatch,
+    LastModified, Range,
+};
+use actix_web::web::{self, Bytes, BytesMut, Data, Payload};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use futures::io::{self as async_io, AsyncReadExt};
+use futures::{stream, Stream, StreamExt};
+use image::{ImageFormat, ImageReader, Limits};
+use log::{error, warn};
+use mongodb::bson::Bson;
+use mongodb::Client;
+
+use crate::api::user_api::AvatarSize;
+use crate::auth::claims::Claims;
+use crate::config::user::UserConfig;
+use crate::constants;
+use crate::models::error_model::ApiErrorType;
+use crate::repository::{avatar_repo, user_repo};
+use crate::services::user_service;
+
+const ORIGINAL: &str = "original";
+const THUMBNAIL: &str = "thumbnail";
+
+// Store a new avatar and its thumbnail. Replacing the avatar is a write to the user, with the
+// same `If-Match` rules and audit fields as an update.
+pub async fn put_avatar(
+    client: &Data<Client>,
+    config: &UserConfig,
+    claims: &Claims,
+    id: String,
+    req: &HttpRequest,
+    payload: Payload,
+    if_match: Option<IfMatch>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let versions = user_service::accepted_versions(config, if_match)?;
+    let content = read_upload(req, payload, config.avatar_max_bytes).await?;
+    // The declared content type is not trusted, the image is recognized by its first bytes.
+    let Some(content_type) = image_type(&content) else {
+        warn!("Avatar upload for user {} is not a supported image", id);
+        return Err(ApiErrorType::UnsupportedMediaType {
+            supported: constants::AVATAR_CONTENT_TYPES.join(", "),
+        });
+    };
+    let original = content.clone();
+    let thumbnail = match web::block(move || thumbnail(&original)).await {
+        Ok(thumbnail) => thumbnail?,
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    find_user(client, &id).await?;
+    // Files of the avatar being replaced. A concurrent upload keeps its own files.
+    let previous = match avatar_repo::file_ids(client, &id).await {
+        Ok(previous) => previous,
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+
+    let mut stored: Vec<String> = vec![];
+    for (variant, content_type, content) in [
+        (ORIGINAL, content_type, &content[..]),
+        (THUMBNAIL, "image/png", &thumbnail[..]),
+    ] {
+        let etag = user_service::content_tag(content);
+        match avatar_repo::upload(client, &id, variant, content_type, etag.tag(), content).await {
+            Ok(file_id) => stored.push(file_id),
+            Err(err) => {
+                error!("Error: {}", err);
+                discard(client, &stored).await;
+                return Err(ApiErrorType::InternalServerError);
+            }
+        }
+    }
+
+    match user_repo::touch_user(client, &id, versions.as_deref(), &claims.sub).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            discard(client, &stored).await;
+            return Err(user_service::missing_or_changed(client, &id).await);
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            discard(client, &stored).await;
+            return Err(ApiErrorType::InternalServerError);
+        }
+    }
+    // The new avatar is in place, a left over previous one is only logged.
+    if let Err(err) = avatar_repo::delete_files(client, &previous).await {
+        error!("Error deleting previous avatar of user {}: {}", id, err);
+    }
+    written(client, &id).await
+}
+
+// Stream the avatar or its thumbnail. A single byte range is answered with `206`.
+pub async fn get_avatar(
+    client: &Data<Client>,
+    config: &UserConfig,
+    id: String,
+    size: AvatarSize,
+    range: Option<Range>,
+    if_none_match: Option<IfNoneMatch>,
+) -> Result<HttpResponse, ApiErrorType> {
+    find_user(client, &id).await?;
+    let variant = match size {
+        AvatarSize::Original => ORIGINAL,
+        AvatarSize::Thumbnail => THUMBNAIL,
+    };
+    let file = match avatar_repo::find(client, &id, variant).await {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            warn!("User {} has no avatar", id);
+            return Err(ApiErrorType::AvatarNotFound);
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    let metadata = file.metadata.unwrap_or_default();
+    let tag = EntityTag::new_strong(metadata.get_str("etag").unwrap_or_default().to_owned());
+    let content_type = metadata
+        .get_str("content_type")
+        .unwrap_or("application/octet-stream")
+        .to_owned();
+    let uploaded = file.upload_date.to_chrono();
+    let length = file.length;
+
+    let not_modified = user_service::is_not_modified(&tag, None, if_none_match, None);
+    let range = match byte_range(range, length) {
+        Some(range) => range,
+        None if not_modified => None,
+        None => {
+            warn!("Unsatisfiable range for avatar of user {}", id);
+            return Ok(HttpResponse::RangeNotSatisfiable()
+                .insert_header(header::ContentRange(ContentRangeSpec::Bytes {
+                    range: None,
+                    instance_length: Some(length),
+                }))
+                .finish());
+        }
+    };
+    let mut response = match range {
+        _ if not_modified => HttpResponse::NotModified(),
+        Some((start, end)) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header(header::ContentRange(ContentRangeSpec::Bytes {
+                range: Some((start, end)),
+                instance_length: Some(length),
+            }));
+            response
+        }
+        None => HttpResponse::Ok(),
+    };
+    response
+        .insert_header((header::CACHE_CONTROL, config.user_cache_control.as_str()))
+        .insert_header(ETag(tag))
+        .insert_header(LastModified(HttpDate::from(SystemTime::from(uploaded))))
+        .insert_header((header::ACCEPT_RANGES, "bytes"));
+    if not_modified {
+        return Ok(response.finish());
+    }
+
+    let (start, len) = match range {
+        Some((start, end)) => (start, end - start + 1),
+        None => (0, length),
+    };
+    let body = match read_range(client, file.id, start, len).await {
+        Ok(body) => body,
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    Ok(response
+        .content_type(content_type)
+        // Images are compressed already and ranges are of the stored bytes.
+        .insert_header(ContentEncoding::Identity)
+        .no_chunking(len)
+        .streaming(body))
+}
+
+// Requested byte range, `Some(None)` for the whole avatar. Several ranges are not supported
+// and get the whole avatar. `None` when the range is outside the avatar.
+fn byte_range(range: Option<Range>, length: u64) -> Option<Option<(u64, u64)>> {
+    match range {
+        Some(Range::Bytes(specs)) if specs.len() == 1 => {
+            specs[0].to_satisfiable_range(length).map(Some)
+        }
+        _ => Some(None),
+    }
+}
+
+// Stream `len` bytes of a GridFS file from `start`.
+async fn read_range(
+    client: &Data<Client>,
+    id: Bson,
+    start: u64,
+    len: u64,
+) -> Result<impl Stream<Item = Result<Bytes, std::io::Error>>, std::io::Error> {
+    let mut download = avatar_repo::open(client, id)
+        .await
+        .map_err(std::io::Error::other)?;
+    // GridFS downloads can not seek, skipped bytes are read and dropped.
+    async_io::copy((&mut download).take(start), &mut async_io::sink()).await?;
+    let reader = download.take(len);
+    Ok(stream::unfold(reader, |mut reader| async move {
+        let mut buffer = vec![0; constants::AVATAR_READ_CHUNK_BYTES];
+        match reader.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(Bytes::from(buffer)), reader))
+            }
+            Err(err) => Some((Err(err), reader)),
+        }
+    }))
+}
+
+// Remove the avatar and its thumbnail. A write to the user, like `put_avatar`.
+pub async fn delete_avatar(
+    client: &Data<Client>,
+    config: &UserConfig,
+    claims: &Claims,
+    id: String,
+    if_match: Option<IfMatch>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let versions = user_service::accepted_versions(config, if_match)?;
+    find_user(client, &id).await?;
+    match avatar_repo::find(client, &id, ORIGINAL).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            warn!("User {} has no avatar to delete", id);
+            return Err(ApiErrorType::AvatarNotFound);
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    }
+    match user_repo::touch_user(client, &id, versions.as_deref(), &claims.sub).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(user_service::missing_or_changed(client, &id).await),
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    }
+    if let Err(err) = avatar_repo::delete_all(client, &id).await {
+        error!("Error: {}", err);
+        return Err(ApiErrorType::InternalServerError);
+    }
+    written(client, &id).await
+}
+
+async fn find_user(client: &Data<Client>, id: &String) -> Result<(), ApiErrorType> {
+    match user_repo::get_user(client, id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => {
+            warn!("User with id - {} not found for avatar", id);
+            Err(ApiErrorType::UserNotFound)
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// `204` with the validators of the user after the write.
+async fn written(client: &Data<Client>, id: &String) -> Result<HttpResponse, ApiErrorType> {
+    match user_repo::get_user(client, id).await {
+        Ok(Some(user)) => {
+            let mut response = HttpResponse::NoContent();
+            user_service::validators(&mut response, &user);
+            Ok(response.finish())
+        }
+        Ok(None) => Err(ApiErrorType::UserNotFound),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Remove the files of an upload that did not go through.
+async fn discard(client: &Data<Client>, ids: &[String]) {
+    if let Err(err) = avatar_repo::delete_files(client, ids).await {
+        error!("Error deleting discarded avatar files {:?}: {}", ids, err);
+    }
+}
+
+// The image is the `avatar` field of a `multipart/form-data` body, or the whole body.
+async fn read_upload(
+    req: &HttpRequest,
+    payload: Payload,
+    max: usize,
+) -> Result<Bytes, ApiErrorType> {
+    if req.content_type() != constants::MULTIPART_CONTENT_TYPE {
+        return read_limited(payload, max).await;
+    }
+    let mut multipart = Multipart::new(req.headers(), payload);
+    while let Some(field) = multipart.next().await {
+        let field = field.map_err(bad_upload)?;
+        if field.name() == Some(constants::AVATAR_FIELD) {
+            return read_limited(field, max).await;
+        }
+    }
+    Err(ApiErrorType::InvalidImage {
+        detail: format!("multipart body has no {} field", constants::AVATAR_FIELD),
+    })
+}
+
+async fn read_limited<S, E>(mut body: S, max: usize) -> Result<Bytes, ApiErrorType>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Display,
+{
+    let mut content = BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(bad_upload)?;
+        if content.len() + chunk.len() > max {
+            warn!("Avatar upload is larger than {} bytes", max);
+            return Err(ApiErrorType::AvatarTooLarge { max });
+        }
+        content.extend_from_slice(&chunk);
+    }
+    Ok(content.freeze())
+}
+
+fn bad_upload(err: impl Display) -> ApiErrorType {
+    warn!("Error reading avatar upload: {}", err);
+    ApiErrorType::BadRequest
+}
+
+// Content type from the magic bytes of PNG, JPEG, GIF and WebP images.
+fn image_type(content: &[u8]) -> Option<&'static str> {
+    if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if content.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if content.len() >= 12 && &content[..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+// PNG thumbnail that fits in a square of `AVATAR_THUMBNAIL_SIZE`. Decoding also rejects
+// images that only look valid by their first bytes.
+fn thumbnail(content: &[u8]) -> Result<Vec<u8>, ApiErrorType> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(constants::AVATAR_MAX_DIMENSION);
+    limits.max_image_height = Some(constants::AVATAR_MAX_DIMENSION);
+    let mut reader = ImageReader::new(Cursor::new(content))
+        .with_guessed_format()
+        .map_err(invalid_image)?;
+    reader.limits(limits);
+    let image = reader.decode().map_err(invalid_image)?;
+    let mut png = Cursor::new(Vec::new());
+    image
+        .thumbnail(
+            constants::AVATAR_THUMBNAIL_SIZE,
+            constants::AVATAR_THUMBNAIL_SIZE,
+        )
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(|err| {
+            error!("Error: {}", err);
+            ApiErrorType::InternalServerError
+        })?;
+    Ok(png.into_inner())
+}
+
+fn invalid_image(err: impl Display) -> ApiErrorType {
+    warn!("Invalid avatar image: {}", err);
+    ApiErrorType::InvalidImage {
+        detail: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::ByteRangeSpec;
+    use image::{GenericImageView, RgbImage};
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        RgbImage::new(width, height)
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        png.into_inner()
+    }
+
+    #[test]
+    fn images_are_recognized_by_their_first_bytes() {
+        assert_eq!(image_type(&png(1, 1)), Some("image/png"));
+        assert_eq!(image_type(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(image_type(b"GIF89a..."), Some("image/gif"));
+        assert_eq!(image_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(image_type(b"RIFF\0\0\0\0WAVE"), None);
+        assert_eq!(image_type(b"<svg></svg>"), None);
+    }
+
+    #[test]
+    fn single_byte_ranges_are_served() {
+        let range = |spec| Some(Range::Bytes(vec![spec]));
+        assert_eq!(byte_range(None, 100), Some(None));
+        assert_eq!(
+            byte_range(range(ByteRangeSpec::FromTo(10, 19)), 100),
+            Some(Some((10, 19)))
+        );
+        assert_eq!(
+            byte_range(range(ByteRangeSpec::Last(10)), 100),
+            Some(Some((90, 99)))
+        );
+        assert_eq!(byte_range(range(ByteRangeSpec::From(100)), 100), None);
+    }
+
+    #[test]
+    fn multiple_ranges_get_the_whole_file() {
+        let ranges = Range::Bytes(vec![
+            ByteRangeSpec::FromTo(0, 9),
+            ByteRangeSpec::FromTo(20, 29),
+        ]);
+        assert_eq!(byte_range(Some(ranges), 100), Some(None));
+    }
+
+    #[test]
+    fn thumbnail_fits_the_square() {
+        let small = thumbnail(&png(512, 256)).unwrap();
+        let image = image::load_from_memory(&small).unwrap();
+        assert_eq!(
+            image.dimensions(),
+            (
+                constants::AVATAR_THUMBNAIL_SIZE,
+                constants::AVATAR_THUMBNAIL_SIZE / 2
+            )
+        );
+        assert!(thumbnail(b"\x89PNG\r\n\x1a\nnot really").is_err());
+    }
+}
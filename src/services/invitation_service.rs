@@ -0,0 +1,110 @@
+use actix_web::web::{Data, Path};
+use actix_web::HttpResponse;
+use chrono::{Duration, SecondsFormat, Utc};
+use log::{error, warn};
+use mongodb::Client;
+use nanoid::nanoid;
+
+use crate::api::invitation_api::{InvitationRequest, InvitationResponse};
+use crate::auth::claims::Claims;
+use crate::constants;
+use crate::models::{error_model::ApiErrorType, invitation_model::Invitation};
+use crate::repository::invitation_repo;
+
+// Create a new invitation on behalf of the signed in admin.
+pub async fn create_invitation(
+    client: &Data<Client>,
+    claims: &Claims,
+    invitation_request: InvitationRequest,
+) -> Result<HttpResponse, ApiErrorType> {
+    let current_time = Utc::now();
+    let expires_in_hours = invitation_request
+        .expires_in_hours
+        .unwrap_or(constants::DEFAULT_INVITATION_EXPIRY_HOURS);
+    let invitation = Invitation {
+        id: nanoid!(),
+        email: invitation_request.email.map(|e| e.to_lowercase()),
+        roles: invitation_request
+            .roles
+            .unwrap_or_else(|| vec![String::from("ROLE_USER")]),
+        created_by: claims.sub.to_owned(),
+        used_by: None,
+        created_ts: current_time,
+        expires_ts: current_time + Duration::hours(expires_in_hours),
+    };
+    match invitation_repo::create_invitation(client, &invitation).await {
+        Ok(_) => Ok(HttpResponse::Created().json(to_response(invitation))),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+pub async fn get_pending_invitations(client: &Data<Client>) -> Result<HttpResponse, ApiErrorType> {
+    match invitation_repo::get_pending_invitations(client).await {
+        Ok(invitations) => Ok(HttpResponse::Ok().json(
+            invitations
+                .into_iter()
+                .map(to_response)
+                .collect::<Vec<InvitationResponse>>(),
+        )),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+pub async fn revoke_invitation(
+    client: &Data<Client>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let code = path.into_inner();
+    match invitation_repo::delete_invitation(client, &code).await {
+        Ok(res) if res.deleted_count == 1 => Ok(HttpResponse::NoContent().finish()),
+        Ok(_) => {
+            warn!("Invitation with code - {} not found for revoke", code);
+            Err(ApiErrorType::InvitationNotFound)
+        }
+        Err(err) => {
+            error!("Error : {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Redeem an invite code for the registering user.
+pub async fn redeem(
+    client: &Data<Client>,
+    code: &String,
+    email: &str,
+    auth_id: &String,
+) -> Result<Invitation, ApiErrorType> {
+    match invitation_repo::redeem(client, code, email, auth_id).await {
+        Ok(Some(invitation)) => Ok(invitation),
+        Ok(None) => {
+            warn!("Invalid invite code used for registration of {}", email);
+            Err(ApiErrorType::InvalidInvitation)
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+fn to_response(invitation: Invitation) -> InvitationResponse {
+    InvitationResponse {
+        code: invitation.id,
+        email: invitation.email,
+        roles: invitation.roles,
+        created_by: invitation.created_by,
+        created_ts: invitation
+            .created_ts
+            .to_rfc3339_opts(SecondsFormat::Micros, true),
+        expires_ts: invitation
+            .expires_ts
+            .to_rfc3339_opts(SecondsFormat::Micros, true),
+    }
+}
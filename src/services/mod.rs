@@ -1,3 +1,7 @@
 pub mod auth_service;
+pub mod avatar_service;
+pub mod invitation_service;
 pub mod location_service;
+pub mod role_grant_service;
+pub mod user_event_service;
 pub mod user_service;
@@ -0,0 +1,234 @@
+use std::time::Duration as StdDuration;
+
+use actix_web::rt::{spawn, time};
+use actix_web::web::{Data, Path};
+use actix_web::HttpResponse;
+use chrono::{Duration, SecondsFormat, Utc};
+use log::{error, info, warn};
+use mongodb::Client;
+use nanoid::nanoid;
+use validator::{ValidationError, ValidationErrors};
+
+use crate::api::role_grant_api::{RoleGrantRequest, RoleGrantResponse};
+use crate::auth::claims::Claims;
+use crate::config::role_grant::RoleGrantConfig;
+use crate::models::error_model::ApiErrorType;
+use crate::models::role_grant_model::{GrantStatus, RoleGrant};
+use crate::repository::{auth_repo, role_grant_repo};
+
+// Request a temporary role for the signed in user.
+pub async fn request_grant(
+    client: &Data<Client>,
+    config: &RoleGrantConfig,
+    claims: &Claims,
+    grant_request: RoleGrantRequest,
+) -> Result<HttpResponse, ApiErrorType> {
+    check_grantable(config, &grant_request.role)?;
+    match auth_repo::fetch_by_id(client, &claims.sub).await {
+        Ok(Some(auth)) if auth.roles.contains(&grant_request.role) => {
+            warn!(
+                "User {} already has role {}",
+                claims.sub, grant_request.role
+            );
+            return Err(ApiErrorType::BadRequest);
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(ApiErrorType::AuthenticationError),
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    }
+
+    let grant = RoleGrant {
+        id: nanoid!(),
+        auth_id: claims.sub.to_owned(),
+        role: grant_request.role,
+        justification: grant_request.justification,
+        duration_hours: grant_request.duration_hours,
+        status: GrantStatus::Pending,
+        decided_by: None,
+        decision_note: None,
+        requested_ts: Utc::now(),
+        decided_ts: None,
+        expires_ts: None,
+    };
+    match role_grant_repo::create_grant(client, &grant).await {
+        Ok(_) => Ok(HttpResponse::Created().json(to_response(grant))),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Only roles on the allowlist can be requested. Other roles fail validation.
+fn check_grantable(config: &RoleGrantConfig, role: &str) -> Result<(), ApiErrorType> {
+    if config.grantable_roles.iter().any(|r| r == role) {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("grantable_role").with_message(
+        format!(
+            "role must be one of [{}]",
+            config.grantable_roles.join(", ")
+        )
+        .into(),
+    );
+    error.add_param("value".into(), &role);
+    let mut errors = ValidationErrors::new();
+    errors.add("role", error);
+    warn!("Role {} can not be granted", role);
+    Err(ApiErrorType::ValidationError {
+        validation_error: errors,
+        object: "RoleGrantRequest".to_owned(),
+    })
+}
+
+pub async fn get_grants(
+    client: &Data<Client>,
+    status: Option<GrantStatus>,
+) -> Result<HttpResponse, ApiErrorType> {
+    match role_grant_repo::get_grants(client, status).await {
+        Ok(grants) => Ok(HttpResponse::Ok().json(
+            grants
+                .into_iter()
+                .map(to_response)
+                .collect::<Vec<RoleGrantResponse>>(),
+        )),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Approve or deny a pending role grant. Approved grants expire after the requested duration.
+pub async fn decide_grant(
+    client: &Data<Client>,
+    config: &RoleGrantConfig,
+    claims: &Claims,
+    path: Path<String>,
+    status: GrantStatus,
+    note: Option<String>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let id = path.into_inner();
+    let grant = match role_grant_repo::get_grant(client, &id).await {
+        Ok(Some(g)) => g,
+        Ok(None) => {
+            warn!("Role grant with id - {} not found", id);
+            return Err(ApiErrorType::RoleGrantNotFound);
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    // Four eyes principle. Nobody approves or denies their own elevation.
+    if grant.auth_id == claims.sub {
+        warn!("User {} tried to decide own role grant {}", claims.sub, id);
+        return Err(ApiErrorType::AuthorizationError);
+    }
+    // Requests made before the role left the allowlist can still be denied, not approved.
+    if status == GrantStatus::Approved {
+        check_grantable(config, &grant.role)?;
+    }
+
+    let expires_ts = match status {
+        GrantStatus::Approved => Some(Utc::now() + Duration::hours(grant.duration_hours)),
+        _ => None,
+    };
+    let result =
+        role_grant_repo::decide_grant(client, &id, status, &claims.sub, note, expires_ts).await;
+    match result {
+        Ok(update) if update.modified_count == 1 => {
+            info!("Role grant {} {:?} by {}", id, status, claims.sub);
+            match role_grant_repo::get_grant(client, &id).await {
+                Ok(Some(g)) => Ok(HttpResponse::Ok().json(to_response(g))),
+                Ok(None) => Err(ApiErrorType::RoleGrantNotFound),
+                Err(err) => {
+                    error!("Error: {}", err);
+                    Err(ApiErrorType::InternalServerError)
+                }
+            }
+        }
+        Ok(_) => Err(ApiErrorType::RoleGrantNotPending),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Periodically mark approved role grants past their expiry as expired.
+pub fn spawn_expiry_sweeper(client: Data<Client>, interval_secs: u64) {
+    spawn(async move {
+        let mut interval = time::interval(StdDuration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match role_grant_repo::expire_grants(&client).await {
+                Ok(res) if res.modified_count > 0 => {
+                    info!("Expired {} role grants", res.modified_count)
+                }
+                Ok(_) => {}
+                Err(err) => error!("Error expiring role grants: {}", err),
+            }
+        }
+    });
+}
+
+fn to_response(grant: RoleGrant) -> RoleGrantResponse {
+    RoleGrantResponse {
+        id: grant.id,
+        auth_id: grant.auth_id,
+        role: grant.role,
+        justification: grant.justification,
+        duration_hours: grant.duration_hours,
+        status: grant.status,
+        decided_by: grant.decided_by,
+        decision_note: grant.decision_note,
+        requested_ts: grant
+            .requested_ts
+            .to_rfc3339_opts(SecondsFormat::Micros, true),
+        decided_ts: grant
+            .decided_ts
+            .map(|ts| ts.to_rfc3339_opts(SecondsFormat::Micros, true)),
+        expires_ts: grant
+            .expires_ts
+            .map(|ts| ts.to_rfc3339_opts(SecondsFormat::Micros, true)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::ResponseError;
+
+    use super::*;
+
+    fn config() -> RoleGrantConfig {
+        RoleGrantConfig {
+            grantable_roles: vec!["ROLE_AUDITOR".to_owned()],
+        }
+    }
+
+    #[test]
+    fn grantable_role_is_accepted() {
+        assert!(check_grantable(&config(), "ROLE_AUDITOR").is_ok());
+        let admin = RoleGrantConfig {
+            grantable_roles: vec!["ROLE_ADMIN".to_owned()],
+        };
+        assert!(check_grantable(&admin, "ROLE_ADMIN").is_ok());
+    }
+
+    #[test]
+    fn roles_off_the_allowlist_fail_validation() {
+        for role in ["ROLE_ADMIN", "ROLE_ANYTHING", "role_auditor"] {
+            let err = check_grantable(&config(), role).unwrap_err();
+            assert_eq!(err.status_code().as_u16(), 422);
+            let api_error = err.api_error();
+            assert_eq!(api_error.sub_errors.len(), 1);
+            let sub_error = serde_json::to_value(&api_error.sub_errors[0]).unwrap();
+            assert_eq!(sub_error["field"], "role");
+            assert_eq!(sub_error["message"], "role must be one of [ROLE_AUDITOR]");
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::future::ready;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::header::{self, CacheDirective, ContentEncoding};
+use actix_web::rt::time::{self, Instant};
+use actix_web::web::{Bytes, Data};
+use actix_web::HttpResponse;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use futures::{stream, StreamExt};
+use log::{error, info, warn};
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType, ResumeToken};
+use mongodb::error::ErrorKind;
+use mongodb::Client;
+
+use crate::api::user_api::UserFilter;
+use crate::config::user::UserConfig;
+use crate::constants;
+use crate::models::{error_model::ApiErrorType, user_model::User};
+use crate::repository::user_repo;
+use crate::services::user_service;
+
+// Mongo error code when the resume token is older than the oplog.
+const CHANGE_STREAM_HISTORY_LOST: i32 = 286;
+
+// Count of open event streams, limited to `USER.EVENTS.MAX_SUBSCRIBERS`.
+pub struct EventSubscribers {
+    open: Arc<AtomicUsize>,
+    max: usize,
+}
+
+// Held by an open stream. Frees its place when the client goes away.
+struct Subscription(Arc<AtomicUsize>);
+
+impl EventSubscribers {
+    pub fn new(max: usize) -> Self {
+        EventSubscribers {
+            open: Arc::new(AtomicUsize::new(0)),
+            max,
+        }
+    }
+
+    fn subscribe(&self) -> Option<Subscription> {
+        self.open
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+                (open < self.max).then_some(open + 1)
+            })
+            .ok()
+            .map(|_| Subscription(self.open.clone()))
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Stream created, updated and deleted users matching the list filters as Server-Sent Events.
+// The id of each event is its change stream resume token, sent back in `Last-Event-ID`.
+pub async fn user_events(
+    client: &Data<Client>,
+    config: &UserConfig,
+    subscribers: &EventSubscribers,
+    filter: &UserFilter,
+    last_event_id: Option<&str>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let resume_after = match last_event_id {
+        Some(id) => Some(decode_event_id(id).ok_or_else(|| {
+            warn!("Invalid Last-Event-ID: {}", id);
+            ApiErrorType::InvalidEventId
+        })?),
+        None => None,
+    };
+    let subscription = subscribers.subscribe().ok_or_else(|| {
+        warn!("Refusing user event stream, {} open", subscribers.max);
+        ApiErrorType::TooManySubscribers {
+            max: subscribers.max,
+        }
+    })?;
+
+    let filter = user_service::filter_document(filter, "fullDocument.");
+    let resuming = resume_after.is_some();
+    let changes = match user_repo::watch_users(client, filter, resume_after).await {
+        Ok(changes) => changes,
+        Err(err) => {
+            if let ErrorKind::Command(command_error) = err.kind.as_ref() {
+                if resuming && command_error.code == CHANGE_STREAM_HISTORY_LOST {
+                    warn!("User events can not be resumed: {}", err);
+                    return Err(ApiErrorType::InvalidEventId);
+                }
+            }
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    info!("User event stream opened");
+
+    let events = changes
+        .filter_map(|change| {
+            ready(match change {
+                Ok(event) => event_message(&event).map(Ok),
+                Err(err) => {
+                    error!("Error: {}", err);
+                    Some(Err(io::Error::other(err)))
+                }
+            })
+        })
+        .map(Some);
+    // Comments keep proxies from closing idle connections.
+    let every = Duration::from_secs(config.events_heartbeat_secs);
+    let heartbeat = stream::unfold(
+        (
+            time::interval_at(Instant::now() + every, every),
+            subscription,
+        ),
+        |(mut interval, subscription)| async move {
+            interval.tick().await;
+            Some((
+                Some(Ok(Bytes::from_static(b": heartbeat\n\n"))),
+                (interval, subscription),
+            ))
+        },
+    );
+    // End with the change stream, the heartbeat alone never ends.
+    let body = stream::select(events.chain(stream::once(ready(None))), heartbeat)
+        .take_while(|message| ready(message.is_some()))
+        .filter_map(ready);
+
+    Ok(HttpResponse::Ok()
+        .content_type(constants::EVENT_STREAM_CONTENT_TYPE)
+        .insert_header(header::CacheControl(vec![CacheDirective::NoCache]))
+        // Compressing would hold events back until a block is full.
+        .insert_header(ContentEncoding::Identity)
+        .streaming(body))
+}
+
+fn event_message(event: &ChangeStreamEvent<User>) -> Option<Bytes> {
+    // Missing when the user was purged before the lookup.
+    let user = event.full_document.as_ref()?;
+    let deleted = event
+        .update_description
+        .as_ref()
+        .is_some_and(|change| change.updated_fields.contains_key("deleted_at"));
+    let name = match event.operation_type {
+        OperationType::Insert => "created",
+        _ if deleted => "deleted",
+        _ => "updated",
+    };
+    let data = serde_json::to_string(&user_service::to_response(user)).ok()?;
+    Some(Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        encode_event_id(&event.id)?,
+        name,
+        data
+    )))
+}
+
+fn encode_event_id(token: &ResumeToken) -> Option<String> {
+    let bytes = bson::to_vec(token).ok()?;
+    Some(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+fn decode_event_id(id: &str) -> Option<ResumeToken> {
+    let bytes = URL_SAFE_NO_PAD.decode(id.trim()).ok()?;
+    bson::from_slice(&bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    fn event(
+        operation_type: &str,
+        updated_fields: Option<bson::Document>,
+    ) -> ChangeStreamEvent<User> {
+        let mut event = doc! {
+            "_id": {"_data": "8263A1B2C3000000012B0229296E04"},
+            "operationType": operation_type,
+            "fullDocument": {
+                "_id": "usr_1",
+                "name": "John Doe",
+                "location": "Chennai",
+                "title": "Engineer",
+                "version": 2_i64,
+            },
+        };
+        if let Some(updated_fields) = updated_fields {
+            event.insert(
+                "updateDescription",
+                doc! {"updatedFields": updated_fields, "removedFields": []},
+            );
+        }
+        bson::from_document(event).unwrap()
+    }
+
+    fn message(event: &ChangeStreamEvent<User>) -> String {
+        String::from_utf8(event_message(event).unwrap().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn event_id_round_trips() {
+        let token = event("insert", None).id;
+        let id = encode_event_id(&token).unwrap();
+        let decoded = decode_event_id(&format!(" {} ", id)).unwrap();
+        assert_eq!(encode_event_id(&decoded).unwrap(), id);
+        assert!(decode_event_id("not an id").is_none());
+    }
+
+    #[test]
+    fn events_are_named_after_the_change() {
+        assert!(message(&event("insert", None)).contains("\nevent: created\n"));
+        let updated = event("update", Some(doc! {"title": "Architect"}));
+        assert!(message(&updated).contains("\nevent: updated\n"));
+        let deleted = event("update", Some(doc! {"deleted_at": "2026-01-01"}));
+        assert!(message(&deleted).contains("\nevent: deleted\n"));
+    }
+
+    #[test]
+    fn event_message_is_a_server_sent_event() {
+        let message = message(&event("insert", None));
+        assert!(message.starts_with("id: "));
+        assert!(message.ends_with("\n\n"));
+        let data = message
+            .lines()
+            .find_map(|line| line.strip_prefix("data: "))
+            .unwrap();
+        let user: serde_json::Value = serde_json::from_str(data).unwrap();
+        assert_eq!(user["_id"], "usr_1");
+    }
+}
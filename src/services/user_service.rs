@@ -1,28 +1,75 @@
-use actix_web::web::{Data, Json, Path};
-use actix_web::HttpResponse;
-use log::{error, warn};
-use mongodb::error::Error;
+use std::collections::BTreeMap;
+use std::io;
+use std::time::{Duration as StdDuration, SystemTime};
+
+use actix_web::http::header::{
+    self, ETag, EntityTag, HttpDate, IfMatch, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::rt::{spawn, time};
+use actix_web::web::{Bytes, Data, Path};
+use actix_web::{HttpResponse, HttpResponseBuilder};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use futures::{stream, StreamExt};
+use json_patch::Patch;
+use log::{error, info, warn};
+use mongodb::bson::{doc, Document};
+use mongodb::error::{BulkWriteFailure, Error, ErrorKind};
 use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use validator::{Validate, ValidationErrors};
 
-use crate::api::user_api::Pagination;
+use crate::api::user_api::{
+    BulkOperation, BulkRequest, BulkResponse, BulkResult, BulkStatus, ExportFormat, ImportResponse,
+    ImportRowError, Pagination, UserFields, UserFilter, UserHistoryResponse, UserRecord,
+    UserResponse, UserRevisionResponse, UserSearch,
+};
+use crate::auth::claims::Claims;
+use crate::config::user::UserConfig;
 use crate::constants;
-use crate::models::user_list_response::{Link, LinkHref, Meta, UserListResponse};
+use crate::models::auth_model::AccountSummary;
+use crate::models::user_list_response::{CountMode, Link, LinkHref, Meta, UserListResponse, Users};
+use crate::repository::{auth_repo, avatar_repo, user_history_repo};
 use crate::{models::error_model::ApiErrorType, models::user_model::User, repository::user_repo};
 
 // add a new user to MongoDB
 pub async fn create_user(
     client: &Data<Client>,
-    new_user: Json<User>,
+    claims: &Claims,
+    new_user: User,
 ) -> Result<HttpResponse, ApiErrorType> {
+    let user = add_user(client, &claims.sub, new_user).await?;
+    let mut response = HttpResponse::Created();
+    validators(&mut response, &user);
+    Ok(response.json(to_response(&user)))
+}
+
+// Add a user with the fields of `new_user`. Shared by the REST, GraphQL and gRPC endpoints.
+pub async fn add_user(
+    client: &Data<Client>,
+    created_by: &str,
+    new_user: User,
+) -> Result<User, ApiErrorType> {
     let data = User {
         id: None,
         name: new_user.name.to_owned(),
         location: new_user.location.to_owned(),
         title: new_user.title.to_owned(),
+        auth_id: None,
+        version: 0,
+        created_ts: None,
+        created_by: None,
+        updated_ts: None,
+        updated_by: None,
+        deleted_at: None,
+        deleted_by: None,
     };
-    let user_detail = user_repo::create_user(client, data).await;
+    let user_detail = user_repo::create_user(client, data, created_by).await;
     match user_detail {
-        Ok(Some(user)) => Ok(HttpResponse::Created().json(user)),
+        Ok(Some(user)) => Ok(user),
         Ok(None) => Err(ApiErrorType::InternalServerError),
         Err(err) => {
             error!("Error: {}", err);
@@ -31,41 +78,566 @@ pub async fn create_user(
     }
 }
 
+// Run a batch of user operations in request order. Consecutive creates are inserted together.
+// Ordered batches stop at the first failed operation and report the rest as skipped.
+pub async fn bulk_users(
+    client: &Data<Client>,
+    config: &UserConfig,
+    claims: &Claims,
+    request: BulkRequest,
+) -> Result<HttpResponse, ApiErrorType> {
+    if request.operations.len() > config.bulk_max_operations {
+        return Err(ApiErrorType::BatchTooLarge {
+            max: config.bulk_max_operations,
+        });
+    }
+    let ordered = request.ordered.unwrap_or(true);
+
+    let mut results = vec![];
+    let mut stopped = false;
+    let mut operations = request.operations.into_iter().enumerate().peekable();
+    while let Some((index, operation)) = operations.next() {
+        if stopped {
+            results.push(bulk_result(index, BulkStatus::Skipped, None, None));
+            continue;
+        }
+        let step = match operation {
+            BulkOperation::Create { user } => {
+                let mut batch = vec![(index, user)];
+                while let Some((_, BulkOperation::Create { .. })) = operations.peek() {
+                    if let Some((index, BulkOperation::Create { user })) = operations.next() {
+                        batch.push((index, user));
+                    }
+                }
+                bulk_create(client, claims, batch, ordered).await
+            }
+            BulkOperation::Update { id, user, version } => {
+                vec![bulk_update(client, claims, index, id, user, version).await]
+            }
+            BulkOperation::Delete { id, version } => {
+                vec![bulk_delete(client, claims, index, id, version).await]
+            }
+        };
+        stopped = ordered
+            && step
+                .iter()
+                .any(|result| result.status == BulkStatus::Failed);
+        results.extend(step);
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| result.status == BulkStatus::Failed)
+        .count();
+    let skipped = results
+        .iter()
+        .filter(|result| result.status == BulkStatus::Skipped)
+        .count();
+    Ok(HttpResponse::Ok().json(BulkResponse {
+        ordered,
+        succeeded: results.len() - failed - skipped,
+        failed,
+        skipped,
+        results,
+    }))
+}
+
+// Validate and insert a run of creates with one `insert_many`.
+async fn bulk_create(
+    client: &Data<Client>,
+    claims: &Claims,
+    batch: Vec<(usize, User)>,
+    ordered: bool,
+) -> Vec<BulkResult> {
+    let mut results = vec![];
+    let mut indexes = vec![];
+    let mut new_users = vec![];
+    let mut invalid = false;
+    for (index, user) in batch {
+        if ordered && invalid {
+            results.push(bulk_result(index, BulkStatus::Skipped, None, None));
+            continue;
+        }
+        match user.validate() {
+            Ok(_) => {
+                indexes.push(index);
+                new_users.push(user_repo::new_user_document(user, &claims.sub));
+            }
+            Err(err) => {
+                invalid = true;
+                results.push(bulk_failure(index, None, user_validation_error(err)));
+            }
+        }
+    }
+    if new_users.is_empty() {
+        return results;
+    }
+
+    // Positions in `new_users` that were not inserted.
+    let write_errors: Vec<usize> = match user_repo::insert_users(client, &new_users, ordered).await
+    {
+        Ok(_) => vec![],
+        Err(err) => {
+            error!("Error: {}", err);
+            match err.kind.as_ref() {
+                ErrorKind::BulkWrite(BulkWriteFailure {
+                    write_errors: Some(errors),
+                    write_concern_error: None,
+                    ..
+                }) => errors.iter().map(|error| error.index).collect(),
+                _ => (0..new_users.len()).collect(),
+            }
+        }
+    };
+    let first_error = write_errors.iter().min().copied();
+    for (position, (index, user)) in indexes.into_iter().zip(new_users).enumerate() {
+        if write_errors.contains(&position) {
+            results.push(bulk_failure(index, None, ApiErrorType::InternalServerError));
+        } else if ordered && first_error.is_some_and(|first| position > first) {
+            results.push(bulk_result(index, BulkStatus::Skipped, None, None));
+        } else {
+            results.push(bulk_result(index, BulkStatus::Created, user.id, None));
+        }
+    }
+    results.sort_by_key(|result| result.index);
+    results
+}
+
+async fn bulk_update(
+    client: &Data<Client>,
+    claims: &Claims,
+    index: usize,
+    id: String,
+    user: User,
+    version: Option<i64>,
+) -> BulkResult {
+    if let Err(err) = user.validate() {
+        return bulk_failure(index, Some(id), user_validation_error(err));
+    }
+    let versions = version.map(|version| vec![version]);
+    let data = User {
+        id: Some(String::from(&id)),
+        name: user.name,
+        location: user.location,
+        title: user.title,
+        auth_id: None,
+        version: 0,
+        created_ts: None,
+        created_by: None,
+        updated_ts: None,
+        updated_by: None,
+        deleted_at: None,
+        deleted_by: None,
+    };
+    match user_repo::update_user(client, &id, data, versions.as_deref(), &claims.sub).await {
+        Ok(Some(_)) => bulk_result(index, BulkStatus::Updated, Some(id), None),
+        Ok(None) if versions.is_some() => {
+            let err = missing_or_changed(client, &id).await;
+            bulk_failure(index, Some(id), err)
+        }
+        Ok(None) => bulk_failure(index, Some(id), ApiErrorType::UserNotFound),
+        Err(err) => {
+            error!("Error: {}", err);
+            bulk_failure(index, Some(id), ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+async fn bulk_delete(
+    client: &Data<Client>,
+    claims: &Claims,
+    index: usize,
+    id: String,
+    version: Option<i64>,
+) -> BulkResult {
+    let versions = version.map(|version| vec![version]);
+    match user_repo::delete_user(client, &id, versions.as_deref(), &claims.sub).await {
+        Ok(Some(_)) => bulk_result(index, BulkStatus::Deleted, Some(id), None),
+        Ok(None) if versions.is_some() => {
+            let err = missing_or_changed(client, &id).await;
+            bulk_failure(index, Some(id), err)
+        }
+        Ok(None) => bulk_failure(index, Some(id), ApiErrorType::UserNotFound),
+        Err(err) => {
+            error!("Error: {}", err);
+            bulk_failure(index, Some(id), ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+fn bulk_result(
+    index: usize,
+    status: BulkStatus,
+    id: Option<String>,
+    err: Option<ApiErrorType>,
+) -> BulkResult {
+    BulkResult {
+        index,
+        status,
+        id,
+        error: err.map(|err| err.api_error()),
+    }
+}
+
+fn bulk_failure(index: usize, id: Option<String>, err: ApiErrorType) -> BulkResult {
+    bulk_result(index, BulkStatus::Failed, id, Some(err))
+}
+
+fn user_validation_error(err: ValidationErrors) -> ApiErrorType {
+    ApiErrorType::ValidationError {
+        validation_error: err,
+        object: "User".to_string(),
+    }
+}
+
+// Columns of the CSV export, in `UserRecord` order.
+const USER_CSV_HEADER: [&str; 6] = ["id", "name", "location", "title", "version", "updated_ts"];
+
+// Stream users matching the list filters straight from the Mongo cursor.
+pub async fn export_users(
+    client: &Data<Client>,
+    filter: &UserFilter,
+    format: ExportFormat,
+) -> Result<HttpResponse, ApiErrorType> {
+    let sort = parse_sort(filter.sort.as_deref())?;
+    let cursor =
+        match user_repo::stream_users(client, filter_document(filter, ""), sort_document(&sort))
+            .await
+        {
+            Ok(cursor) => cursor,
+            Err(err) => {
+                error!("Error: {}", err);
+                return Err(ApiErrorType::InternalServerError);
+            }
+        };
+
+    let (content_type, extension, header_line) = match format {
+        ExportFormat::Csv => (
+            constants::CSV_CONTENT_TYPE,
+            "csv",
+            Some(csv_line(|writer| writer.write_record(USER_CSV_HEADER))),
+        ),
+        ExportFormat::Ndjson => (constants::NDJSON_CONTENT_TYPE, "ndjson", None),
+    };
+    let lines = cursor.map(move |user| match user {
+        Ok(user) => export_line(format, &user),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(io::Error::other(err))
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"users.{}\"", extension),
+        ))
+        .streaming(stream::iter(header_line).chain(lines)))
+}
+
+fn export_line(format: ExportFormat, user: &User) -> Result<Bytes, io::Error> {
+    match format {
+        ExportFormat::Csv => csv_line(|writer| writer.serialize(to_record(user))),
+        ExportFormat::Ndjson => {
+            let mut line = serde_json::to_vec(&to_response(user))?;
+            line.push(b'\n');
+            Ok(Bytes::from(line))
+        }
+    }
+}
+
+// Write one CSV line with quoting and escaping.
+fn csv_line(
+    write: impl FnOnce(&mut csv::Writer<Vec<u8>>) -> csv::Result<()>,
+) -> Result<Bytes, io::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    write(&mut writer)?;
+    match writer.into_inner() {
+        Ok(line) => Ok(Bytes::from(line)),
+        Err(err) => Err(err.into_error()),
+    }
+}
+
+// Create users from a CSV or NDJSON upload. Every row is validated and failed rows are
+// reported by number. A dry run only validates.
+pub async fn import_users(
+    client: &Data<Client>,
+    config: &UserConfig,
+    claims: &Claims,
+    content_type: &str,
+    dry_run: bool,
+    body: &[u8],
+) -> Result<HttpResponse, ApiErrorType> {
+    let rows = match content_type {
+        constants::CSV_CONTENT_TYPE => read_csv(body),
+        constants::NDJSON_CONTENT_TYPE => read_ndjson(body),
+        _ => {
+            return Err(ApiErrorType::UnsupportedMediaType {
+                supported: format!(
+                    "{} or {}",
+                    constants::CSV_CONTENT_TYPE,
+                    constants::NDJSON_CONTENT_TYPE
+                ),
+            })
+        }
+    };
+    let total = rows.len();
+
+    let mut errors = vec![];
+    let mut valid = vec![];
+    for (row, user) in rows {
+        let user = user.and_then(|user| match user.validate() {
+            Ok(_) => Ok(user),
+            Err(err) => Err(user_validation_error(err)),
+        });
+        match user {
+            Ok(user) => valid.push((row, user)),
+            Err(err) => errors.push(ImportRowError {
+                row,
+                error: err.api_error(),
+            }),
+        }
+    }
+    let valid_count = valid.len();
+
+    let mut imported = 0;
+    if !dry_run {
+        let mut batch = valid;
+        while !batch.is_empty() {
+            let rest = batch.split_off(batch.len().min(config.bulk_max_operations));
+            for result in bulk_create(client, claims, batch, false).await {
+                match result.error {
+                    Some(error) => errors.push(ImportRowError {
+                        row: result.index,
+                        error,
+                    }),
+                    None => imported += 1,
+                }
+            }
+            batch = rest;
+        }
+    }
+    errors.sort_by_key(|err| err.row);
+
+    Ok(HttpResponse::Ok().json(ImportResponse {
+        dry_run,
+        rows: total,
+        valid: valid_count,
+        imported,
+        failed: errors.len(),
+        errors,
+    }))
+}
+
+// Read CSV records by header name.
+fn read_csv(body: &[u8]) -> Vec<(usize, Result<User, ApiErrorType>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+    reader
+        .deserialize::<UserRecord>()
+        .enumerate()
+        .map(|(position, record)| {
+            let user = match record {
+                Ok(record) => Ok(from_record(record)),
+                Err(err) => Err(ApiErrorType::MalformedRecord {
+                    detail: err.to_string(),
+                }),
+            };
+            (position + 1, user)
+        })
+        .collect()
+}
+
+// Read one JSON user per line. Blank lines are skipped but still counted.
+fn read_ndjson(body: &[u8]) -> Vec<(usize, Result<User, ApiErrorType>)> {
+    body.split(|byte| *byte == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim_ascii().is_empty())
+        .map(|(position, line)| {
+            let user =
+                serde_json::from_slice::<User>(line).map_err(|err| ApiErrorType::MalformedRecord {
+                    detail: err.to_string(),
+                });
+            (position + 1, user)
+        })
+        .collect()
+}
+
+fn to_record(user: &User) -> UserRecord {
+    UserRecord {
+        id: user.id.clone(),
+        name: user.name.to_owned(),
+        location: user.location.to_owned(),
+        title: user.title.to_owned(),
+        version: Some(user.version),
+        updated_ts: user
+            .updated_ts
+            .map(|ts| ts.to_rfc3339_opts(SecondsFormat::Micros, true)),
+    }
+}
+
+fn from_record(record: UserRecord) -> User {
+    User {
+        id: None,
+        name: record.name,
+        location: record.location,
+        title: record.title,
+        auth_id: None,
+        version: 0,
+        created_ts: None,
+        created_by: None,
+        updated_ts: None,
+        updated_by: None,
+        deleted_at: None,
+        deleted_by: None,
+    }
+}
+
+// Get a user that is not deleted. Used by the gRPC endpoint.
+pub async fn find_user(client: &Data<Client>, id: &String) -> Result<User, ApiErrorType> {
+    match user_repo::get_user(client, id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => {
+            warn!("User with id - {} not found for get user by ID", id);
+            Err(ApiErrorType::UserNotFound)
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// A page of users matching the filter, sorted by name, and the number of matching users.
+// Used by the gRPC endpoint.
+pub async fn find_users(
+    client: &Data<Client>,
+    filter: &UserFilter,
+    offset: u64,
+    limit: i64,
+) -> Result<(Vec<User>, u64), ApiErrorType> {
+    let filter_doc = filter_document(filter, "");
+    let page = user_repo::get_user_page(
+        client,
+        filter_doc.clone(),
+        doc! {"name": 1, "_id": 1},
+        offset,
+        limit,
+    );
+    let total = user_repo::get_users_size(client, filter_doc);
+    match futures::future::try_join(page, total).await {
+        Ok(result) => Ok(result),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
 pub async fn get_user_by_id(
     client: &Data<Client>,
+    config: &UserConfig,
     path: Path<String>,
+    fields: &UserFields,
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
 ) -> Result<HttpResponse, ApiErrorType> {
     let id = path.into_inner();
     if id.is_empty() {
         warn!("User with id - {} not found for get user by ID", id);
         return Err(ApiErrorType::BadRequest);
     }
-    let user_detail = user_repo::get_user(client, &id).await;
-    handle_optional_user_response(user_detail)
+    let selection = parse_fields(fields, &constants::USER_FIELDS)?;
+    let user = match user_projection(&selection) {
+        Some(projection) => user_repo::get_user_fields(client, &id, projection).await,
+        None => user_repo::get_user(client, &id).await,
+    };
+    match user {
+        Ok(Some(user)) => {
+            let mut body = to_response(&user);
+            if selection.account {
+                body.account = linked_account(client, user.auth_id.as_ref()).await?;
+            }
+            let mut body = serde_json::to_value(body).unwrap_or_default();
+            retain_fields(&mut body, &selection, "_id");
+            // The embedded account changes without the user version. Then the tag is taken
+            // from the whole body and there is no `Last-Modified`.
+            let (tag, updated_ts) = if selection.account {
+                (content_tag(body.to_string().as_bytes()), None)
+            } else {
+                (entity_tag(&user), user.updated_ts)
+            };
+            let not_modified = is_not_modified(&tag, updated_ts, if_none_match, if_modified_since);
+            let mut response = if not_modified {
+                HttpResponse::NotModified()
+            } else {
+                HttpResponse::Ok()
+            };
+            response.insert_header((header::CACHE_CONTROL, config.user_cache_control.as_str()));
+            tag_validators(&mut response, tag, updated_ts);
+            if not_modified {
+                return Ok(response.finish());
+            }
+            Ok(response.json(body))
+        }
+        Ok(None) => Err(ApiErrorType::UserNotFound),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
 }
 
 pub async fn update_user(
     client: &Data<Client>,
+    config: &UserConfig,
+    claims: &Claims,
     path: Path<String>,
-    update_user: Json<User>,
+    update_user: User,
+    if_match: Option<IfMatch>,
 ) -> Result<HttpResponse, ApiErrorType> {
     let id = path.into_inner();
     if id.is_empty() {
         return Err(ApiErrorType::BadRequest);
     };
+    let versions = accepted_versions(config, if_match)?;
+    let user = replace_user(client, &id, update_user, versions.as_deref(), &claims.sub).await?;
+    handle_optional_user_response(Ok(Some(user)))
+}
+
+// Replace the fields of a user. With `versions`, only when its stored version is one of them.
+// Shared by the REST, GraphQL and gRPC endpoints.
+pub async fn replace_user(
+    client: &Data<Client>,
+    id: &String,
+    update_user: User,
+    versions: Option<&[i64]>,
+    updated_by: &str,
+) -> Result<User, ApiErrorType> {
     let data = User {
-        id: Some(String::from(&id)),
+        id: Some(String::from(id)),
         name: update_user.name.to_owned(),
         location: update_user.location.to_owned(),
         title: update_user.title.to_owned(),
+        auth_id: None,
+        version: 0,
+        created_ts: None,
+        created_by: None,
+        updated_ts: None,
+        updated_by: None,
+        deleted_at: None,
+        deleted_by: None,
     };
 
-    let update_result = user_repo::update_user(client, &id, data).await;
+    let update_result = user_repo::update_user(client, id, data, versions, updated_by).await;
     match update_result {
-        Ok(update) => {
-            if update.matched_count == 1 {
-                let updated_user_info = user_repo::get_user(client, &id).await;
-                handle_optional_user_response(updated_user_info)
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => {
+            if versions.is_some() {
+                Err(missing_or_changed(client, id).await)
             } else {
                 warn!("User with id -{} not found update user by ID", id);
                 Err(ApiErrorType::UserNotFound)
@@ -78,19 +650,158 @@ pub async fn update_user(
     }
 }
 
+// Patch document of a PATCH request.
+enum UserPatch {
+    // RFC 7396 JSON Merge Patch.
+    Merge(Value),
+    // RFC 6902 JSON Patch operations.
+    Operations(Patch),
+}
+
+// Patch a user. The patched user is validated and written only if the stored user did not
+// change since it was read. Concurrent changes are retried a few times, unless the client
+// asked for a specific version with `If-Match`.
+pub async fn patch_user(
+    client: &Data<Client>,
+    config: &UserConfig,
+    claims: &Claims,
+    path: Path<String>,
+    content_type: &str,
+    patch: Value,
+    if_match: Option<IfMatch>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let id = path.into_inner();
+    if id.is_empty() {
+        return Err(ApiErrorType::BadRequest);
+    };
+    let versions = accepted_versions(config, if_match)?;
+    let patch = match content_type {
+        constants::MERGE_PATCH_CONTENT_TYPE => UserPatch::Merge(patch),
+        constants::JSON_PATCH_CONTENT_TYPE => match serde_json::from_value(patch) {
+            Ok(operations) => UserPatch::Operations(operations),
+            Err(err) => {
+                return Err(ApiErrorType::InvalidPatch {
+                    detail: err.to_string(),
+                })
+            }
+        },
+        _ => {
+            return Err(ApiErrorType::UnsupportedMediaType {
+                supported: format!(
+                    "{} or {}",
+                    constants::MERGE_PATCH_CONTENT_TYPE,
+                    constants::JSON_PATCH_CONTENT_TYPE
+                ),
+            })
+        }
+    };
+
+    for _ in 0..constants::MAX_PATCH_ATTEMPTS {
+        let current = match user_repo::get_user(client, &id).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                warn!("User with id -{} not found for patch user by ID", id);
+                return Err(ApiErrorType::UserNotFound);
+            }
+            Err(err) => {
+                error!("Error: {}", err);
+                return Err(ApiErrorType::InternalServerError);
+            }
+        };
+        if let Some(versions) = &versions {
+            if !versions.contains(&current.version) {
+                return Err(ApiErrorType::PreconditionFailed);
+            }
+        }
+
+        let patched = apply_patch(&current, &patch)?;
+        if let Err(err) = patched.validate() {
+            warn!("Payload validation Error on patch user: {}", err);
+            return Err(ApiErrorType::ValidationError {
+                validation_error: err,
+                object: "User".to_string(),
+            });
+        }
+
+        match user_repo::patch_user(client, &current, &patched, &claims.sub).await {
+            Ok(Some(user)) => return handle_optional_user_response(Ok(Some(user))),
+            Ok(None) => warn!("User with id -{} changed while patching. Retrying", id),
+            Err(err) => {
+                error!("Error: {}", err);
+                return Err(ApiErrorType::InternalServerError);
+            }
+        }
+    }
+    Err(ApiErrorType::UpdateConflict)
+}
+
+// Apply the patch to the JSON form of the user, as returned by GET.
+fn apply_patch(current: &User, patch: &UserPatch) -> Result<User, ApiErrorType> {
+    let mut doc = match serde_json::to_value(to_response(current)) {
+        Ok(doc) => doc,
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    match patch {
+        UserPatch::Merge(merge) => json_patch::merge(&mut doc, merge),
+        UserPatch::Operations(operations) => {
+            if let Err(err) = json_patch::patch(&mut doc, operations) {
+                return Err(ApiErrorType::InvalidPatch {
+                    detail: err.to_string(),
+                });
+            }
+        }
+    }
+
+    let patched: User = match serde_json::from_value(doc) {
+        Ok(user) => user,
+        Err(err) => {
+            return Err(ApiErrorType::InvalidPatch {
+                detail: err.to_string(),
+            })
+        }
+    };
+    if patched.id != current.id {
+        return Err(ApiErrorType::InvalidPatch {
+            detail: "_id can not be changed".to_owned(),
+        });
+    }
+    Ok(patched)
+}
+
+// Soft delete a user. It can be restored until it is purged.
 pub async fn delete_user(
     client: &Data<Client>,
+    config: &UserConfig,
+    claims: &Claims,
     path: Path<String>,
+    if_match: Option<IfMatch>,
 ) -> Result<HttpResponse, ApiErrorType> {
     let id = path.into_inner();
     if id.is_empty() {
         return Err(ApiErrorType::UserNotFound);
     };
-    let result = user_repo::delete_user(client, &id).await;
+    let versions = accepted_versions(config, if_match)?;
+    remove_user(client, &id, versions.as_deref(), &claims.sub).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Soft delete a user. With `versions`, only when its stored version is one of them.
+// Shared by the REST, GraphQL and gRPC endpoints.
+pub async fn remove_user(
+    client: &Data<Client>,
+    id: &String,
+    versions: Option<&[i64]>,
+    deleted_by: &str,
+) -> Result<(), ApiErrorType> {
+    let result = user_repo::delete_user(client, id, versions, deleted_by).await;
     match result {
-        Ok(res) => {
-            if res.deleted_count == 1 {
-                Ok(HttpResponse::NoContent().finish())
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => {
+            if versions.is_some() {
+                Err(missing_or_changed(client, id).await)
             } else {
                 warn!("User with id -{} not found for delete user by ID", id);
                 Err(ApiErrorType::UserNotFound)
@@ -103,77 +814,1571 @@ pub async fn delete_user(
     }
 }
 
-pub async fn get_all_users(
+// Bring back a soft deleted user that was not purged yet.
+pub async fn restore_user(
+    client: &Data<Client>,
+    claims: &Claims,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let id = path.into_inner();
+    match user_repo::restore_user(client, &id, &claims.sub).await {
+        Ok(Some(user)) => handle_optional_user_response(Ok(Some(user))),
+        Ok(None) => {
+            warn!("Deleted user with id -{} not found for restore", id);
+            Err(ApiErrorType::UserNotFound)
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// List soft deleted users, most recently deleted first.
+pub async fn get_deleted_users(
     client: &Data<Client>,
     pagination: &Pagination,
 ) -> Result<HttpResponse, ApiErrorType> {
     let offset = pagination.offset.unwrap_or(constants::DEFAULT_OFFSET_SIZE);
-    let limit = pagination.limit.unwrap_or(constants::DEFAULT_LIMIT_SIZE);
-    let user_list = user_repo::get_all_users(client, offset, limit).await;
-    let user_count = user_repo::get_users_size(client).await.unwrap_or(0);
-    let last_offset = (user_count / (limit as u64)) * limit as u64;
+    let limit = page_limit(pagination.limit)?;
+    match user_repo::get_deleted_users(client, offset, limit).await {
+        Ok(users) => Ok(HttpResponse::Ok().json(users.iter().map(to_response).collect::<Vec<_>>())),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
 
-    let next_offset = i64::try_from(offset).unwrap_or(0) + limit;
-    let previous_offset = i64::try_from(offset).unwrap_or(0) - limit;
+// List the revisions of a user, newest first. The history is kept after the user is purged.
+pub async fn get_user_history(
+    client: &Data<Client>,
+    id: String,
+    pagination: &Pagination,
+) -> Result<HttpResponse, ApiErrorType> {
+    let offset = pagination.offset.unwrap_or(constants::DEFAULT_OFFSET_SIZE);
+    let limit = page_limit(pagination.limit)?;
 
-    match user_list {
-        Ok(u) => {
-            let response = UserListResponse {
-                data: u,
-                meta: Meta {
-                    offset,
-                    limit,
-                    total_results: user_count,
-                    search_criteria: None,
-                    sort_by: None,
-                },
-                _link: Link {
-                    first: LinkHref {
-                        href: format!("/api/users?offset={}&limit={}", 0, limit).to_string(),
-                    },
-                    last: LinkHref {
-                        href: format!("/api/users?offset={}&limit={}", last_offset, limit)
-                            .to_string(),
-                    },
-                    previous: if previous_offset < 0 {
-                        None
-                    } else {
-                        Some(LinkHref {
-                            href: format!("/api/users?offset={}&limit={}", previous_offset, limit)
-                                .to_string(),
-                        })
-                    },
-                    next: if (next_offset as u64) > last_offset {
-                        None
-                    } else {
-                        Some(LinkHref {
-                            href: format!("/api/users?offset={}&limit={}", next_offset, limit)
-                                .to_string(),
-                        })
-                    },
-                    self_link: LinkHref {
-                        href: format!("/api/users?offset={}&limit={}", offset, limit).to_string(),
-                    },
-                },
-            };
-            Ok(HttpResponse::Ok().json(response))
-        }
+    let total = match user_history_repo::get_history_size(client, &id).await {
+        Ok(total) => total,
         Err(err) => {
-            error!("Error : {}", err);
-            Err(ApiErrorType::InternalServerError)
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    // A user that was never changed has no history yet.
+    if total == 0 {
+        match user_repo::get_user(client, &id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                warn!("User with id -{} not found for history", id);
+                return Err(ApiErrorType::UserNotFound);
+            }
+            Err(err) => {
+                error!("Error: {}", err);
+                return Err(ApiErrorType::InternalServerError);
+            }
         }
     }
+    let revisions = match user_history_repo::get_history(client, &id, offset, limit).await {
+        Ok(revisions) => revisions,
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+
+    let last_offset = (total / (limit as u64)) * limit as u64;
+    let has_next = offset + (limit as u64) <= last_offset;
+    Ok(HttpResponse::Ok().json(UserHistoryResponse {
+        data: revisions
+            .iter()
+            .map(|revision| UserRevisionResponse {
+                rev: revision.rev,
+                operation: revision.operation,
+                changed_by: revision.changed_by.to_owned(),
+                changed_ts: revision
+                    .changed_ts
+                    .to_rfc3339_opts(SecondsFormat::Micros, true),
+                user: to_response(&revision.user),
+                changes: revision.changes.clone(),
+            })
+            .collect(),
+        meta: Meta {
+            offset: Some(offset),
+            limit,
+            total_results: Some(total),
+            count: CountMode::Exact,
+            search_criteria: None,
+            sort_by: None,
+        },
+        _link: offset_links(
+            &format!("/api/users/{}/history", id),
+            offset,
+            limit,
+            Some(last_offset),
+            has_next,
+            "",
+        ),
+    }))
 }
 
-fn handle_optional_user_response(
-    user: Result<Option<User>, Error>,
+// Set the name, location and title of a user back to the ones of a revision. The revert is a
+// new revision, so it can be reverted in turn.
+pub async fn revert_user(
+    client: &Data<Client>,
+    config: &UserConfig,
+    claims: &Claims,
+    id: String,
+    rev: i64,
+    if_match: Option<IfMatch>,
 ) -> Result<HttpResponse, ApiErrorType> {
-    match user {
-        Ok(Some(user)) => Ok(HttpResponse::Ok().json(user)),
-        Ok(None) => Err(ApiErrorType::UserNotFound),
+    let versions = accepted_versions(config, if_match)?;
+    let revision = match user_history_repo::get_revision(client, &id, rev).await {
+        Ok(Some(revision)) => revision,
+        Ok(None) => {
+            warn!("User with id -{} has no revision {}", id, rev);
+            return Err(ApiErrorType::RevisionNotFound);
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    let result = user_repo::revert_user(
+        client,
+        &id,
+        &revision.user,
+        versions.as_deref(),
+        &claims.sub,
+    )
+    .await;
+    match result {
+        Ok(Some(user)) => handle_optional_user_response(Ok(Some(user))),
+        Ok(None) if versions.is_some() => Err(missing_or_changed(client, &id).await),
+        Ok(None) => {
+            warn!("User with id -{} not found for revert", id);
+            Err(ApiErrorType::UserNotFound)
+        }
         Err(err) => {
             error!("Error: {}", err);
             Err(ApiErrorType::InternalServerError)
         }
     }
 }
+
+// Periodically remove users soft deleted longer than the retention period.
+pub fn spawn_purge_sweeper(client: Data<Client>, retention_days: i64, interval_secs: u64) {
+    spawn(async move {
+        let mut interval = time::interval(StdDuration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            let deleted_before = Utc::now() - Duration::days(retention_days);
+            match purge_users(&client, deleted_before).await {
+                Ok(purged) if purged > 0 => info!("Purged {} deleted users", purged),
+                Ok(_) => {}
+                Err(err) => error!("Error purging deleted users: {}", err),
+            }
+        }
+    });
+}
+
+// Permanently remove users soft deleted before the given time, with their avatar files.
+async fn purge_users(client: &Data<Client>, deleted_before: DateTime<Utc>) -> Result<u64, Error> {
+    let ids = user_repo::get_purgeable_user_ids(client, deleted_before).await?;
+    let mut purged = 0;
+    for id in ids {
+        if !user_repo::purge_user(client, &id, deleted_before).await? {
+            continue;
+        }
+        purged += 1;
+        if let Err(err) = avatar_repo::delete_all(client, &id).await {
+            error!("Error deleting avatar of purged user {}: {}", id, err);
+        }
+    }
+    Ok(purged)
+}
+
+// Versions of the user accepted by the `If-Match` header. `None` accepts any version.
+// Weak tags never match, as If-Match uses the strong comparison.
+pub fn accepted_versions(
+    config: &UserConfig,
+    if_match: Option<IfMatch>,
+) -> Result<Option<Vec<i64>>, ApiErrorType> {
+    match if_match {
+        Some(IfMatch::Any) => Ok(None),
+        Some(IfMatch::Items(tags)) if !tags.is_empty() => Ok(Some(
+            tags.iter()
+                .filter(|tag| !tag.weak)
+                .filter_map(|tag| tag.tag().parse().ok())
+                .collect(),
+        )),
+        _ if config.require_if_match => Err(ApiErrorType::PreconditionRequired),
+        _ => Ok(None),
+    }
+}
+
+// Tell apart a conditional write that missed because of the version or a missing user.
+pub async fn missing_or_changed(client: &Data<Client>, id: &String) -> ApiErrorType {
+    match user_repo::get_user(client, id).await {
+        Ok(Some(_)) => {
+            warn!("User with id -{} does not match If-Match", id);
+            ApiErrorType::PreconditionFailed
+        }
+        Ok(None) => {
+            warn!("User with id -{} not found for conditional write", id);
+            ApiErrorType::UserNotFound
+        }
+        Err(err) => {
+            error!("Error: {}", err);
+            ApiErrorType::InternalServerError
+        }
+    }
+}
+
+fn entity_tag(user: &User) -> EntityTag {
+    EntityTag::new_strong(user.version.to_string())
+}
+
+// Strong tag of a response body that has no version of its own.
+pub fn content_tag(body: &[u8]) -> EntityTag {
+    let digest = Sha256::digest(body);
+    EntityTag::new_strong(hex::encode(&digest[..16]))
+}
+
+// Add the cache validators of the user to the response.
+pub fn validators(response: &mut HttpResponseBuilder, user: &User) {
+    tag_validators(response, entity_tag(user), user.updated_ts);
+}
+
+fn tag_validators(
+    response: &mut HttpResponseBuilder,
+    tag: EntityTag,
+    updated_ts: Option<DateTime<Utc>>,
+) {
+    response.insert_header(ETag(tag));
+    if let Some(updated_ts) = updated_ts {
+        response.insert_header(LastModified(HttpDate::from(SystemTime::from(updated_ts))));
+    }
+}
+
+// Whether a conditional GET can be answered with 304 Not Modified.
+// `If-Modified-Since` is only used without `If-None-Match`.
+pub fn is_not_modified(
+    tag: &EntityTag,
+    updated_ts: Option<DateTime<Utc>>,
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+) -> bool {
+    match if_none_match {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) if !tags.is_empty() => {
+            tags.iter().any(|candidate| candidate.weak_eq(tag))
+        }
+        _ => match (updated_ts, if_modified_since) {
+            // HTTP dates have no sub-second precision.
+            (Some(updated_ts), Some(IfModifiedSince(since))) => {
+                updated_ts.timestamp() <= DateTime::<Utc>::from(SystemTime::from(since)).timestamp()
+            }
+            _ => false,
+        },
+    }
+}
+
+pub fn to_response(user: &User) -> UserResponse {
+    UserResponse {
+        id: user.id.clone().unwrap_or_default(),
+        name: user.name.to_owned(),
+        location: user.location.to_owned(),
+        title: user.title.to_owned(),
+        auth_id: user.auth_id.to_owned(),
+        version: user.version,
+        created_ts: user
+            .created_ts
+            .map(|ts| ts.to_rfc3339_opts(SecondsFormat::Micros, true)),
+        created_by: user.created_by.to_owned(),
+        updated_ts: user
+            .updated_ts
+            .map(|ts| ts.to_rfc3339_opts(SecondsFormat::Micros, true)),
+        updated_by: user.updated_by.to_owned(),
+        deleted_at: user
+            .deleted_at
+            .map(|ts| ts.to_rfc3339_opts(SecondsFormat::Micros, true)),
+        deleted_by: user.deleted_by.to_owned(),
+        account: None,
+    }
+}
+
+pub async fn get_all_users(
+    client: &Data<Client>,
+    config: &UserConfig,
+    pagination: &Pagination,
+    filter: &UserFilter,
+    fields: &UserFields,
+    if_none_match: Option<IfNoneMatch>,
+) -> Result<HttpResponse, ApiErrorType> {
+    let limit = page_limit(pagination.limit)?;
+    let sort = parse_sort(filter.sort.as_deref())?;
+    let sort_by = sort_param(&sort);
+    let filter_doc = filter_document(filter, "");
+    let selection = parse_fields(fields, &constants::USER_LIST_FIELDS)?;
+    let projection = list_projection(&selection, &sort);
+
+    // Filters, sort and count mode are carried through the links.
+    let search_criteria = serde_urlencoded::to_string(filter)
+        .ok()
+        .filter(|criteria| !criteria.is_empty());
+    let mut link_query = match &search_criteria {
+        Some(criteria) => format!("&{}&{}", criteria, query_pair("sort", &sort_by)),
+        None => format!("&{}", query_pair("sort", &sort_by)),
+    };
+    if let Some(count) = pagination.count {
+        link_query.push_str(&format!("&count={}", count.as_str()));
+    }
+    if let Some(fields) = &selection.fields {
+        link_query.push_str(&format!("&{}", query_pair("fields", &fields.join(","))));
+    }
+    if selection.account {
+        link_query.push_str("&include=account");
+    }
+
+    let list = UserListQuery {
+        limit,
+        sort,
+        sort_by,
+        filter_doc,
+        projection,
+        search_criteria,
+        link_query,
+    };
+    let mut response = if pagination.after.is_some() || pagination.before.is_some() {
+        get_users_by_cursor(client, pagination, list).await?
+    } else {
+        get_users_by_offset(client, pagination, list).await?
+    };
+    if selection.account {
+        embed_accounts(client, &mut response.data).await?;
+    }
+    let mut response = serde_json::to_value(response).unwrap_or_default();
+    if let Some(users) = response["data"].as_array_mut() {
+        for user in users {
+            retain_fields(user, &selection, "id");
+        }
+    }
+
+    // The page has no version, so it is tagged by its content.
+    let body = match serde_json::to_vec(&response) {
+        Ok(body) => body,
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    let tag = content_tag(&body);
+    let not_modified = is_not_modified(&tag, None, if_none_match, None);
+    let mut http_response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    http_response
+        .insert_header((header::CACHE_CONTROL, config.list_cache_control.as_str()))
+        .insert_header(ETag(tag));
+    if not_modified {
+        Ok(http_response.finish())
+    } else {
+        Ok(http_response
+            .content_type(header::ContentType::json())
+            .body(body))
+    }
+}
+
+// Parsed list request shared by offset and cursor pagination.
+struct UserListQuery {
+    limit: i64,
+    sort: Vec<(String, i32)>,
+    sort_by: String,
+    filter_doc: Document,
+    // Fields read from Mongo. Always has the sort fields for the cursors.
+    projection: Document,
+    search_criteria: Option<String>,
+    link_query: String,
+}
+
+// Fields and relations picked with `fields` and `include`.
+struct FieldSelection {
+    // `None` returns every field.
+    fields: Option<Vec<String>>,
+    account: bool,
+}
+
+fn parse_fields(fields: &UserFields, allowed: &[&str]) -> Result<FieldSelection, ApiErrorType> {
+    let selected = match fields.fields.as_deref() {
+        Some(list) => {
+            let mut selected: Vec<String> = vec![];
+            for field in list.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+                if !allowed.contains(&field) {
+                    return Err(ApiErrorType::InvalidQueryParameter {
+                        parameter: "fields".to_owned(),
+                        value: list.to_owned(),
+                        message: format!("fields must be some of {}", allowed.join(", ")),
+                    });
+                }
+                if !selected.iter().any(|f| f == field) {
+                    selected.push(field.to_owned());
+                }
+            }
+            Some(selected)
+        }
+        None => None,
+    };
+    let mut account = false;
+    if let Some(list) = fields.include.as_deref() {
+        for include in list.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            if !constants::USER_INCLUDES.contains(&include) {
+                return Err(ApiErrorType::InvalidQueryParameter {
+                    parameter: "include".to_owned(),
+                    value: list.to_owned(),
+                    message: format!(
+                        "include must be some of {}",
+                        constants::USER_INCLUDES.join(", ")
+                    ),
+                });
+            }
+            account = true;
+        }
+    }
+    Ok(FieldSelection {
+        fields: selected,
+        account,
+    })
+}
+
+// Projection of a single user. The version and update time are always read for the
+// validators. `None` reads the whole user.
+fn user_projection(selection: &FieldSelection) -> Option<Document> {
+    let fields = selection.fields.as_ref()?;
+    let mut projection = doc! {"version": 1, "updated_ts": 1};
+    for field in fields {
+        projection.insert(field, 1);
+    }
+    if selection.account {
+        projection.insert("auth_id", 1);
+    }
+    Some(projection)
+}
+
+fn list_projection(selection: &FieldSelection, sort: &[(String, i32)]) -> Document {
+    let mut projection = Document::new();
+    match &selection.fields {
+        Some(fields) => {
+            for field in fields {
+                projection.insert(field, 1);
+            }
+        }
+        None => {
+            for field in constants::USER_LIST_FIELDS {
+                projection.insert(field, 1);
+            }
+        }
+    }
+    for (field, _) in sort {
+        projection.insert(field, 1);
+    }
+    if selection.account {
+        projection.insert("auth_id", 1);
+    }
+    projection
+}
+
+// Drop the fields that were not asked for. The id and embedded relations are kept.
+fn retain_fields(user: &mut Value, selection: &FieldSelection, id: &str) {
+    if let (Some(fields), Value::Object(user)) = (&selection.fields, user) {
+        user.retain(|key, _| key == id || key == "account" || fields.iter().any(|f| f == key));
+    }
+}
+
+async fn linked_account(
+    client: &Data<Client>,
+    auth_id: Option<&String>,
+) -> Result<Option<AccountSummary>, ApiErrorType> {
+    let Some(auth_id) = auth_id else {
+        return Ok(None);
+    };
+    match auth_repo::fetch_by_id(client, auth_id).await {
+        Ok(auth) => Ok(auth.as_ref().map(AccountSummary::from)),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Embed the linked accounts of a page of users, fetched with one query.
+async fn embed_accounts(client: &Data<Client>, users: &mut [Users]) -> Result<(), ApiErrorType> {
+    let ids: Vec<String> = users.iter().filter_map(|u| u.auth_id.clone()).collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let accounts = match auth_repo::fetch_by_ids(client, &ids).await {
+        Ok(accounts) => accounts,
+        Err(err) => {
+            error!("Error: {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    for user in users.iter_mut() {
+        user.account = accounts
+            .iter()
+            .find(|auth| user.auth_id.as_ref() == Some(&auth.id))
+            .map(AccountSummary::from);
+    }
+    Ok(())
+}
+
+// Links of an offset paginated list. `link_query` carries the other query parameters.
+fn offset_links(
+    path: &str,
+    offset: u64,
+    limit: i64,
+    last_offset: Option<u64>,
+    has_next: bool,
+    link_query: &str,
+) -> Link {
+    let next_offset = i64::try_from(offset).unwrap_or(0) + limit;
+    let previous_offset = i64::try_from(offset).unwrap_or(0) - limit;
+    let href = |offset: i64| LinkHref {
+        href: format!("{}?offset={}&limit={}{}", path, offset, limit, link_query),
+    };
+    Link {
+        first: href(0),
+        last: last_offset.map(|last| href(last as i64)),
+        previous: if previous_offset < 0 {
+            None
+        } else {
+            Some(href(previous_offset))
+        },
+        next: if has_next {
+            Some(href(next_offset))
+        } else {
+            None
+        },
+        self_link: href(offset as i64),
+    }
+}
+
+// Full text search over name, title and location, most relevant first.
+pub async fn search_users(
+    client: &Data<Client>,
+    search: &UserSearch,
+) -> Result<HttpResponse, ApiErrorType> {
+    let text = search.q.trim();
+    if text.is_empty() {
+        return Err(ApiErrorType::InvalidQueryParameter {
+            parameter: "q".to_owned(),
+            value: search.q.to_owned(),
+            message: "Search text is required".to_owned(),
+        });
+    }
+    let offset = search.offset.unwrap_or(constants::DEFAULT_OFFSET_SIZE);
+    let limit = page_limit(search.limit)?;
+
+    let mut users = match user_repo::search_users(client, text, offset, limit).await {
+        Ok(users) => users,
+        Err(err) => {
+            error!("Error : {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    let total = match user_repo::search_users_size(client, text).await {
+        Ok(total) => total,
+        Err(err) => {
+            error!("Error : {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    if search.highlight.unwrap_or(false) {
+        let terms = search_terms(text);
+        for user in users.iter_mut() {
+            let highlights: BTreeMap<String, String> = [
+                ("name", &user.name),
+                ("title", &user.title),
+                ("location", &user.location),
+            ]
+            .into_iter()
+            .filter_map(|(field, value)| {
+                highlight(value, &terms).map(|marked| (field.to_owned(), marked))
+            })
+            .collect();
+            user.highlights = Some(highlights);
+        }
+    }
+
+    let search_criteria = serde_urlencoded::to_string(search).ok();
+    let link_query = match &search_criteria {
+        Some(criteria) => format!("&{}", criteria),
+        None => String::new(),
+    };
+    let last_offset = (total / (limit as u64)) * limit as u64;
+    let has_next = offset + (limit as u64) <= last_offset;
+    Ok(HttpResponse::Ok().json(UserListResponse {
+        data: users,
+        meta: Meta {
+            offset: Some(offset),
+            limit,
+            total_results: Some(total),
+            count: CountMode::Exact,
+            search_criteria,
+            sort_by: None,
+        },
+        _link: offset_links(
+            "/api/users/search",
+            offset,
+            limit,
+            Some(last_offset),
+            has_next,
+            &link_query,
+        ),
+    }))
+}
+
+// Lowercase words of the search text. Negated (`-word`) terms are left out.
+fn search_terms(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .filter(|token| !token.starts_with('-'))
+        .flat_map(|token| token.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// HTML escape the text and wrap words starting with a search term. Prefix matching stands in
+// for the stemming of the text index, so `engineer` marks `Engineering`.
+fn highlight(text: &str, terms: &[String]) -> Option<String> {
+    let mut marked = String::with_capacity(text.len());
+    let mut matched = false;
+    let mut word = String::new();
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if c.is_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            let lower = word.to_lowercase();
+            if terms.iter().any(|term| lower.starts_with(term.as_str())) {
+                matched = true;
+                marked.push_str(constants::HIGHLIGHT_START);
+                marked.push_str(&escape_html(&word));
+                marked.push_str(constants::HIGHLIGHT_END);
+            } else {
+                marked.push_str(&escape_html(&word));
+            }
+            word.clear();
+        }
+        marked.push_str(&escape_html(&c.to_string()));
+    }
+    // Drop the space added to flush the last word.
+    marked.pop();
+    if matched {
+        Some(marked)
+    } else {
+        None
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Page with `skip`. Gets slower as the offset grows.
+async fn get_users_by_offset(
+    client: &Data<Client>,
+    pagination: &Pagination,
+    list: UserListQuery,
+) -> Result<UserListResponse, ApiErrorType> {
+    let offset = pagination.offset.unwrap_or(constants::DEFAULT_OFFSET_SIZE);
+    let limit = list.limit;
+    let count_mode = pagination.count.unwrap_or(CountMode::Exact);
+    let user_list = user_repo::get_all_users(
+        client,
+        list.filter_doc.clone(),
+        sort_document(&list.sort),
+        offset,
+        limit,
+        list.projection,
+    )
+    .await;
+    let user_count = count_users(client, count_mode, list.filter_doc).await;
+    let last_offset = user_count.map(|count| (count / (limit as u64)) * limit as u64);
+
+    let next_offset = i64::try_from(offset).unwrap_or(0) + limit;
+    let link_query = list.link_query;
+
+    match user_list {
+        Ok(u) => {
+            // Without a total there is a next page as long as this one is full.
+            let has_next = match last_offset {
+                Some(last) => (next_offset as u64) <= last,
+                None => u.len() as i64 == limit,
+            };
+            let response = UserListResponse {
+                data: u,
+                meta: Meta {
+                    offset: Some(offset),
+                    limit,
+                    total_results: user_count,
+                    count: count_mode,
+                    search_criteria: list.search_criteria,
+                    sort_by: Some(list.sort_by),
+                },
+                _link: offset_links(
+                    "/api/users",
+                    offset,
+                    limit,
+                    last_offset,
+                    has_next,
+                    &link_query,
+                ),
+            };
+            Ok(response)
+        }
+        Err(err) => {
+            error!("Error : {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+// Keyset page after or before a cursor. Uses the sort index at any depth.
+// An empty `after` is the first page and an empty `before` the last page.
+async fn get_users_by_cursor(
+    client: &Data<Client>,
+    pagination: &Pagination,
+    list: UserListQuery,
+) -> Result<UserListResponse, ApiErrorType> {
+    let (parameter, value, backward) = match (&pagination.after, &pagination.before) {
+        (Some(after), None) => ("after", after, false),
+        (None, Some(before)) => ("before", before, true),
+        _ => {
+            return Err(ApiErrorType::InvalidQueryParameter {
+                parameter: "before".to_owned(),
+                value: pagination.before.to_owned().unwrap_or_default(),
+                message: "use either after or before".to_owned(),
+            })
+        }
+    };
+    let cursor = if value.is_empty() {
+        None
+    } else {
+        Some(ListCursor::decode(value, &list.sort_by, parameter)?)
+    };
+    let limit = list.limit;
+    let count_mode = pagination.count.unwrap_or(CountMode::None);
+
+    // Walk backwards in reverse sort order and flip the page afterwards.
+    let page_filter = match &cursor {
+        Some(c) => {
+            doc! {"$and": [list.filter_doc.clone(), keyset_document(&list.sort, c, backward)]}
+        }
+        None => list.filter_doc.clone(),
+    };
+    let page_sort = if backward {
+        let reversed: Vec<(String, i32)> = list
+            .sort
+            .iter()
+            .map(|(field, direction)| (field.to_owned(), -direction))
+            .collect();
+        let mut sort_doc = sort_document(&reversed);
+        sort_doc.insert("_id", -1);
+        sort_doc
+    } else {
+        sort_document(&list.sort)
+    };
+    // One extra user tells if there is another page in the walking direction.
+    let users = user_repo::get_all_users(
+        client,
+        page_filter,
+        page_sort,
+        0,
+        limit + 1,
+        list.projection,
+    )
+    .await;
+    let mut users = match users {
+        Ok(u) => u,
+        Err(err) => {
+            error!("Error : {}", err);
+            return Err(ApiErrorType::InternalServerError);
+        }
+    };
+    let has_more = users.len() as i64 > limit;
+    users.truncate(limit.max(0) as usize);
+    if backward {
+        users.reverse();
+    }
+    let user_count = count_users(client, count_mode, list.filter_doc).await;
+
+    let has_next = if backward { cursor.is_some() } else { has_more };
+    let has_previous = if backward { has_more } else { cursor.is_some() };
+    let page_cursor = |user: Option<&Users>| match (user, &cursor) {
+        (Some(u), _) => ListCursor::from_user(&list.sort, &list.sort_by, u).encode(),
+        (None, Some(c)) => c.encode(),
+        (None, None) => "".to_owned(),
+    };
+    let next_cursor = page_cursor(users.last());
+    let previous_cursor = page_cursor(users.first());
+    let link_query = list.link_query;
+
+    let response = UserListResponse {
+        data: users,
+        meta: Meta {
+            offset: None,
+            limit,
+            total_results: user_count,
+            count: count_mode,
+            search_criteria: list.search_criteria,
+            sort_by: Some(list.sort_by),
+        },
+        _link: Link {
+            first: LinkHref {
+                href: format!("/api/users?after=&limit={}{}", limit, link_query),
+            },
+            last: Some(LinkHref {
+                href: format!("/api/users?before=&limit={}{}", limit, link_query),
+            }),
+            previous: has_previous.then(|| LinkHref {
+                href: format!(
+                    "/api/users?before={}&limit={}{}",
+                    previous_cursor, limit, link_query
+                ),
+            }),
+            next: has_next.then(|| LinkHref {
+                href: format!(
+                    "/api/users?after={}&limit={}{}",
+                    next_cursor, limit, link_query
+                ),
+            }),
+            self_link: LinkHref {
+                href: format!(
+                    "/api/users?{}={}&limit={}{}",
+                    parameter, value, limit, link_query
+                ),
+            },
+        },
+    };
+    Ok(response)
+}
+
+// Count failures are logged and reported as unknown total.
+async fn count_users(client: &Data<Client>, mode: CountMode, filter: Document) -> Option<u64> {
+    let count = match mode {
+        CountMode::Exact => user_repo::get_users_size(client, filter).await,
+        CountMode::Estimated => user_repo::get_estimated_users_size(client).await,
+        CountMode::None => return None,
+    };
+    match count {
+        Ok(c) => Some(c),
+        Err(err) => {
+            error!("Error counting users: {}", err);
+            None
+        }
+    }
+}
+
+// Opaque keyset cursor. Sort key values and `_id` of the first or last user of a page.
+#[derive(Serialize, Deserialize)]
+struct ListCursor {
+    // Sort the cursor was created for. A cursor is only valid for the same sort.
+    sort: String,
+    keys: Vec<String>,
+    id: String,
+}
+
+impl ListCursor {
+    fn from_user(sort: &[(String, i32)], sort_by: &str, user: &Users) -> ListCursor {
+        ListCursor {
+            sort: sort_by.to_owned(),
+            keys: sort
+                .iter()
+                .map(|(field, _)| match field.as_str() {
+                    "location" => user.location.to_owned(),
+                    "title" => user.title.to_owned(),
+                    _ => user.name.to_owned(),
+                })
+                .collect(),
+            id: user.id.to_owned(),
+        }
+    }
+
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(value: &str, sort_by: &str, parameter: &str) -> Result<ListCursor, ApiErrorType> {
+        let cursor = URL_SAFE_NO_PAD
+            .decode(value)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<ListCursor>(&json).ok())
+            .filter(|c| c.sort == sort_by && c.keys.len() == sort_by.split(',').count());
+        cursor.ok_or_else(|| ApiErrorType::InvalidQueryParameter {
+            parameter: parameter.to_owned(),
+            value: value.to_owned(),
+            message: "cursor is invalid or was created for another sort".to_owned(),
+        })
+    }
+}
+
+// Users strictly after the cursor in sort order, or strictly before when walking backwards.
+// For sort `a, b, _id` that is `a > x or (a = x and b > y) or (a = x and b = y and _id > z)`,
+// with `<` for descending fields.
+fn keyset_document(sort: &[(String, i32)], cursor: &ListCursor, backward: bool) -> Document {
+    let mut keys: Vec<(&str, i32, &String)> = sort
+        .iter()
+        .zip(&cursor.keys)
+        .map(|((field, direction), value)| (field.as_str(), *direction, value))
+        .collect();
+    keys.push(("_id", 1, &cursor.id));
+
+    let mut branches: Vec<Document> = vec![];
+    for (i, (field, direction, value)) in keys.iter().enumerate() {
+        let mut branch = Document::new();
+        for (equal_field, _, equal_value) in &keys[..i] {
+            branch.insert(*equal_field, *equal_value);
+        }
+        let operator = if (*direction > 0) != backward {
+            "$gt"
+        } else {
+            "$lt"
+        };
+        branch.insert(*field, doc! {operator: *value});
+        branches.push(branch);
+    }
+    doc! {"$or": branches}
+}
+
+// The page size must be between 1 and the maximum. Zero would divide by zero in the links.
+fn page_limit(limit: Option<i64>) -> Result<i64, ApiErrorType> {
+    let limit = limit.unwrap_or(constants::DEFAULT_LIMIT_SIZE);
+    if !(1..=constants::MAX_LIMIT_SIZE).contains(&limit) {
+        return Err(ApiErrorType::InvalidQueryParameter {
+            parameter: "limit".to_owned(),
+            value: limit.to_string(),
+            message: format!("limit must be between 1 and {}", constants::MAX_LIMIT_SIZE),
+        });
+    }
+    Ok(limit)
+}
+
+// A single `name=value` pair for a link, encoded.
+fn query_pair(name: &str, value: &str) -> String {
+    serde_urlencoded::to_string([(name, value)]).unwrap_or_default()
+}
+
+// Parse `sort` like `-location,name` into fields with sort direction. Only allowlisted fields.
+fn parse_sort(sort: Option<&str>) -> Result<Vec<(String, i32)>, ApiErrorType> {
+    let sort = sort
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or(constants::DEFAULT_USER_SORT);
+    let mut fields: Vec<(String, i32)> = vec![];
+    for item in sort.split(',').map(str::trim) {
+        let (field, direction) = match item.strip_prefix('-') {
+            Some(field) => (field, -1),
+            None => (item.strip_prefix('+').unwrap_or(item), 1),
+        };
+        if !constants::SORTABLE_USER_FIELDS.contains(&field)
+            || fields.iter().any(|(f, _)| f == field)
+        {
+            return Err(ApiErrorType::InvalidQueryParameter {
+                parameter: "sort".to_owned(),
+                value: sort.to_owned(),
+                message: format!(
+                    "sort fields must be unique and one of {}",
+                    constants::SORTABLE_USER_FIELDS.join(", ")
+                ),
+            });
+        }
+        fields.push((field.to_owned(), direction));
+    }
+    Ok(fields)
+}
+
+fn sort_param(sort: &[(String, i32)]) -> String {
+    sort.iter()
+        .map(|(field, direction)| {
+            if *direction < 0 {
+                format!("-{}", field)
+            } else {
+                field.to_owned()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+// `_id` is always the last sort key so pages are stable for equal values.
+fn sort_document(sort: &[(String, i32)]) -> Document {
+    let mut sort_doc = Document::new();
+    for (field, direction) in sort {
+        sort_doc.insert(field, direction);
+    }
+    sort_doc.insert("_id", 1);
+    sort_doc
+}
+
+// All given filters must match. `prefix` is put before each field name.
+pub fn filter_document(filter: &UserFilter, prefix: &str) -> Document {
+    let fields = [
+        (
+            "name",
+            &filter.name,
+            &filter.name_prefix,
+            &filter.name_contains,
+        ),
+        (
+            "location",
+            &filter.location,
+            &filter.location_prefix,
+            &filter.location_contains,
+        ),
+        (
+            "title",
+            &filter.title,
+            &filter.title_prefix,
+            &filter.title_contains,
+        ),
+    ];
+    let mut conditions: Vec<Document> = vec![];
+    for (name, exact, starts_with, contains) in fields {
+        let field = format!("{}{}", prefix, name);
+        if let Some(value) = exact {
+            conditions.push(doc! {&field: value});
+        }
+        if let Some(value) = starts_with {
+            conditions.push(doc! {&field: {"$regex": format!("^{}", escape_regex(value))}});
+        }
+        if let Some(value) = contains {
+            conditions.push(doc! {&field: {"$regex": escape_regex(value), "$options": "i"}});
+        }
+    }
+    if conditions.is_empty() {
+        doc! {}
+    } else {
+        doc! {"$and": conditions}
+    }
+}
+
+// Match user input literally in a regular expression.
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn handle_optional_user_response(
+    user: Result<Option<User>, Error>,
+) -> Result<HttpResponse, ApiErrorType> {
+    match user {
+        Ok(Some(user)) => {
+            let mut response = HttpResponse::Ok();
+            validators(&mut response, &user);
+            Ok(response.json(to_response(&user)))
+        }
+        Ok(None) => Err(ApiErrorType::UserNotFound),
+        Err(err) => {
+            error!("Error: {}", err);
+            Err(ApiErrorType::InternalServerError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn user() -> User {
+        User {
+            id: Some("usr_1".to_owned()),
+            name: "John Doe".to_owned(),
+            location: "Chennai".to_owned(),
+            title: "Engineer".to_owned(),
+            auth_id: None,
+            version: 3,
+            created_ts: None,
+            created_by: None,
+            updated_ts: None,
+            updated_by: None,
+            deleted_at: None,
+            deleted_by: None,
+        }
+    }
+
+    #[test]
+    fn merge_patch_changes_given_fields_only() {
+        let patch = UserPatch::Merge(json!({"title": "Architect"}));
+        let patched = apply_patch(&user(), &patch).unwrap();
+        assert_eq!(patched.title, "Architect");
+        assert_eq!(patched.name, "John Doe");
+        assert_eq!(patched.location, "Chennai");
+    }
+
+    #[test]
+    fn merge_patch_can_not_remove_required_fields() {
+        let patch = UserPatch::Merge(json!({"location": null}));
+        assert!(matches!(
+            apply_patch(&user(), &patch),
+            Err(ApiErrorType::InvalidPatch { .. })
+        ));
+    }
+
+    #[test]
+    fn json_patch_applies_operations() {
+        let operations = serde_json::from_value(json!([
+            {"op": "test", "path": "/name", "value": "John Doe"},
+            {"op": "replace", "path": "/location", "value": "Madurai"},
+        ]))
+        .unwrap();
+        let patched = apply_patch(&user(), &UserPatch::Operations(operations)).unwrap();
+        assert_eq!(patched.location, "Madurai");
+        assert_eq!(patched.id.as_deref(), Some("usr_1"));
+    }
+
+    #[test]
+    fn failed_json_patch_test_is_invalid_patch() {
+        let operations = serde_json::from_value(json!([
+            {"op": "test", "path": "/name", "value": "Jane"},
+        ]))
+        .unwrap();
+        assert!(matches!(
+            apply_patch(&user(), &UserPatch::Operations(operations)),
+            Err(ApiErrorType::InvalidPatch { .. })
+        ));
+    }
+
+    #[test]
+    fn patch_can_not_change_id_or_field_types() {
+        for patch in [json!({"_id": "usr_2"}), json!({"name": 42})] {
+            assert!(matches!(
+                apply_patch(&user(), &UserPatch::Merge(patch)),
+                Err(ApiErrorType::InvalidPatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn limit_must_be_between_one_and_max() {
+        assert_eq!(page_limit(None).unwrap(), constants::DEFAULT_LIMIT_SIZE);
+        assert_eq!(page_limit(Some(1)).unwrap(), 1);
+        for limit in [0, -1, constants::MAX_LIMIT_SIZE + 1] {
+            assert!(matches!(
+                page_limit(Some(limit)),
+                Err(ApiErrorType::InvalidQueryParameter { parameter, .. }) if parameter == "limit"
+            ));
+        }
+    }
+
+    #[test]
+    fn query_pair_is_encoded() {
+        assert_eq!(query_pair("sort", "-name,title"), "sort=-name%2Ctitle");
+        assert_eq!(query_pair("sort", "a&b=c"), "sort=a%26b%3Dc");
+    }
+
+    #[test]
+    fn sort_is_parsed_with_direction() {
+        let sort = parse_sort(Some("-location, +name")).unwrap();
+        assert_eq!(
+            sort,
+            vec![("location".to_owned(), -1), ("name".to_owned(), 1)]
+        );
+        assert_eq!(sort_param(&sort), "-location,name");
+        assert_eq!(
+            sort_document(&sort),
+            doc! {"location": -1, "name": 1, "_id": 1}
+        );
+    }
+
+    #[test]
+    fn empty_sort_uses_default() {
+        for sort in [None, Some(""), Some("  ")] {
+            assert_eq!(
+                parse_sort(sort).unwrap(),
+                vec![(constants::DEFAULT_USER_SORT.to_owned(), 1)]
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_or_repeated_sort_fields_are_rejected() {
+        for sort in ["age", "name,-name", "name,"] {
+            assert!(matches!(
+                parse_sort(Some(sort)),
+                Err(ApiErrorType::InvalidQueryParameter { parameter, .. }) if parameter == "sort"
+            ));
+        }
+    }
+
+    #[test]
+    fn filter_document_combines_conditions() {
+        assert_eq!(filter_document(&UserFilter::default(), ""), doc! {});
+        let filter = UserFilter {
+            name: Some("John".to_owned()),
+            location_prefix: Some("Chen".to_owned()),
+            title_contains: Some("eng".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter_document(&filter, "user."),
+            doc! {"$and": [
+                {"user.name": "John"},
+                {"user.location": {"$regex": "^Chen"}},
+                {"user.title": {"$regex": "eng", "$options": "i"}},
+            ]}
+        );
+    }
+
+    #[test]
+    fn regex_metacharacters_are_escaped() {
+        assert_eq!(escape_regex("a.b*c"), "a\\.b\\*c");
+        assert_eq!(escape_regex("(x|y)$"), "\\(x\\|y\\)\\$");
+        assert_eq!(escape_regex("plain"), "plain");
+    }
+
+    fn users() -> Users {
+        Users {
+            id: "usr_1".to_owned(),
+            name: "John Doe".to_owned(),
+            location: "Chennai".to_owned(),
+            title: "Engineer".to_owned(),
+            auth_id: None,
+            account: None,
+            score: None,
+            highlights: None,
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips_for_the_same_sort() {
+        let sort = parse_sort(Some("-location,name")).unwrap();
+        let encoded = ListCursor::from_user(&sort, "-location,name", &users()).encode();
+        let cursor = ListCursor::decode(&encoded, "-location,name", "after").unwrap();
+        assert_eq!(cursor.keys, vec!["Chennai", "John Doe"]);
+        assert_eq!(cursor.id, "usr_1");
+    }
+
+    #[test]
+    fn cursor_for_another_sort_or_garbage_is_rejected() {
+        let sort = parse_sort(Some("name")).unwrap();
+        let encoded = ListCursor::from_user(&sort, "name", &users()).encode();
+        for (value, sort_by) in [(encoded.as_str(), "title"), ("not a cursor", "name")] {
+            assert!(matches!(
+                ListCursor::decode(value, sort_by, "before"),
+                Err(ApiErrorType::InvalidQueryParameter { parameter, .. }) if parameter == "before"
+            ));
+        }
+    }
+
+    #[test]
+    fn keyset_document_seeks_past_the_cursor() {
+        let sort = parse_sort(Some("-location,name")).unwrap();
+        let cursor = ListCursor::from_user(&sort, "-location,name", &users());
+        assert_eq!(
+            keyset_document(&sort, &cursor, false),
+            doc! {"$or": [
+                {"location": {"$lt": "Chennai"}},
+                {"location": "Chennai", "name": {"$gt": "John Doe"}},
+                {"location": "Chennai", "name": "John Doe", "_id": {"$gt": "usr_1"}},
+            ]}
+        );
+        assert_eq!(
+            keyset_document(&sort, &cursor, true),
+            doc! {"$or": [
+                {"location": {"$gt": "Chennai"}},
+                {"location": "Chennai", "name": {"$lt": "John Doe"}},
+                {"location": "Chennai", "name": "John Doe", "_id": {"$lt": "usr_1"}},
+            ]}
+        );
+    }
+
+    fn config(require_if_match: bool) -> UserConfig {
+        UserConfig {
+            require_if_match,
+            user_cache_control: "private, no-cache".to_owned(),
+            list_cache_control: "private, no-cache".to_owned(),
+            bulk_max_operations: 1000,
+            deleted_retention_days: 30,
+            import_max_bytes: 1024,
+            avatar_max_bytes: 1024,
+            events_max_subscribers: 1,
+            events_heartbeat_secs: 15,
+        }
+    }
+
+    #[test]
+    fn if_match_lists_the_strong_versions() {
+        let if_match = IfMatch::Items(vec![
+            EntityTag::new_strong("3".to_owned()),
+            EntityTag::new_weak("4".to_owned()),
+            EntityTag::new_strong("x".to_owned()),
+        ]);
+        assert_eq!(
+            accepted_versions(&config(false), Some(if_match)).unwrap(),
+            Some(vec![3])
+        );
+        assert_eq!(
+            accepted_versions(&config(true), Some(IfMatch::Any)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn if_match_is_required_when_configured() {
+        assert_eq!(accepted_versions(&config(false), None).unwrap(), None);
+        assert!(matches!(
+            accepted_versions(&config(true), None),
+            Err(ApiErrorType::PreconditionRequired)
+        ));
+        assert!(matches!(
+            accepted_versions(&config(true), Some(IfMatch::Items(vec![]))),
+            Err(ApiErrorType::PreconditionRequired)
+        ));
+    }
+
+    #[test]
+    fn if_none_match_is_compared_weakly() {
+        let tag = entity_tag(&user());
+        let matching = IfNoneMatch::Items(vec![EntityTag::new_weak("3".to_owned())]);
+        let other = IfNoneMatch::Items(vec![EntityTag::new_strong("2".to_owned())]);
+        assert!(is_not_modified(&tag, None, Some(matching), None));
+        assert!(is_not_modified(&tag, None, Some(IfNoneMatch::Any), None));
+        assert!(!is_not_modified(&tag, None, Some(other), None));
+    }
+
+    #[test]
+    fn if_modified_since_is_used_without_if_none_match() {
+        let tag = entity_tag(&user());
+        let updated_ts = DateTime::<Utc>::from_timestamp(1_700_000_000, 500_000_000);
+        let since = |secs: u64| {
+            Some(IfModifiedSince(HttpDate::from(
+                SystemTime::UNIX_EPOCH + StdDuration::from_secs(secs),
+            )))
+        };
+        assert!(is_not_modified(
+            &tag,
+            updated_ts,
+            None,
+            since(1_700_000_000)
+        ));
+        assert!(!is_not_modified(
+            &tag,
+            updated_ts,
+            None,
+            since(1_699_999_999)
+        ));
+        assert!(!is_not_modified(&tag, None, None, since(1_700_000_000)));
+        let other = IfNoneMatch::Items(vec![EntityTag::new_strong("2".to_owned())]);
+        assert!(!is_not_modified(
+            &tag,
+            updated_ts,
+            Some(other),
+            since(1_700_000_000)
+        ));
+    }
+
+    #[test]
+    fn content_tag_depends_on_the_body() {
+        assert_eq!(content_tag(b"[]"), content_tag(b"[]"));
+        assert_ne!(content_tag(b"[]"), content_tag(b"[{}]"));
+        assert!(!content_tag(b"[]").weak);
+    }
+
+    #[test]
+    fn bulk_operations_are_tagged_by_op() {
+        let request: BulkRequest = serde_json::from_value(json!({
+            "operations": [
+                {"op": "create", "user": {"name": "Jane", "location": "Pune", "title": "CTO"}},
+                {"op": "delete", "id": "usr_1", "version": 3},
+            ]
+        }))
+        .unwrap();
+        assert_eq!(request.ordered, None);
+        assert!(matches!(
+            request.operations.as_slice(),
+            [
+                BulkOperation::Create { .. },
+                BulkOperation::Delete { id, version: Some(3) }
+            ] if id == "usr_1"
+        ));
+    }
+
+    #[actix_web::test]
+    async fn bulk_request_over_the_limit_is_rejected() {
+        let client = Data::new(
+            Client::with_uri_str("mongodb://localhost:27017")
+                .await
+                .unwrap(),
+        );
+        let claims = Claims::new(&"usr_1".to_owned(), &vec![], &[]);
+        let operation = json!({"op": "delete", "id": "usr_1"});
+        let request: BulkRequest = serde_json::from_value(json!({
+            "operations": vec![operation; 1001]
+        }))
+        .unwrap();
+        assert!(matches!(
+            bulk_users(&client, &config(false), &claims, request).await,
+            Err(ApiErrorType::BatchTooLarge { max: 1000 })
+        ));
+    }
+
+    #[test]
+    fn bulk_failure_carries_the_api_error() {
+        let result = bulk_failure(2, Some("usr_1".to_owned()), ApiErrorType::UserNotFound);
+        assert_eq!(result.index, 2);
+        assert_eq!(result.status, BulkStatus::Failed);
+        assert_eq!(result.error.unwrap().status, 404);
+    }
+
+    #[test]
+    fn csv_rows_are_numbered_and_ids_ignored() {
+        let body = b"id,name,location,title\nusr_9, Jane ,Pune,CTO\nusr_8,Bob\n";
+        let rows = read_csv(body);
+        assert_eq!(rows.len(), 2);
+        let (row, user) = &rows[0];
+        let user = user.as_ref().unwrap();
+        assert_eq!(*row, 1);
+        assert_eq!(user.id, None);
+        assert_eq!(user.name, "Jane");
+        assert!(matches!(
+            rows[1],
+            (2, Err(ApiErrorType::MalformedRecord { .. }))
+        ));
+    }
+
+    #[test]
+    fn ndjson_skips_blank_lines_but_counts_them() {
+        let body = b"{\"name\":\"Jane\",\"location\":\"Pune\",\"title\":\"CTO\"}\n\n{oops}\n";
+        let rows = read_ndjson(body);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 1);
+        assert_eq!(rows[0].1.as_ref().unwrap().location, "Pune");
+        assert!(matches!(
+            rows[1],
+            (3, Err(ApiErrorType::MalformedRecord { .. }))
+        ));
+    }
+
+    #[test]
+    fn export_lines_match_the_format() {
+        let mut user = user();
+        user.title = "Engineer, \"Senior\"".to_owned();
+        let csv = export_line(ExportFormat::Csv, &user).unwrap();
+        assert_eq!(
+            &csv[..],
+            b"usr_1,John Doe,Chennai,\"Engineer, \"\"Senior\"\"\",3,\n"
+        );
+        let ndjson = export_line(ExportFormat::Ndjson, &user).unwrap();
+        assert!(ndjson.ends_with(b"\n"));
+        let line: Value = serde_json::from_slice(&ndjson).unwrap();
+        assert_eq!(line["_id"], "usr_1");
+        assert_eq!(line["title"], "Engineer, \"Senior\"");
+    }
+
+    #[test]
+    fn search_terms_skip_negated_words() {
+        assert_eq!(
+            search_terms("Senior  Engineer -manager co-founder"),
+            vec!["senior", "engineer", "co", "founder"]
+        );
+    }
+
+    #[test]
+    fn highlight_marks_words_starting_with_a_term() {
+        let terms = search_terms("engineer");
+        assert_eq!(
+            highlight("Engineering Lead", &terms).as_deref(),
+            Some("<em>Engineering</em> Lead")
+        );
+        assert_eq!(highlight("Designer", &terms), None);
+    }
+
+    #[test]
+    fn highlight_escapes_html() {
+        let terms = search_terms("bob");
+        assert_eq!(
+            highlight("<b>Bob</b> & 'co'", &terms).as_deref(),
+            Some("&lt;b&gt;<em>Bob</em>&lt;/b&gt; &amp; &#39;co&#39;")
+        );
+        assert_eq!(escape_html("\"x\""), "&quot;x&quot;");
+    }
+
+    fn fields(fields: Option<&str>, include: Option<&str>) -> UserFields {
+        UserFields {
+            fields: fields.map(str::to_owned),
+            include: include.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn fields_are_deduplicated_and_include_is_parsed() {
+        let selection = parse_fields(
+            &fields(Some("name, title,name,"), Some("account")),
+            &constants::USER_LIST_FIELDS,
+        )
+        .unwrap();
+        assert_eq!(
+            selection.fields,
+            Some(vec!["name".to_owned(), "title".to_owned()])
+        );
+        assert!(selection.account);
+        let selection = parse_fields(&fields(None, None), &constants::USER_FIELDS).unwrap();
+        assert_eq!(selection.fields, None);
+        assert!(!selection.account);
+    }
+
+    #[test]
+    fn unknown_fields_and_includes_are_rejected() {
+        for (list, include, parameter) in [
+            (Some("password"), None, "fields"),
+            (None, Some("roles"), "include"),
+        ] {
+            assert!(matches!(
+                parse_fields(&fields(list, include), &constants::USER_LIST_FIELDS),
+                Err(ApiErrorType::InvalidQueryParameter { parameter: p, .. }) if p == parameter
+            ));
+        }
+    }
+
+    #[test]
+    fn retain_fields_keeps_the_id_and_account() {
+        let selection = parse_fields(
+            &fields(Some("title"), Some("account")),
+            &constants::USER_FIELDS,
+        )
+        .unwrap();
+        let mut body =
+            json!({"_id": "usr_1", "name": "John Doe", "title": "Engineer", "account": {}});
+        retain_fields(&mut body, &selection, "_id");
+        assert_eq!(
+            body,
+            json!({"_id": "usr_1", "title": "Engineer", "account": {}})
+        );
+
+        let mut body = json!({"_id": "usr_1", "name": "John Doe"});
+        retain_fields(
+            &mut body,
+            &parse_fields(&fields(None, None), &constants::USER_FIELDS).unwrap(),
+            "_id",
+        );
+        assert_eq!(body, json!({"_id": "usr_1", "name": "John Doe"}));
+    }
+}